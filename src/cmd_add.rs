@@ -1,18 +1,94 @@
 use crate::common;
+use crate::common::source;
+use crate::cmd_split;
 
+use std::env;
+use std::fs;
 use std::path;
 use std::process;
 
+#[allow(clippy::single_component_path_imports)]
 use log;
 
-pub fn run(uris : &[String], is_playlist : bool) -> common::Result<()> {
+const DEFAULT_FORMATS : &[&str] = &["flac", "wav", "mp3"];
+const DOWNLOADS_NAME : &str = "downloads.txt";
+const DOWNLOADS_TEMPLATE : &str =
+        "after_move:%(filepath)s\t%(webpage_url)s\t%(extractor)s\t%(epoch>%Y-%m-%d)s";
+const TRANSCODE_CODECS : &[&str] = &[
+    "aac", "alac", "flac", "m4a", "mp3", "opus", "vorbis", "wav",
+];
+
+/// Describes which audio formats to download, and what to convert them into
+/// once they have been downloaded.
+#[derive(Debug)]
+pub struct CodecPolicy {
+    formats : Vec<String>,
+    transcode : Option<String>,
+    bitrate : Option<String>,
+    ffmpeg_path : Option<path::PathBuf>,
+}
+
+impl CodecPolicy {
+    pub fn from_config() -> common::Result<Self> {
+        let formats = common::find_config_list("add.formats").unwrap_or_else(|| {
+            DEFAULT_FORMATS.iter().map(|x| x.to_string()).collect()
+        });
+        let transcode = common::find_config("add.transcode");
+        let bitrate = common::find_config("add.bitrate");
+        let mut ffmpeg_path = None;
+        if let Some(codec) = &transcode {
+            if !TRANSCODE_CODECS.contains(&codec.as_str()) {
+                return Err(format!("unsupported transcode codec '{}', expected one of: {}",
+                        codec, TRANSCODE_CODECS.join(", ")).into());
+            }
+            ffmpeg_path = common::find_ffmpeg_path();
+            if ffmpeg_path.is_none() {
+                return Err("an executable to `ffmpeg` is required to transcode files\n\
+                            make sure `ffmpeg` is in your PATH, or add `ffmpeg = <path>` \
+                            to your `catty.toml`".into());
+            }
+        }
+        Ok(Self { formats, transcode, bitrate, ffmpeg_path })
+    }
+
+    /// Returns whether the downloaded files can hold cover art. `yt-dlp`
+    /// fails when asked to embed a thumbnail into a WAV file.
+    fn can_embed_thumbnail(&self) -> bool {
+        self.transcode.as_deref() != Some("wav")
+    }
+
+    /// Builds a `yt-dlp` format selector from the list of preferred formats,
+    /// falling back to the best audio available.
+    fn format_selector(&self) -> String {
+        let mut selector = String::new();
+        for format in &self.formats {
+            selector.push_str("ba[ext=");
+            selector.push_str(format);
+            selector.push_str("]/");
+        }
+        selector.push_str("ba");
+        selector
+    }
+}
+
+pub fn run(uris : &[String], is_playlist : bool, split : bool, yes : bool) -> common::Result<()> {
     assert!(!uris.is_empty());
     if let Some(ytdlp_path) = common::find_ytdlp_path() {
+        let policy = CodecPolicy::from_config()?;
+        log::debug!("using codec policy: {:?}", policy);
         log::info!("downloading files using installation: {}", ytdlp_path.display());
         let uris_n = uris.len();
         for (i, uri) in uris.iter().enumerate() {
             log::info!("task [{} / {}]", i, uris_n);
-            fetch_uri(&ytdlp_path, uri, is_playlist)?;
+            let mut proc = fetch_command(&ytdlp_path, &policy, uri, is_playlist);
+            if split {
+                add_split_args(&mut proc);
+            }
+            let files = run_command(proc)?;
+            record_sources(&files)?;
+            if split {
+                split_files(&files, yes)?;
+            }
         }
     } else {
         log::error!("an executable to `yt-dlp` is required for this command, aborting");
@@ -22,7 +98,14 @@ pub fn run(uris : &[String], is_playlist : bool) -> common::Result<()> {
     Ok(())
 }
 
-fn fetch_uri(ytdlp_path : &path::Path, uri : &str, is_playlist : bool) -> common::Result<()> {
+/// Builds the `yt-dlp` command used to download a URI. Extra arguments can be
+/// added to the command before it is passed to `run_command`.
+pub fn fetch_command(
+    ytdlp_path : &path::Path,
+    policy : &CodecPolicy,
+    uri : &str,
+    is_playlist : bool,
+) -> process::Command {
     let mut proc = process::Command::new(ytdlp_path);
     if is_playlist {
         proc.args([
@@ -32,19 +115,52 @@ fn fetch_uri(ytdlp_path : &path::Path, uri : &str, is_playlist : bool) -> common
     } else {
         proc.arg("--no-playlist");
     }
-    proc.args([
-        "--embed-metadata",  // grab as much metadata as we can get
-        "--embed-thumbnail", // grab the thumbnail, too
-        // skip video download, we don't need it
-        // also try and find the best audio format
-        "-f", "ba[ext=flac]/ba[ext=wav]/ba[ext=mp3]/ba",
-    ]);
+    proc.arg("--embed-metadata"); // grab as much metadata as we can get
+    if policy.can_embed_thumbnail() {
+        proc.arg("--embed-thumbnail"); // grab the thumbnail, too
+    }
+    // skip video download, we don't need it
+    // also try and find the best audio format
+    proc.args(["-f", policy.format_selector().as_str()]);
+    if let Some(codec) = &policy.transcode {
+        // metadata and thumbnails are embedded after extraction, so the
+        // converted file keeps its tags and cover art
+        proc.args(["--extract-audio", "--audio-format", codec.as_str()]);
+        if let Some(bitrate) = &policy.bitrate {
+            proc.args(["--audio-quality", bitrate.as_str()]);
+        }
+    }
+    if let Some(ffmpeg_path) = &policy.ffmpeg_path {
+        proc.arg("--ffmpeg-location");
+        proc.arg(ffmpeg_path);
+    }
     // make sure the file path is descriptive
     let mut file_name = (if is_playlist { "%(playlist|Playlist)s/" } else { "" }).to_string();
     file_name.push_str("%(artist,creator,uploader,uploader_id|Unknown)s - %(title,track,fulltitle,webpage_url_basename|Unnamed)s.%(ext)s");
     proc.args(["-o", file_name.as_str()]);
     // submit command with the URI
     proc.arg(uri);
+    proc
+}
+
+/// A file downloaded by `yt-dlp`.
+#[derive(Debug)]
+pub struct Download {
+    pub path : path::PathBuf,
+    pub source : Option<source::Source>,
+}
+
+/// Runs a `yt-dlp` command, returning the files it downloaded.
+pub fn run_command(mut proc : process::Command) -> common::Result<Vec<Download>> {
+    fs::create_dir_all(common::DATA_DIR)?;
+    // `yt-dlp` resolves relative paths under `--paths`, so the path has to be absolute
+    let current_dir = env::current_dir()?;
+    let downloads_path = current_dir.join(common::DATA_DIR).join(DOWNLOADS_NAME);
+    if downloads_path.exists() {
+        fs::remove_file(&downloads_path)?;
+    }
+    proc.args(["--print-to-file", DOWNLOADS_TEMPLATE]);
+    proc.arg(&downloads_path);
     proc.stdout(process::Stdio::inherit()); // keep writing output
     proc.stderr(process::Stdio::inherit());
     log::debug!("running process with args: {:?}", proc.get_args());
@@ -52,5 +168,51 @@ fn fetch_uri(ytdlp_path : &path::Path, uri : &str, is_playlist : bool) -> common
     if !output.status.success() {
         log::warn!("recieved non-zero exit code, see output window");
     }
+    let mut files = Vec::new();
+    if let Ok(downloads) = fs::read_to_string(&downloads_path) {
+        for line in downloads.lines() {
+            let (file_path, record) = line.split_once('\t').unwrap_or((line, ""));
+            if !file_path.trim().is_empty() {
+                let file_path = path::Path::new(file_path.trim());
+                files.push(Download {
+                    path : file_path.strip_prefix(&current_dir).unwrap_or(file_path).to_path_buf(),
+                    source : source::Source::from_record(record),
+                });
+            }
+        }
+        fs::remove_file(&downloads_path)?;
+    }
+    Ok(files)
+}
+
+/// Asks `yt-dlp` to keep any chapter data and the description of the upload,
+/// so the file can be split into tracks afterwards.
+pub fn add_split_args(proc : &mut process::Command) {
+    proc.args(["--embed-chapters", "--write-description"]);
+}
+
+/// Keeps a record of where each downloaded file came from.
+pub fn record_sources(files : &[Download]) -> common::Result<()> {
+    for file in files {
+        if let Some(source) = &file.source {
+            log::debug!("recording source of '{}': {:?}", file.path.display(), source);
+            source::record(&file.path, source)?;
+        }
+    }
+    Ok(())
+}
+
+/// Splits full-album uploads into separate tracks.
+pub fn split_files(files : &[Download], yes : bool) -> common::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let ffmpeg_path = if let Some(x) = common::find_ffmpeg_path() { x } else {
+        log::error!("an executable to `ffmpeg` is required to split files, skipping");
+        return Ok(());
+    };
+    for file in files {
+        cmd_split::split_file(&ffmpeg_path, &file.path, None, yes)?;
+    }
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,203 @@
+use std::path;
+use std::collections::{ BTreeMap, HashSet };
+use crate::common;
+use crate::common::art;
+use crate::common::index::Index;
+use crate::common::select::Selector;
+use crate::common::tags;
+
+use clap::Subcommand;
+
+#[derive(Subcommand, Debug)]
+pub enum ArtCommand {
+    /// Writes the cover art embedded in the tracks of each album into the
+    /// album directory as `cover.jpg`, unless it already has a folder image.
+    /// The file name can be changed with `art.file-name` in the config.
+    Extract {
+        #[command(flatten)]
+        select : Selector,
+        /// Crop 16:9 video thumbnails to a square.
+        #[arg(long)]
+        square : bool,
+    },
+    /// Embeds the folder image of each album into its tracks which have no
+    /// cover art. The tracks are listed and confirmed before any are changed.
+    Embed {
+        #[command(flatten)]
+        select : Selector,
+        /// Crop 16:9 video thumbnails to a square, including those which are
+        /// already embedded.
+        #[arg(long)]
+        square : bool,
+    },
+    /// Reports albums whose tracks have different cover art, or are missing
+    /// it.
+    Check {
+        #[command(flatten)]
+        select : Selector,
+    },
+}
+
+/// The tracks of an album, grouped by the directory they're stored in.
+type Albums = BTreeMap<path::PathBuf, Vec<path::PathBuf>>;
+
+/// Where the cover art embedded into a track comes from.
+enum Source {
+    /// The video thumbnail which is already embedded, cropped to a square.
+    Embedded,
+    /// The folder image of the album.
+    Folder(path::PathBuf),
+}
+
+pub fn run(command : &ArtCommand, yes : bool) -> common::Result<()> {
+    let mut index = Index::load();
+    let result = match command {
+        ArtCommand::Extract { select, square } => find_albums(&mut index, select)
+                .and_then(|albums| extract(&albums, *square)),
+        ArtCommand::Embed { select, square } => select.files_or_library(&mut index)
+                .and_then(|files| embed(&files, *square, yes)),
+        ArtCommand::Check { select } => find_albums(&mut index, select)
+                .and_then(|albums| check(&albums)),
+    };
+    index.save()?;
+    result
+}
+
+/// Groups the selected tracks by their directory, skipping directories
+/// without any tracks from an album.
+fn find_albums(index : &mut Index, select : &Selector) -> common::Result<Albums> {
+    let mut albums = Albums::new();
+    let mut album_dirs = HashSet::new();
+    for file in select.files_or_library(index)? {
+        let dir = file.parent().unwrap_or(path::Path::new("")).to_path_buf();
+        match index.get(&file) {
+            Ok(entry) => if entry.meta.album.is_some() {
+                album_dirs.insert(dir.clone());
+            },
+            Err(err) => log::warn!("failed to read file '{}'\nreason = {}", file.display(), err),
+        }
+        albums.entry(dir).or_default().push(file);
+    }
+    albums.retain(|dir, _| !dir.as_os_str().is_empty() && album_dirs.contains(dir));
+    Ok(albums)
+}
+
+fn find_ffmpeg(square : bool) -> common::Result<Option<path::PathBuf>> {
+    if !square {
+        return Ok(None);
+    }
+    match common::find_ffmpeg_path() {
+        Some(ffmpeg_path) => Ok(Some(ffmpeg_path)),
+        None => Err("an executable to `ffmpeg` is required to crop images\n\
+                     make sure `ffmpeg` is in your PATH, or add `ffmpeg = <path>` \
+                     to your `catty.toml`".into()),
+    }
+}
+
+fn extract(albums : &Albums, square : bool) -> common::Result<()> {
+    let ffmpeg_path = find_ffmpeg(square)?;
+    let mut n_written = 0;
+    for (dir, files) in albums {
+        match art::write_folder_image(dir, files, ffmpeg_path.as_deref()) {
+            Ok(Some(_)) => n_written += 1,
+            Ok(None) => (),
+            Err(err) => log::warn!("failed to write folder image for '{}'\nreason = {}", dir.display(), err),
+        }
+    }
+    log::info!("wrote {} folder images", n_written);
+    Ok(())
+}
+
+fn embed(files : &[path::PathBuf], square : bool, yes : bool) -> common::Result<()> {
+    let ffmpeg_path = find_ffmpeg(square)?;
+    let mut planned = Vec::new();
+    for file in files {
+        let cover = match tags::read_cover(file) {
+            Ok(cover) => cover,
+            Err(err) => {
+                log::warn!("failed to read cover art of '{}'\nreason = {}", file.display(), err);
+                continue;
+            },
+        };
+        match cover {
+            // replace embedded video thumbnails with a square version
+            Some(cover) if ffmpeg_path.is_some() && art::is_widescreen(&cover) => planned.push((file, Source::Embedded)),
+            Some(_) => (),
+            None => match art::find_folder_image(file.parent().unwrap_or(path::Path::new(""))) {
+                Some(image_path) => planned.push((file, Source::Folder(image_path))),
+                None => log::debug!("no cover art available for: {}", file.display()),
+            },
+        }
+    }
+    if planned.is_empty() {
+        log::info!("there is no cover art to embed");
+        return Ok(());
+    }
+    for (file, source) in &planned {
+        match source {
+            Source::Embedded => println!("{}: crop cover art to a square", file.display()),
+            Source::Folder(image_path) => println!("{}: embed {}", file.display(), image_path.display()),
+        }
+    }
+    if !yes && !common::ask_confirm() {
+        return Ok(());
+    }
+    let mut n_embedded = 0;
+    for (file, source) in planned {
+        match embed_file(file, &source, ffmpeg_path.as_deref()) {
+            Ok(()) => {
+                log::info!("embedded cover art into: {}", file.display());
+                n_embedded += 1;
+            },
+            Err(err) => log::warn!("failed to embed cover art into '{}'\nreason = {}", file.display(), err),
+        }
+    }
+    log::info!("embedded cover art into {} files", n_embedded);
+    Ok(())
+}
+
+fn embed_file(file : &path::Path, source : &Source, ffmpeg_path : Option<&path::Path>) -> common::Result<()> {
+    let image = match source {
+        Source::Embedded => tags::read_cover(file)?.ok_or("the cover art was removed")?,
+        Source::Folder(image_path) => art::read_folder_image(image_path)?,
+    };
+    let image = match ffmpeg_path {
+        Some(ffmpeg_path) if art::is_widescreen(&image) => art::crop_square(ffmpeg_path, &image)?,
+        _ => image,
+    };
+    tags::write_cover(file, &image)
+}
+
+fn check(albums : &Albums) -> common::Result<()> {
+    let mut n_inconsistent = 0;
+    for (dir, files) in albums {
+        let mut covers = HashSet::new();
+        let mut n_missing = 0;
+        for file in files {
+            match tags::read_cover(file) {
+                Ok(Some(image)) => {
+                    covers.insert(art::image_hash(&image));
+                },
+                Ok(None) => n_missing += 1,
+                Err(err) => log::debug!("failed to read cover art of '{}'\nreason = {}", file.display(), err),
+            }
+        }
+        let mut problems = Vec::new();
+        if covers.len() > 1 {
+            problems.push(format!("{} different covers", covers.len()));
+        }
+        if n_missing > 0 {
+            problems.push(format!("{} of {} tracks without cover art", n_missing, files.len()));
+        }
+        if art::find_folder_image(dir).is_none() {
+            problems.push("no folder image".to_string());
+        }
+        // a missing folder image alone isn't inconsistent
+        if covers.len() > 1 || n_missing > 0 {
+            println!("{}: {}", dir.display(), problems.join(", "));
+            n_inconsistent += 1;
+        }
+    }
+    log::info!("{} of {} albums have inconsistent cover art", n_inconsistent, albums.len());
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,351 @@
+use std::fs;
+use std::path;
+use std::collections::{ BTreeSet, HashMap };
+use crate::common;
+use crate::common::companion;
+use crate::common::select::Selector;
+use crate::common::sniff;
+use crate::common::spectrum;
+use crate::common::walk;
+
+use clap::ValueEnum;
+
+/// The kinds of problems that `check` can find.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum IssueKind {
+    /// The file isn't where `sort` would put it.
+    Misplaced,
+    /// A tag is missing, but other tracks in the same album have it.
+    MissingTag,
+    /// Tracks in the same album disagree about a tag.
+    ConflictingTags,
+    /// An album is missing some of its track numbers.
+    TrackGap,
+    /// Several tracks in an album have the same track number.
+    DuplicateTrack,
+    /// The file or its tags cannot be read.
+    Unreadable,
+    /// A directory which doesn't contain any files.
+    EmptyDir,
+    /// A file which isn't audio, or a companion of an audio file.
+    StrayFile,
+    /// A lossless file whose spectrum looks like it was transcoded from lossy
+    /// audio. Only checked if `ffmpeg` is installed.
+    LossySource,
+}
+
+impl IssueKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Misplaced => "misplaced",
+            Self::MissingTag => "missing-tag",
+            Self::ConflictingTags => "conflicting-tags",
+            Self::TrackGap => "track-gap",
+            Self::DuplicateTrack => "duplicate-track",
+            Self::Unreadable => "unreadable",
+            Self::EmptyDir => "empty-dir",
+            Self::StrayFile => "stray-file",
+            Self::LossySource => "lossy-source",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Issue {
+    kind : IssueKind,
+    path : path::PathBuf,
+    message : String,
+}
+
+impl Issue {
+    fn new(kind : IssueKind, file_path : &path::Path, message : impl Into<String>) -> Self {
+        let path = walk::rel_to_library(file_path).unwrap_or_else(|| file_path.to_path_buf());
+        Self { kind, path, message : message.into() }
+    }
+}
+
+/// Audits the library, printing every problem found. Returns the number of
+/// problems, so that the exit code can reflect whether the library is healthy.
+pub fn run(select : &Selector, skip : &[IssueKind], json : bool) -> common::Result<usize> {
+    let mut index = common::index::Index::load();
+    let mut issues = Vec::new();
+    let files = if select.is_empty() {
+        log::info!("no paths supplied, checking every file in the library");
+        let mut files = Vec::new();
+        common::library_foreach(|file| {
+            files.push(file.to_path_buf());
+            Ok(())
+        })?;
+        check_dir(&mut walk::Walker::new(true, None), path::Path::new("."), &mut issues)?;
+        files
+    } else {
+        select.files(&mut index)?
+    };
+    let verify_quality = !skip.contains(&IssueKind::LossySource);
+    check_files(&mut index, &files, verify_quality, &mut issues);
+    index.save()?;
+    issues.retain(|x| !skip.contains(&x.kind));
+    issues.sort_by(|a, b| a.path.cmp(&b.path).then(a.kind.cmp(&b.kind)));
+    if json {
+        let report = issues.iter()
+                .map(|x| serde_json::json!({
+                    "kind" : x.kind.name(),
+                    "path" : x.path.to_string_lossy(),
+                    "message" : x.message,
+                }))
+                .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for issue in &issues {
+            println!("{:<17} {}: {}", issue.kind.name(), issue.path.display(), issue.message);
+        }
+    }
+    if issues.is_empty() {
+        log::info!("no problems found in {} files", files.len());
+    } else {
+        log::warn!("found {} problems in {} files", issues.len(), files.len());
+    }
+    Ok(issues.len())
+}
+
+fn check_files(
+    index : &mut common::index::Index,
+    files : &[path::PathBuf],
+    verify_quality : bool,
+    issues : &mut Vec<Issue>,
+) {
+    let mut db = common::infer::Database::new();
+    let mut file_meta_map = HashMap::new();
+    let mut verifier = Verifier::new();
+    for file in files {
+        if let Some(message) = check_readable(file) {
+            issues.push(Issue::new(IssueKind::Unreadable, file, message));
+        }
+        let file_meta = match index.parse(file) {
+            Ok(file_meta) => file_meta,
+            Err(err) => {
+                issues.push(Issue::new(IssueKind::Unreadable, file, err.to_string()));
+                continue;
+            },
+        };
+        // check the file is where `sort` would put it
+        let expect_dir = common::meta::get_sort_dir(&file_meta);
+        let actual_dir = walk::rel_to_library(file)
+                .and_then(|x| x.parent().map(|x| x.to_path_buf()))
+                .unwrap_or_default();
+        if !actual_dir.as_os_str().eq_ignore_ascii_case(expect_dir.as_os_str()) {
+            issues.push(Issue::new(IssueKind::Misplaced, file,
+                    format!("expected to be in '{}'", expect_dir.display())));
+        }
+        if verify_quality {
+            if let Some(message) = verifier.check(index, file, &file_meta) {
+                issues.push(Issue::new(IssueKind::LossySource, file, message));
+            }
+        }
+        if let Some(file_location) = db.add_file(file) {
+            file_meta_map.insert(file_location.id, (file.clone(), file_meta));
+        }
+    }
+    if let Err(err) = verifier.cache.save() {
+        log::warn!("failed to save the quality cache\nreason = {}", err);
+    }
+    // check that the tracks of each album agree with each other
+    let (collections, db_files) = db.complete();
+    let mut albums = HashMap::<_, Vec<_>>::new();
+    for db_file in &db_files {
+        if let Some(entry) = file_meta_map.get(&db_file.id) {
+            albums.entry(db_file.id_collection).or_default().push(entry);
+        }
+    }
+    for collection in &collections {
+        if let Some(tracks) = albums.get(&collection.id) {
+            check_album(&collection.path, tracks, issues);
+        }
+    }
+}
+
+/// Checks the spectrum of lossless files, only looking for `ffmpeg` once a
+/// lossless file is found.
+struct Verifier {
+    ffmpeg_path : Option<Option<path::PathBuf>>,
+    cache : spectrum::Cache,
+}
+
+impl Verifier {
+    fn new() -> Self {
+        Self { ffmpeg_path : None, cache : spectrum::Cache::load() }
+    }
+
+    /// Returns why a file looks like a transcode of lossy audio, if it does.
+    fn check(
+        &mut self,
+        index : &mut common::index::Index,
+        file_path : &path::Path,
+        file_meta : &common::meta::TrackMeta,
+    ) -> Option<String> {
+        let sample_rate = file_meta.audio.sample_rate.filter(|_| file_meta.audio.is_lossless())?;
+        let ffmpeg_path = self.ffmpeg_path.get_or_insert_with(common::find_ffmpeg_path).as_ref()?;
+        match self.cache.get_or_analyse(index, ffmpeg_path, file_path, sample_rate, false) {
+            Ok((analysis, _)) if analysis.is_lossy() => Some(analysis.describe()),
+            Ok(_) => None,
+            Err(err) => {
+                log::warn!("failed to analyse file '{}'\nreason = {}", file_path.display(), err);
+                None
+            },
+        }
+    }
+}
+
+/// Returns why a file can't be read, if it looks corrupt.
+fn check_readable(file_path : &path::Path) -> Option<String> {
+    let kind = match common::index::cached_kind(file_path) {
+        Some(kind) => Ok(kind),
+        None => sniff::sniff(file_path),
+    };
+    let has_audio_ext = file_path.extension()
+            .and_then(|x| x.to_str())
+            .is_some_and(common::ext_is_audio_file);
+    match kind {
+        Ok(sniff::Kind::Audio(_)) => (),
+        // only some audio formats are recognised from their contents
+        Ok(sniff::Kind::Unknown) if has_audio_ext => (),
+        Ok(sniff::Kind::Video) => return Some("file contains a video track".to_string()),
+        Ok(sniff::Kind::Unknown) => return Some("file contents are not a recognised audio format".to_string()),
+        Err(err) => return Some(err.to_string()),
+    }
+    match audiotags::Tag::new().read_from_path(file_path) {
+        Ok(_) => None,
+        // formats without tag support, and files without any tags, are fine
+        Err(audiotags::Error::UnknownFileExtension(_))
+        | Err(audiotags::Error::UnsupportedFormat(_)) => None,
+        Err(audiotags::Error::Id3TagError(id3::Error { kind : id3::ErrorKind::NoTag, .. })) => None,
+        Err(err) => Some(format!("failed to read tags: {}", err)),
+    }
+}
+
+fn check_album(
+    dir : &path::Path,
+    tracks : &[&(path::PathBuf, common::meta::TrackMeta)],
+    issues : &mut Vec<Issue>,
+) {
+    if tracks.len() < 2 {
+        return;
+    }
+    let album_names = tracks.iter()
+            .filter_map(|(_, meta)| meta.album.as_ref())
+            .map(|x| x.to_lowercase())
+            .collect::<BTreeSet<_>>();
+    if album_names.is_empty() {
+        return; // probably a folder of singles
+    }
+    if album_names.len() > 1 {
+        let albums = distinct_values(tracks, |x| x.album.clone());
+        issues.push(Issue::new(IssueKind::ConflictingTags, dir,
+                format!("tracks have different albums: {}", albums.join(", "))));
+        return;
+    }
+    let album = tracks.iter().find_map(|(_, meta)| meta.album.as_ref()).unwrap();
+    for (file, meta) in tracks {
+        if meta.album.is_none() {
+            issues.push(Issue::new(IssueKind::MissingTag, file,
+                    format!("album is missing, other tracks use '{}'", album)));
+        }
+    }
+    let authors = distinct_values(tracks, |x| x.album_author.clone());
+    if authors.len() > 1 {
+        issues.push(Issue::new(IssueKind::ConflictingTags, dir,
+                format!("tracks have different album artists: {}", authors.join(", "))));
+    }
+    let years = distinct_values(tracks, |x| x.year.map(|x| x.to_string()));
+    if years.len() > 1 {
+        issues.push(Issue::new(IssueKind::ConflictingTags, dir,
+                format!("tracks have different years: {}", years.join(", "))));
+    }
+    // check track numbers
+    let mut numbers = BTreeSet::new();
+    let mut has_numbers = false;
+    for (file, meta) in tracks {
+        if let Some((number, _)) = meta.track_number {
+            has_numbers = true;
+            if !numbers.insert(number) {
+                issues.push(Issue::new(IssueKind::DuplicateTrack, file,
+                        format!("another track is also number {}", number)));
+            }
+        }
+    }
+    if !has_numbers {
+        return;
+    }
+    for (file, meta) in tracks {
+        if meta.track_number.is_none() {
+            issues.push(Issue::new(IssueKind::MissingTag, file, "track number is missing"));
+        }
+    }
+    let last = *numbers.iter().next_back().unwrap();
+    let gaps = (1..last)
+            .filter(|x| !numbers.contains(x))
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+    if !gaps.is_empty() {
+        issues.push(Issue::new(IssueKind::TrackGap, dir,
+                format!("missing track numbers: {}", gaps.join(", "))));
+    }
+}
+
+/// Collects the distinct values of a tag across an album.
+fn distinct_values(
+    tracks : &[&(path::PathBuf, common::meta::TrackMeta)],
+    f : impl Fn(&common::meta::TrackMeta) -> Option<String>,
+) -> Vec<String> {
+    let values = tracks.iter()
+            .filter_map(|(_, meta)| f(meta))
+            .collect::<BTreeSet<_>>();
+    values.into_iter().collect()
+}
+
+/// Searches a directory for empty directories and stray files. Returns whether
+/// the directory contains any files.
+fn check_dir(
+    walker : &mut walk::Walker,
+    dir : &path::Path,
+    issues : &mut Vec<Issue>,
+) -> common::Result<bool> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        entries.push(entry?.path());
+    }
+    entries.sort();
+    let first_issue = issues.len();
+    let mut has_files = false;
+    for entry in entries {
+        let entry = entry.strip_prefix(".").map(|x| x.to_path_buf()).unwrap_or(entry);
+        // symlinked directories are not followed, to avoid loops
+        let is_dir = fs::symlink_metadata(&entry)?.is_dir();
+        if walker.is_ignored(&entry, is_dir) {
+            continue;
+        }
+        if is_dir {
+            has_files |= check_dir(walker, &entry, issues)?;
+            continue;
+        }
+        has_files = true;
+        if !is_stray_file(&entry) {
+            continue;
+        }
+        issues.push(Issue::new(IssueKind::StrayFile, &entry, "not an audio file"));
+    }
+    if !has_files && dir != path::Path::new(".") {
+        // only report the outermost empty directory
+        issues.truncate(first_issue);
+        issues.push(Issue::new(IssueKind::EmptyDir, dir, "directory has no files"));
+    }
+    Ok(has_files)
+}
+
+fn is_stray_file(file_path : &path::Path) -> bool {
+    let file_name = file_path.file_name().and_then(|x| x.to_str()).unwrap_or_default();
+    if file_name == walk::IGNORE_FILE || file_path == path::Path::new(common::CONFIG_PATH) {
+        return false;
+    }
+    !companion::is_companion_file(file_path) && !common::is_audio_file(file_path)
+}
\ No newline at end of file
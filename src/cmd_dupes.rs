@@ -0,0 +1,263 @@
+use std::fs;
+use std::path;
+use std::collections::{ HashMap, HashSet };
+use crate::common;
+use crate::common::select::Selector;
+use crate::common::probe::AudioProperties;
+use crate::common::sniff;
+
+/// Where duplicates are moved to when they are quarantined, instead of being
+/// deleted. This is inside of the data directory so it is never searched.
+const QUARANTINE_DIR : &str = "quarantine";
+
+/// How close the durations of tracks have to be for them to be grouped by
+/// their metadata, in seconds.
+const DURATION_STEP : f64 = 5.0;
+
+/// A copy of a track, along with the information used to pick the best copy.
+#[derive(Debug)]
+struct Candidate {
+    path : path::PathBuf,
+    format : Option<sniff::Format>,
+    audio : AudioProperties,
+    size : u64,
+}
+
+impl Candidate {
+    fn read(index : &mut common::index::Index, file_path : &path::Path) -> common::Result<Self> {
+        let entry = index.get(file_path)?;
+        let format = match entry.kind {
+            sniff::Kind::Audio(format) => Some(format),
+            _ => None,
+        };
+        let audio = entry.meta.audio.clone();
+        let size = entry.stat.size;
+        Ok(Self { path : file_path.to_path_buf(), format, audio, size })
+    }
+
+    /// Lossless copies are always preferred, followed by copies with a higher
+    /// bitrate and sample rate. Bigger files break any ties.
+    fn quality(&self) -> (bool, u32, u32, u64) {
+        let is_lossless = self.format.is_some_and(|x| x.is_lossless()) || self.audio.is_lossless();
+        (
+            is_lossless,
+            self.audio.bitrate.unwrap_or_default(),
+            self.audio.sample_rate.unwrap_or_default(),
+            self.size,
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Group {
+    reason : &'static str,
+    /// Whether the copies have identical audio, rather than only sharing
+    /// their metadata.
+    is_identical : bool,
+    copies : Vec<Candidate>,
+}
+
+pub fn run(
+    select : &Selector,
+    exact : bool,
+    remove : bool,
+    quarantine : bool,
+    similar : bool,
+    yes : bool,
+) -> common::Result<()> {
+    let mut index = common::index::Index::load();
+    let files = select.files_or_library(&mut index)?;
+    let groups = find_groups(&mut index, &files, exact)?;
+    if groups.is_empty() {
+        log::info!("no duplicates found in {} files", files.len());
+        return index.save();
+    }
+    log::info!("found {} groups of duplicates in {} files", groups.len(), files.len());
+    let mut n_skipped = 0;
+    for group in &groups {
+        print_group(group);
+        if !remove && !quarantine {
+            continue;
+        }
+        // tracks with the same metadata may still be different recordings
+        if !group.is_identical && !similar {
+            n_skipped += 1;
+            continue;
+        }
+        let dupes = group.copies[1..].iter()
+                .filter(|x| x.path.exists())
+                .collect::<Vec<_>>();
+        if dupes.is_empty() {
+            continue;
+        }
+        if quarantine {
+            log::info!("quarantining {} duplicates of '{}'", dupes.len(), group.copies[0].path.display());
+        } else {
+            log::info!("removing {} duplicates of '{}'", dupes.len(), group.copies[0].path.display());
+        }
+        if !yes && !common::ask_confirm() {
+            continue;
+        }
+        for dupe in dupes {
+            if quarantine {
+                quarantine_file(&dupe.path)?;
+            } else {
+                fs::remove_file(&dupe.path)?;
+            }
+        }
+    }
+    if n_skipped > 0 {
+        log::info!("kept {} groups of tracks which only share their metadata \
+                    (use --similar to remove them too)", n_skipped);
+    }
+    index.prune();
+    index.save()
+}
+
+/// Groups files which have identical audio, and then files which share the
+/// same artists, title and rough duration. Copies in each group are ordered
+/// best first.
+fn find_groups(
+    index : &mut common::index::Index,
+    files : &[path::PathBuf],
+    exact : bool,
+) -> common::Result<Vec<Group>> {
+    let mut by_hash = HashMap::<String, Vec<&path::PathBuf>>::new();
+    let mut by_meta = HashMap::<String, Vec<(&path::PathBuf, Option<f64>)>>::new();
+    let mut hashes = HashMap::new();
+    for (n, file) in files.iter().enumerate() {
+        log::debug!("hashing [{} / {}]: {}", n + 1, files.len(), file.display());
+        match index.audio_hash(file) {
+            Ok(hash) => {
+                hashes.insert(file, hash.clone());
+                by_hash.entry(hash).or_default().push(file);
+            },
+            Err(err) => {
+                log::warn!("failed to hash file '{}'\nreason = {}", file.display(), err);
+                continue;
+            },
+        }
+        if exact {
+            continue;
+        }
+        match index.get(file) {
+            Ok(entry) => if let Some(key) = meta_key(&entry.meta) {
+                by_meta.entry(key).or_default().push((file, entry.meta.audio.duration));
+            },
+            Err(err) => {
+                log::warn!("failed to read file '{}'\nreason = {}", file.display(), err);
+            },
+        }
+    }
+    let mut groups = Vec::new();
+    add_groups(index, &mut groups, "identical audio", true, by_hash)?;
+    let mut by_meta = cluster_durations(by_meta);
+    // skip groups that were already found by their hash
+    by_meta.retain(|_, files| {
+        files.iter().map(|x| &hashes[x]).collect::<HashSet<_>>().len() > 1
+    });
+    add_groups(index, &mut groups, "same artists and title", false, by_meta)?;
+    Ok(groups)
+}
+
+fn add_groups(
+    index : &mut common::index::Index,
+    groups : &mut Vec<Group>,
+    reason : &'static str,
+    is_identical : bool,
+    candidates : HashMap<String, Vec<&path::PathBuf>>,
+) -> common::Result<()> {
+    let mut candidates = candidates.into_values()
+            .filter(|x| x.len() > 1)
+            .collect::<Vec<_>>();
+    candidates.sort();
+    for files in candidates {
+        let mut copies = Vec::new();
+        for file in files {
+            copies.push(Candidate::read(index, file)?);
+        }
+        copies.sort_by(|a, b| b.quality().cmp(&a.quality()).then_with(|| a.path.cmp(&b.path)));
+        groups.push(Group { reason, is_identical, copies });
+    }
+    Ok(())
+}
+
+/// Normalises the artists and title of a track, so that differences in case
+/// and punctuation are ignored.
+fn meta_key(meta : &common::meta::TrackMeta) -> Option<String> {
+    let title = common::meta::normalise(meta.title.as_ref()?);
+    let mut artists = meta.artists.iter().map(|x| common::meta::normalise(x)).collect::<Vec<_>>();
+    if artists.is_empty() || title.is_empty() {
+        return None;
+    }
+    artists.sort();
+    Some(format!("{}\x1F{}", artists.join(","), title))
+}
+
+/// Splits tracks with the same metadata by their duration, so that live and
+/// extended versions with the same title aren't grouped together. Tracks with
+/// an unknown duration match any duration, as long as it isn't ambiguous
+/// which version they belong to.
+fn cluster_durations(
+    by_meta : HashMap<String, Vec<(&path::PathBuf, Option<f64>)>>,
+) -> HashMap<String, Vec<&path::PathBuf>> {
+    let mut clusters = HashMap::new();
+    for (key, files) in by_meta {
+        let (mut known, unknown) = files.into_iter().partition::<Vec<_>, _>(|(_, x)| x.is_some());
+        known.sort_by(|(_, a), (_, b)| a.unwrap_or_default().total_cmp(&b.unwrap_or_default()));
+        let mut key_clusters = Vec::<(f64, Vec<&path::PathBuf>)>::new();
+        for (file, duration) in known {
+            let duration = duration.unwrap_or_default();
+            // every track in a cluster is within the step of the shortest
+            match key_clusters.last_mut() {
+                Some((start, files)) if duration - *start <= DURATION_STEP => files.push(file),
+                _ => key_clusters.push((duration, vec![file])),
+            }
+        }
+        let unknown = unknown.into_iter().map(|(file, _)| file);
+        match key_clusters.len() {
+            0 => key_clusters.push((0.0, unknown.collect())),
+            1 => key_clusters[0].1.extend(unknown),
+            _ => for file in unknown {
+                log::debug!("skipping '{}', its duration is unknown", file.display());
+            },
+        }
+        for (n, (_, files)) in key_clusters.into_iter().enumerate() {
+            clusters.insert(format!("{}\x1F{}", key, n), files);
+        }
+    }
+    clusters
+}
+
+fn print_group(group : &Group) {
+    println!("{}:", group.reason);
+    for (n, copy) in group.copies.iter().enumerate() {
+        println!("  {:<6}{:<6}{:>10}{:>10}  {}",
+                if n == 0 { "keep" } else { "dupe" },
+                copy.audio.codec.as_deref().or(copy.format.map(|x| x.name())).unwrap_or("?"),
+                copy.audio.bitrate.map_or("?".to_string(), |x| format!("{}kbps", x)),
+                common::format_size(copy.size),
+                copy.path.display());
+    }
+}
+
+/// Moves a file into the quarantine directory, keeping its path relative to
+/// the library so it can be restored by hand.
+fn quarantine_file(file_path : &path::Path) -> common::Result<()> {
+    let rel_path = file_path.strip_prefix(".").unwrap_or(file_path);
+    let rel_path = if rel_path.is_absolute() {
+        path::Path::new(rel_path.file_name().ok_or("invalid file path")?)
+    } else {
+        rel_path
+    };
+    let new_path = path::Path::new(common::DATA_DIR).join(QUARANTINE_DIR).join(rel_path);
+    if new_path.exists() {
+        return Err(format!("file already exists in quarantine: {}", new_path.display()).into());
+    }
+    if let Some(parent) = new_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    log::info!("moving '{}' to '{}'", file_path.display(), new_path.display());
+    fs::rename(file_path, &new_path)?;
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,196 @@
+use std::fs;
+use std::path;
+use std::process;
+use std::collections::HashSet;
+use crate::common;
+use crate::common::journal::Operation;
+use crate::common::select::Selector;
+use crate::common::tags::{ self, Change, Field };
+use crate::cmd_rename;
+
+use sanitise_file_name as sfn;
+
+const EDIT_FILE_NAME : &str = "edit.toml";
+
+const EDIT_HEADER : &str = "\
+# Edit the tags of each track, then save and close the editor to apply them.
+#  - Empty values remove a tag, and keys which are removed are left unchanged.
+#  - Changing the `path` of a track moves the file.
+#  - Tracks which are removed are left unchanged.
+#  - Don't change the `id` of a track!
+
+";
+
+/// The tags and path of a track, as it was before editing.
+struct Track {
+    path : path::PathBuf,
+    tag : Box<dyn audiotags::AudioTag + Send + Sync>,
+}
+
+/// The changes made to a track in the editor.
+struct Edit {
+    id : usize,
+    changes : Vec<Change>,
+    new_path : Option<path::PathBuf>,
+}
+
+pub fn run(select : &Selector, yes : bool) -> common::Result<()> {
+    let mut index = common::index::Index::load();
+    let mut tracks = Vec::new();
+    for file in select.files(&mut index)? {
+        match tags::read_tag(&file) {
+            Ok(tag) => tracks.push(Track { path : file, tag }),
+            Err(err) => log::warn!("failed to read tags of '{}', skipping\nreason = {}", file.display(), err),
+        }
+    }
+    if tracks.is_empty() {
+        log::warn!("no files to edit");
+        return Ok(());
+    }
+    let edit_path = path::Path::new(common::DATA_DIR).join(EDIT_FILE_NAME);
+    fs::create_dir_all(common::DATA_DIR)?;
+    fs::write(&edit_path, format!("{}{}", EDIT_HEADER, to_toml(&tracks)?))?;
+    open_editor(&edit_path)?;
+    let edits = match parse_edits(&fs::read_to_string(&edit_path)?, &tracks) {
+        Ok(edits) => edits,
+        Err(err) => {
+            return Err(format!("{}\nyour edits have been kept in: {}", err, edit_path.display()).into());
+        },
+    };
+    fs::remove_file(&edit_path)?;
+    if edits.is_empty() {
+        log::info!("no changes were made");
+    }
+    let mut operation = Operation::new("edit");
+    let result = apply_edits(&mut index, &mut operation, &mut tracks, edits, yes);
+    operation.save()?;
+    index.save()?;
+    result
+}
+
+fn apply_edits(
+    index : &mut common::index::Index,
+    operation : &mut Operation,
+    tracks : &mut [Track],
+    edits : Vec<Edit>,
+    yes : bool,
+) -> common::Result<()> {
+    for edit in edits {
+        let track = &mut tracks[edit.id];
+        if !edit.changes.is_empty() {
+            tags::print_changes(&track.path, &edit.changes);
+            if yes || common::ask_confirm() {
+                tags::apply_changes(track.tag.as_mut(), &track.path, &edit.changes)?;
+                operation.record_tags(&track.path, &edit.changes);
+            }
+        }
+        if let Some(new_path) = &edit.new_path {
+            cmd_rename::move_file(index, operation, &track.path, new_path, yes)?;
+        }
+    }
+    Ok(())
+}
+
+fn to_toml(tracks : &[Track]) -> common::Result<String> {
+    let mut items = toml::value::Array::new();
+    for (id, track) in tracks.iter().enumerate() {
+        let mut item = toml::Table::new();
+        item.insert("id".to_string(), toml::Value::Integer(id as i64));
+        item.insert("path".to_string(), toml::Value::String(track.path.to_string_lossy().into_owned()));
+        for field in Field::ALL {
+            let value = field.read(track.tag.as_ref()).unwrap_or_default();
+            let value = match value.parse::<i64>() {
+                Ok(number) if field.is_numeric() => toml::Value::Integer(number),
+                _ => toml::Value::String(value),
+            };
+            item.insert(field.key().to_string(), value);
+        }
+        items.push(toml::Value::Table(item));
+    }
+    let mut doc = toml::Table::new();
+    doc.insert("track".to_string(), toml::Value::Array(items));
+    Ok(toml::to_string(&doc)?)
+}
+
+fn open_editor(file_path : &path::Path) -> common::Result<()> {
+    let editor = common::find_editor();
+    // editors are often configured with arguments, e.g. `code --wait`
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().ok_or("no editor configured")?;
+    log::debug!("opening editor: {}", editor);
+    let status = process::Command::new(program)
+            .args(parts)
+            .arg(file_path)
+            .status()
+            .map_err(|err| format!("failed to open editor '{}': {}", editor, err))?;
+    if !status.success() {
+        return Err(format!("editor '{}' exited unsuccessfully ({})", editor, status).into());
+    }
+    Ok(())
+}
+
+/// Reads the edited file, checking every change before any are applied.
+fn parse_edits(source : &str, tracks : &[Track]) -> common::Result<Vec<Edit>> {
+    let doc = source.parse::<toml::Table>()?;
+    let items = match doc.get("track") {
+        Some(toml::Value::Array(items)) => items.as_slice(),
+        None => &[],
+        Some(_) => return Err("expected `track` to be a list of tracks".into()),
+    };
+    let mut edits = Vec::new();
+    let mut seen = HashSet::new();
+    for item in items {
+        let item = item.as_table().ok_or("expected each track to be a table")?;
+        let id = item.get("id")
+                .and_then(|x| x.as_integer())
+                .and_then(|x| usize::try_from(x).ok())
+                .filter(|x| *x < tracks.len())
+                .ok_or("every track needs the `id` it was given")?;
+        if !seen.insert(id) {
+            return Err(format!("track id {} is used more than once", id).into());
+        }
+        let track = &tracks[id];
+        let mut changes = Vec::new();
+        for field in Field::ALL {
+            let new = match item.get(field.key()) {
+                None => continue,
+                Some(toml::Value::String(x)) if x.trim().is_empty() => None,
+                Some(toml::Value::String(x)) => Some(x.trim().to_string()),
+                Some(toml::Value::Integer(x)) => Some(x.to_string()),
+                Some(_) => return Err(format!("expected `{}` of track {} to be text", field.key(), id).into()),
+            };
+            if let Some(new) = &new {
+                field.validate(new).map_err(|err| format!("{} (track {})", err, id))?;
+            }
+            let old = field.read(track.tag.as_ref());
+            if new != old {
+                changes.push(Change { field, old, new });
+            }
+        }
+        let new_path = match item.get("path").and_then(|x| x.as_str()) {
+            Some(new_path) if path::Path::new(new_path) != track.path => Some(sanitise_path(new_path)?),
+            _ => None,
+        };
+        if !changes.is_empty() || new_path.is_some() {
+            edits.push(Edit { id, changes, new_path });
+        }
+    }
+    Ok(edits)
+}
+
+/// Makes sure a path entered by the user is a valid file name, and stays
+/// inside of the library.
+fn sanitise_path(new_path : &str) -> common::Result<path::PathBuf> {
+    let new_path = path::Path::new(new_path.trim());
+    let file_name = new_path.file_name()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| format!("invalid path '{}'", new_path.display()))?;
+    let file_name = sfn::sanitise_with_options(file_name,
+        &sfn::Options { trim_more_punctuation : false, ..sfn::Options::DEFAULT }
+    );
+    let is_outside = new_path.components().any(|x| !matches!(x, path::Component::Normal(_) | path::Component::CurDir));
+    if is_outside {
+        return Err(format!("path must be relative to the library: {}", new_path.display()).into());
+    }
+    Ok(new_path.with_file_name(file_name))
+}
\ No newline at end of file
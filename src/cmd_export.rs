@@ -0,0 +1,93 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path;
+use crate::common;
+use crate::common::csv;
+use crate::common::select::Selector;
+use crate::common::tags;
+
+use clap::ValueEnum;
+
+/// The columns written to CSV files, in order.
+const COLUMNS : &[&str] = &[
+    "path", "file_name", "artists", "features", "album", "album_artist",
+    "track", "year", "title", "codec", "duration", "bitrate", "sample_rate",
+    "channels", "size", "source", "extractor",
+    "downloaded", "tags",
+];
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// Comma separated values, with a header row.
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+pub fn run(select : &Selector, format : ExportFormat, output : Option<&str>) -> common::Result<()> {
+    let mut index = common::index::Index::load();
+    let mut files = select.files_or_library(&mut index)?;
+    files.sort();
+    let mut rows = Vec::new();
+    for file in &files {
+        match index.get(file) {
+            Ok(entry) => rows.push(track_row(file, entry)),
+            Err(err) => log::warn!("failed to read file '{}'\nreason = {}", file.display(), err),
+        }
+    }
+    index.save()?;
+    let mut out : Box<dyn Write> = match output {
+        Some(output) if output != "-" => Box::new(BufWriter::new(fs::File::create(output)?)),
+        _ => Box::new(BufWriter::new(io::stdout())),
+    };
+    match format {
+        ExportFormat::Csv => {
+            writeln!(out, "{}", COLUMNS.join(","))?;
+            for row in &rows {
+                let cells = COLUMNS.iter()
+                        .map(|column| csv::escape(&csv_cell(&row[column])))
+                        .collect::<Vec<_>>();
+                writeln!(out, "{}", cells.join(","))?;
+            }
+        },
+        ExportFormat::Jsonl => {
+            for row in &rows {
+                writeln!(out, "{}", serde_json::to_string(row)?)?;
+            }
+        },
+    }
+    out.flush()?;
+    log::info!("exported {} tracks", rows.len());
+    Ok(())
+}
+
+/// Collects every exported field of a track.
+fn track_row(file : &path::Path, entry : &common::index::Entry) -> serde_json::Value {
+    let mut row = entry.to_json(file);
+    // custom fields, such as those written by `add`
+    let custom_tags = tags::read_fields(file)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key, serde_json::Value::String(value)))
+            .collect::<serde_json::Map<_, _>>();
+    row["file_name"] = serde_json::json!(file.file_name().map(|x| x.to_string_lossy()));
+    row["tags"] = serde_json::Value::Object(custom_tags);
+    row
+}
+
+/// Flattens a JSON value into the text of a single CSV cell.
+fn csv_cell(value : &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(items) => items.iter()
+                .map(csv_cell)
+                .collect::<Vec<_>>()
+                .join(csv::LIST_SEPARATOR),
+        serde_json::Value::Object(fields) => fields.iter()
+                .map(|(key, value)| format!("{}={}", key, csv_cell(value)))
+                .collect::<Vec<_>>()
+                .join(csv::LIST_SEPARATOR),
+        other => other.to_string(),
+    }
+}
\ No newline at end of file
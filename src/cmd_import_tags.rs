@@ -0,0 +1,141 @@
+use std::fs;
+use std::path;
+use std::collections::HashMap;
+use crate::common;
+use crate::common::csv;
+use crate::common::meta::TrackMeta;
+use crate::common::tags::{ self, Change, Field };
+
+/// Returns the value that `export` writes for a field, so that cells which
+/// weren't edited can be skipped.
+fn exported(field : Field, meta : &TrackMeta) -> String {
+    match field {
+        Field::Artists => meta.artists.join(csv::LIST_SEPARATOR),
+        Field::Album => meta.album.clone().unwrap_or_default(),
+        Field::AlbumArtist => meta.album_author.clone().unwrap_or_default(),
+        Field::Title => meta.title.clone().unwrap_or_default(),
+        Field::Track => meta.track_number.as_ref().map(|x| x.0.to_string()).unwrap_or_default(),
+        Field::Year => meta.year.map(|x| x.to_string()).unwrap_or_default(),
+    }
+}
+
+/// Converts the text of a cell into the value of the tag. Empty cells remove
+/// the tag.
+fn parse_cell(field : Field, cell : &str) -> common::Result<Option<String>> {
+    let cell = cell.trim();
+    if cell.is_empty() {
+        return Ok(None);
+    }
+    let value = match field {
+        Field::Artists => csv::split_list(cell).join(", "),
+        _ => cell.to_string(),
+    };
+    field.validate(&value)?;
+    Ok(Some(value))
+}
+
+pub fn run(csv_path : &str, dry_run : bool, yes : bool) -> common::Result<()> {
+    let rows = csv::parse(&fs::read_to_string(csv_path)?)?;
+    let (header, rows) = rows.split_first().ok_or("CSV file is empty")?;
+    let find_column = |name : &str| header.iter().position(|x| x.trim().eq_ignore_ascii_case(name));
+    let path_column = find_column("path");
+    let source_column = find_column("source");
+    if path_column.is_none() && source_column.is_none() {
+        return Err("CSV file needs a `path` or `source` column to match rows to files".into());
+    }
+    let fields = header.iter()
+            .enumerate()
+            .filter_map(|(i, name)| Field::from_name(name).map(|x| (i, x)))
+            .collect::<Vec<_>>();
+    if fields.is_empty() {
+        log::warn!("CSV file has no columns that can be imported");
+    }
+    let mut index = common::index::Index::load();
+    let mut sources = None;
+    let mut n_changed = 0;
+    for (n, row) in rows.iter().enumerate() {
+        let cell = |column : Option<usize>| column.and_then(|x| row.get(x)).map(|x| x.trim()).unwrap_or_default();
+        // match by path, or by the source URL if the file has since moved
+        let file = match path::PathBuf::from(cell(path_column)) {
+            file if file.is_file() => file,
+            _ if !cell(source_column).is_empty() => {
+                let sources = match &sources {
+                    Some(sources) => sources,
+                    None => sources.insert(find_sources(&mut index)?),
+                };
+                if let Some(file) = sources.get(cell(source_column)) {
+                    file.clone()
+                } else {
+                    log::warn!("no file matches row {} of the CSV file, skipping", n + 2);
+                    continue;
+                }
+            },
+            _ => {
+                log::warn!("no file matches row {} of the CSV file, skipping", n + 2);
+                continue;
+            },
+        };
+        match import_row(&mut index, &file, row, &fields, dry_run, yes) {
+            Ok(true) => n_changed += 1,
+            Ok(false) => (),
+            Err(err) => log::warn!("failed to import row {} for '{}'\nreason = {}", n + 2, file.display(), err),
+        }
+    }
+    index.save()?;
+    if dry_run {
+        log::info!("{} files would be changed", n_changed);
+    } else {
+        log::info!("{} files changed", n_changed);
+    }
+    Ok(())
+}
+
+/// Maps the source URL of every file in the library to its path.
+fn find_sources(index : &mut common::index::Index) -> common::Result<HashMap<String, path::PathBuf>> {
+    let mut sources = HashMap::new();
+    common::library_foreach(|file| {
+        if let Ok(entry) = index.get(file) {
+            if let Some(source) = &entry.source {
+                sources.insert(source.url.clone(), file.to_path_buf());
+            }
+        }
+        Ok(())
+    })?;
+    Ok(sources)
+}
+
+/// Shows the changes a row makes to a file, and writes them if accepted.
+/// Returns whether the file was (or would have been) changed.
+fn import_row(
+    index : &mut common::index::Index,
+    file : &path::Path,
+    row : &[String],
+    fields : &[(usize, Field)],
+    dry_run : bool,
+    yes : bool,
+) -> common::Result<bool> {
+    let meta = index.parse(file)?;
+    let mut tag = tags::read_tag(file)?;
+    let mut changes = Vec::new();
+    for (column, field) in fields {
+        let cell = row.get(*column).map(|x| x.trim()).unwrap_or_default();
+        if cell == exported(*field, &meta) {
+            continue; // the cell wasn't edited
+        }
+        let new = parse_cell(*field, cell)?;
+        let old = field.read(tag.as_ref());
+        if new != old {
+            changes.push(Change { field : *field, old, new });
+        }
+    }
+    if changes.is_empty() {
+        log::debug!("file is unchanged, skipping: {}", file.display());
+        return Ok(false);
+    }
+    tags::print_changes(file, &changes);
+    if dry_run || !(yes || common::ask_confirm()) {
+        return Ok(dry_run);
+    }
+    tags::apply_changes(tag.as_mut(), file, &changes)?;
+    Ok(true)
+}
\ No newline at end of file
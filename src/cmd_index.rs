@@ -0,0 +1,24 @@
+use crate::common;
+use crate::common::index;
+use crate::common::select::Selector;
+
+pub fn run(select : &Selector, rebuild : bool) -> common::Result<()> {
+    let mut index = if rebuild {
+        log::info!("discarding the existing library index");
+        index::Index::new()
+    } else {
+        index::Index::load()
+    };
+    let files = select.files_or_library(&mut index)?;
+    let file_count = files.len();
+    for file in &files {
+        if let Err(err) = index.get(file) {
+            log::warn!("failed to index file '{}'\nreason = {}", file.display(), err);
+        }
+    }
+    let removed = index.prune();
+    index.save()?;
+    log::info!("indexed {} files ({} entries total, {} stale entries removed)",
+            file_count, index.len(), removed);
+    Ok(())
+}
\ No newline at end of file
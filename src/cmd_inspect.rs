@@ -0,0 +1,78 @@
+use std::path;
+use crate::common;
+use crate::common::probe::AudioProperties;
+use crate::common::select::Selector;
+use crate::common::source;
+use crate::common::spectrum;
+
+pub fn run(select : &Selector) -> common::Result<()> {
+    let mut index = common::index::Index::load();
+    let mut cache = spectrum::Cache::load();
+    // only looked for once a lossless file needs verifying
+    let mut ffmpeg_path = None;
+    for file in select.files(&mut index)? {
+        inspect_file(&mut index, &mut cache, &mut ffmpeg_path, &file)?;
+    }
+    cache.save()?;
+    index.save()
+}
+
+fn inspect_file(
+    index : &mut common::index::Index,
+    cache : &mut spectrum::Cache,
+    ffmpeg_path : &mut Option<Option<path::PathBuf>>,
+    file : &path::Path,
+) -> common::Result<()> {
+    let entry = index.get(file)?.clone();
+    let file_meta = &entry.meta;
+    log::debug!("{:?}", file_meta);
+    println!("{}", file.display());
+    print_field("artists", Some(file_meta.artists.join(", ")).filter(|x| !x.is_empty()));
+    print_field("features", Some(file_meta.features.join(", ")).filter(|x| !x.is_empty()));
+    print_field("album", file_meta.album.clone());
+    print_field("album author", file_meta.album_author.clone());
+    print_field("track number", file_meta.track_number.as_ref().map(|x| x.0.to_string()));
+    print_field("title", file_meta.title.clone());
+    print_field("audio", describe_audio(&file_meta.audio));
+    if file_meta.audio.is_lossless() {
+        let quality = file_meta.audio.sample_rate.zip(ffmpeg_path.get_or_insert_with(common::find_ffmpeg_path).as_ref())
+                .and_then(|(sample_rate, ffmpeg_path)| {
+                    match cache.get_or_analyse(index, ffmpeg_path, file, sample_rate, false) {
+                        Ok((analysis, _)) => Some(analysis.describe()),
+                        Err(err) => {
+                            log::warn!("failed to analyse file '{}'\nreason = {}", file.display(), err);
+                            None
+                        },
+                    }
+                });
+        print_field("quality", quality);
+    }
+    // prefer the tags, since they travel with the file
+    let file_source = entry.source.clone().or_else(|| source::find_in_log(file));
+    if let Some(file_source) = file_source {
+        print_field("source", Some(file_source.url));
+        print_field("extractor", file_source.extractor);
+        print_field("downloaded", file_source.date);
+    } else {
+        print_field("source", None);
+    }
+    Ok(())
+}
+
+/// Summarises the technical properties of a track, e.g.
+/// `flac, 44100 Hz, 2 channels, 1013 kbps, 3:04`.
+fn describe_audio(audio : &AudioProperties) -> Option<String> {
+    let parts = [
+        audio.codec.clone(),
+        audio.sample_rate.map(|x| format!("{} Hz", x)),
+        audio.channels.map(|x| format!("{} channels", x)),
+        audio.bitrate.map(|x| format!("{} kbps", x)),
+        audio.duration.map(common::format_duration),
+    ];
+    let parts = parts.into_iter().flatten().collect::<Vec<_>>();
+    if parts.is_empty() { None } else { Some(parts.join(", ")) }
+}
+
+fn print_field(name : &str, value : Option<String>) {
+    println!("  {:<14}{}", name, value.as_deref().unwrap_or("-"));
+}
\ No newline at end of file
@@ -0,0 +1,182 @@
+use std::fs;
+use std::path;
+use std::collections::{ BTreeSet, HashMap, HashSet };
+use crate::common;
+use crate::common::loudness::{ self, Analysis };
+use crate::common::select::Selector;
+use crate::common::sniff::{ Format, Kind };
+use crate::common::tags;
+use crate::common::walk::Walker;
+
+const TRACK_GAIN : &str = "REPLAYGAIN_TRACK_GAIN";
+const TRACK_PEAK : &str = "REPLAYGAIN_TRACK_PEAK";
+const ALBUM_GAIN : &str = "REPLAYGAIN_ALBUM_GAIN";
+const ALBUM_PEAK : &str = "REPLAYGAIN_ALBUM_PEAK";
+
+/// Opus files store their gain relative to a different reference loudness,
+/// and have no peak tags.
+const R128_TRACK_GAIN : &str = "R128_TRACK_GAIN";
+const R128_ALBUM_GAIN : &str = "R128_ALBUM_GAIN";
+
+struct Track {
+    file : path::PathBuf,
+    album : Option<String>,
+    analysis : Analysis,
+    is_opus : bool,
+}
+
+pub fn run(select : &Selector, force : bool) -> common::Result<()> {
+    let ffmpeg_path = if let Some(x) = common::find_ffmpeg_path() { x } else {
+        log::error!("an executable to `ffmpeg` is required for this command, aborting");
+        log::info!("make sure `ffmpeg` is in your PATH\n\
+                    alternatively, add `ffmpeg = <path>` to your `catty.toml`");
+        return Ok(());
+    };
+    let mut index = common::index::Index::load();
+    let mut cache = loudness::Cache::load();
+    let mut db = common::infer::Database::new();
+    let mut tracks = HashMap::new();
+    let mut n_analysed = 0;
+    let mut files = select.files_or_library(&mut index)?;
+    if !select.is_empty() {
+        add_album_tracks(&mut files)?;
+    }
+    let n_files = files.len();
+    for (n, file) in files.into_iter().enumerate() {
+        let analysis = match analyse(&ffmpeg_path, &mut index, &mut cache, &file, force) {
+            Ok((analysis, is_new)) => {
+                if is_new {
+                    log::info!("analysed [{} / {}] {}", n + 1, n_files, file.display());
+                    n_analysed += 1;
+                }
+                analysis
+            },
+            Err(err) => {
+                log::warn!("failed to analyse file '{}'\nreason = {}", file.display(), err);
+                continue;
+            },
+        };
+        let album = index.parse(&file).ok().and_then(|x| x.album);
+        let is_opus = index.get(&file).is_ok_and(|x| x.kind == Kind::Audio(Format::Opus));
+        if let Some(file_location) = db.add_file(&file) {
+            tracks.insert(file_location.id, Track { file, album, analysis, is_opus });
+        }
+    }
+    cache.save()?;
+    index.save()?;
+    // tracks are grouped into albums by their directory
+    let (collections, db_files) = db.complete();
+    let mut albums = HashMap::<_, Vec<_>>::new();
+    for db_file in &db_files {
+        if let Some(track) = tracks.get(&db_file.id) {
+            albums.entry(db_file.id_collection).or_default().push(track);
+        }
+    }
+    let mut n_written = 0;
+    for collection in &collections {
+        let album_tracks = if let Some(x) = albums.get(&collection.id) { x } else { continue };
+        // only directories holding a single album get an album gain
+        let album_names = album_tracks.iter()
+                .map(|x| x.album.as_deref().map(common::meta::normalise))
+                .collect::<HashSet<_>>();
+        let album_analysis = match album_names.into_iter().collect::<Vec<_>>().as_slice() {
+            [Some(_)] => loudness::combine(&album_tracks.iter().map(|x| x.analysis).collect::<Vec<_>>()),
+            _ => None,
+        };
+        for track in album_tracks {
+            match write_tags(track, album_analysis.as_ref()) {
+                Ok(true) => n_written += 1,
+                Ok(false) => log::debug!("loudness tags are already current: {}", track.file.display()),
+                Err(err) => log::warn!("failed to write loudness tags to '{}'\nreason = {}", track.file.display(), err),
+            }
+        }
+    }
+    log::info!("analysed {} files, and updated the tags of {} files", n_analysed, n_written);
+    Ok(())
+}
+
+/// Adds the other tracks in the directories of the selected files, since the
+/// album gain has to be measured over every track of an album.
+fn add_album_tracks(files : &mut Vec<path::PathBuf>) -> common::Result<()> {
+    let dirs = files.iter()
+            .map(|x| x.parent().unwrap_or(path::Path::new("")).to_path_buf())
+            .collect::<BTreeSet<_>>();
+    let mut seen = files.iter().cloned().collect::<HashSet<_>>();
+    let mut walker = Walker::new(false, None);
+    let mut n_added = 0;
+    for dir in dirs {
+        let read_dir = if dir.as_os_str().is_empty() { path::Path::new(".") } else { &dir };
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(read_dir)? {
+            entries.push(dir.join(entry?.file_name()));
+        }
+        entries.sort();
+        for entry in entries {
+            if entry.is_file() && common::is_audio_file(&entry)
+                    && !walker.is_ignored(&entry, false) && seen.insert(entry.clone()) {
+                files.push(entry);
+                n_added += 1;
+            }
+        }
+    }
+    if n_added > 0 {
+        log::info!("including {} other tracks from the same directories, for their album gain", n_added);
+    }
+    Ok(())
+}
+
+/// Returns the loudness of a file, only decoding it if the cached analysis
+/// is missing or `force` is enabled.
+fn analyse(
+    ffmpeg_path : &path::Path,
+    index : &mut common::index::Index,
+    cache : &mut loudness::Cache,
+    file : &path::Path,
+    force : bool,
+) -> common::Result<(Analysis, bool)> {
+    let audio_hash = index.audio_hash(file)?;
+    if !force {
+        if let Some(analysis) = cache.get(&audio_hash) {
+            return Ok((analysis, false));
+        }
+    }
+    let analysis = loudness::analyse(ffmpeg_path, file)?;
+    cache.insert(audio_hash, analysis);
+    Ok((analysis, true))
+}
+
+/// Writes the ReplayGain tags of a track, or the R128 tags of an Opus file,
+/// unless they're already up to date. Returns whether the tags were written.
+fn write_tags(track : &Track, album : Option<&Analysis>) -> common::Result<bool> {
+    let format_gain = |x : &Analysis| format!("{:.2} dB", x.gain());
+    let format_peak = |x : &Analysis| format!("{:.6}", x.peak_ratio());
+    let format_r128 = |x : &Analysis| x.r128_gain().to_string();
+    let values = if track.is_opus {
+        [
+            (R128_TRACK_GAIN, Some(format_r128(&track.analysis))),
+            (R128_ALBUM_GAIN, album.map(format_r128)),
+            // ReplayGain tags aren't used in Opus files
+            (TRACK_GAIN, None),
+            (TRACK_PEAK, None),
+            (ALBUM_GAIN, None),
+            (ALBUM_PEAK, None),
+        ].to_vec()
+    } else {
+        [
+            (TRACK_GAIN, Some(format_gain(&track.analysis))),
+            (TRACK_PEAK, Some(format_peak(&track.analysis))),
+            (ALBUM_GAIN, album.map(format_gain)),
+            (ALBUM_PEAK, album.map(format_peak)),
+        ].to_vec()
+    };
+    let fields = values.iter().map(|(key, value)| (*key, value.as_deref())).collect::<Vec<_>>();
+    let existing = tags::read_fields(&track.file)?;
+    let is_current = fields.iter().all(|(key, value)| {
+        existing.iter().find(|x| x.0 == *key).map(|x| x.1.as_str()) == *value
+    });
+    if is_current {
+        return Ok(false);
+    }
+    tags::write_fields(&track.file, &fields)?;
+    Ok(true)
+}
\ No newline at end of file
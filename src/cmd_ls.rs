@@ -0,0 +1,87 @@
+use std::path;
+use crate::common;
+use crate::common::index;
+use crate::common::query;
+
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    /// One file path per line.
+    Paths,
+    /// A table of the most common fields.
+    Table,
+    /// A JSON array with every field.
+    Json,
+}
+
+pub fn run(query : &[String], format : OutputFormat) -> common::Result<()> {
+    let query = query::Query::parse(&query.join(" "))?;
+    log::debug!("{:?}", query);
+    let mut index = index::Index::load();
+    let mut results = Vec::new();
+    common::library_foreach(|file| {
+        let entry = match index.get(file) {
+            Ok(entry) => entry,
+            Err(err) => {
+                log::warn!("failed to read file '{}'\nreason = {}", file.display(), err);
+                return Ok(());
+            },
+        };
+        if query.matches(file, entry) {
+            results.push((file.to_path_buf(), entry.clone()));
+        }
+        Ok(())
+    })?;
+    index.save()?;
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    match format {
+        OutputFormat::Paths => {
+            for (file, _) in &results {
+                println!("{}", file.display());
+            }
+        },
+        OutputFormat::Table => print_table(&results),
+        OutputFormat::Json => {
+            let tracks = results.iter()
+                    .map(|(file, entry)| entry.to_json(file))
+                    .collect::<Vec<_>>();
+            println!("{}", serde_json::to_string_pretty(&tracks)?);
+        },
+    }
+    log::info!("{} matching files", results.len());
+    Ok(())
+}
+
+fn print_table(results : &[(path::PathBuf, index::Entry)]) {
+    let header = ["artist", "album", "#", "title", "year", "path"];
+    let mut rows = vec![header.map(String::from)];
+    for (file, entry) in results {
+        let meta = &entry.meta;
+        rows.push([
+            meta.artists.join(", "),
+            meta.album.clone().unwrap_or_default(),
+            meta.track_number.as_ref().map(|x| x.0.to_string()).unwrap_or_default(),
+            meta.title.clone().unwrap_or_default(),
+            meta.year.map(|x| x.to_string()).unwrap_or_default(),
+            file.display().to_string(),
+        ]);
+    }
+    let mut widths = [0; 6];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in &rows {
+        let mut line = String::new();
+        for (i, (width, cell)) in widths.iter().zip(row).enumerate() {
+            if i + 1 == row.len() {
+                line.push_str(cell);
+            } else {
+                line.push_str(&format!("{:<width$}  ", cell, width = width));
+            }
+        }
+        println!("{}", line);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,108 @@
+use std::fs;
+use std::path;
+use crate::common;
+use crate::common::lyrics;
+use crate::common::select::Selector;
+use crate::common::tags;
+
+use clap::Subcommand;
+
+#[derive(Subcommand, Debug)]
+pub enum LyricsCommand {
+    /// Embeds the `.lrc` file next to each track into its tags.
+    Embed {
+        #[command(flatten)]
+        select : Selector,
+        /// Replace lyrics which are already embedded.
+        #[arg(long)]
+        force : bool,
+    },
+    /// Writes the lyrics embedded in each track to an `.lrc` file next to it.
+    Extract {
+        #[command(flatten)]
+        select : Selector,
+        /// Replace `.lrc` files which already exist.
+        #[arg(long)]
+        force : bool,
+    },
+    /// Lists the tracks which have no lyrics, neither embedded nor in an
+    /// `.lrc` file.
+    Missing {
+        #[command(flatten)]
+        select : Selector,
+    },
+}
+
+pub fn run(command : &LyricsCommand) -> common::Result<()> {
+    let mut index = common::index::Index::load();
+    let (select, action) = match command {
+        LyricsCommand::Embed { select, .. } => (select, "embedded"),
+        LyricsCommand::Extract { select, .. } => (select, "extracted"),
+        LyricsCommand::Missing { select } => (select, "missing"),
+    };
+    let mut files = select.files_or_library(&mut index)?;
+    files.sort();
+    index.save()?;
+    let mut n_files = 0;
+    for file in &files {
+        let result = match command {
+            LyricsCommand::Embed { force, .. } => embed(file, *force),
+            LyricsCommand::Extract { force, .. } => extract(file, *force),
+            LyricsCommand::Missing { .. } => is_missing(file),
+        };
+        match result {
+            Ok(true) => n_files += 1,
+            Ok(false) => (),
+            Err(err) => log::warn!("failed to read lyrics of '{}'\nreason = {}", file.display(), err),
+        }
+    }
+    log::info!("lyrics {} for {} of {} tracks", action, n_files, files.len());
+    Ok(())
+}
+
+fn embed(file : &path::Path, force : bool) -> common::Result<bool> {
+    let lrc_path = lyrics::lrc_path(file);
+    if !lrc_path.is_file() {
+        return Ok(false);
+    }
+    let text = fs::read_to_string(&lrc_path)?;
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(&text);
+    match tags::read_lyrics(file)? {
+        Some(embedded) if embedded == text => return Ok(false),
+        Some(_) if !force => {
+            log::info!("track already has lyrics, skipping (use --force to replace them): {}", file.display());
+            return Ok(false);
+        },
+        _ => (),
+    }
+    tags::write_lyrics(file, text)?;
+    log::info!("embedded lyrics from: {}", lrc_path.display());
+    Ok(true)
+}
+
+fn extract(file : &path::Path, force : bool) -> common::Result<bool> {
+    let text = match tags::read_lyrics(file)? {
+        Some(text) => text,
+        None => return Ok(false),
+    };
+    let lrc_path = lyrics::lrc_path(file);
+    if lrc_path.exists() && !force {
+        log::info!("lyrics file already exists, skipping (use --force to replace it): {}", lrc_path.display());
+        return Ok(false);
+    }
+    let mut text = text;
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    fs::write(&lrc_path, text)?;
+    log::info!("extracted lyrics to: {}", lrc_path.display());
+    Ok(true)
+}
+
+fn is_missing(file : &path::Path) -> common::Result<bool> {
+    if lyrics::lrc_path(file).is_file() || tags::read_lyrics(file)?.is_some() {
+        return Ok(false);
+    }
+    println!("{}", file.display());
+    Ok(true)
+}
\ No newline at end of file
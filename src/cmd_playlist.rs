@@ -0,0 +1,285 @@
+use std::fs;
+use std::path;
+use std::collections::HashMap;
+use crate::common;
+use crate::common::index::Index;
+use crate::common::meta::{ self, TrackMeta };
+use crate::common::playlist::{ self, Format, M3u, M3uEntry };
+use crate::common::select::Selector;
+
+use clap::Subcommand;
+use sanitise_file_name as sfn;
+
+#[derive(Subcommand, Debug)]
+pub enum PlaylistCommand {
+    /// Writes a playlist of the selected files, such as those matching
+    /// `--where <QUERY>`, into the playlist directory.
+    Create {
+        /// The name of the playlist, which is also used as its file name.
+        name : String,
+        #[command(flatten)]
+        select : Selector,
+        /// The format of the playlist.
+        #[arg(short, long, value_enum, default_value = "m3u8")]
+        format : Format,
+    },
+    /// Regenerates the smart playlists declared in `catty.toml` as
+    /// `[[playlist]]` tables, so they pick up changes to the library.
+    Update {
+        /// Only update the smart playlists with these names.
+        names : Vec<String>,
+    },
+    /// Repairs entries of M3U playlists which point to files that no longer
+    /// exist, by finding the track in the library with the same file name or
+    /// metadata.
+    Fix {
+        /// The playlists to repair (every M3U playlist in the library by
+        /// default).
+        playlists : Vec<String>,
+    },
+}
+
+/// A playlist which is regenerated from a query, declared in `catty.toml` as
+/// a `[[playlist]]` table.
+#[derive(Debug)]
+struct SmartPlaylist {
+    name : String,
+    query : String,
+    format : Format,
+}
+
+impl SmartPlaylist {
+    fn from_table(table : &toml::Table) -> common::Result<Self> {
+        let name = table.get("name").and_then(|x| x.as_str())
+                .ok_or("playlist is missing a `name` field")?
+                .to_string();
+        let query = table.get("where").and_then(|x| x.as_str())
+                .ok_or_else(|| format!("playlist '{}' is missing a `where` field", name))?
+                .to_string();
+        let format = match table.get("format").and_then(|x| x.as_str()) {
+            Some(format) => Format::from_name(format)
+                    .ok_or_else(|| format!("unknown format '{}' for playlist '{}'", format, name))?,
+            None => Format::M3u8,
+        };
+        Ok(Self { name, query, format })
+    }
+}
+
+fn find_smart_playlists() -> common::Result<Vec<SmartPlaylist>> {
+    let mut playlists = Vec::new();
+    if let Some(value) = common::find_config_value("playlist") {
+        let tables = value.as_array().ok_or("expected `playlist` to be an array of tables")?;
+        for table in tables {
+            let table = table.as_table().ok_or("expected `playlist` to be a table")?;
+            playlists.push(SmartPlaylist::from_table(table)?);
+        }
+    }
+    Ok(playlists)
+}
+
+pub fn run(command : &PlaylistCommand, yes : bool) -> common::Result<()> {
+    let mut index = Index::load();
+    let result = match command {
+        PlaylistCommand::Create { name, select, format }
+            => create(&mut index, name, select, *format, yes),
+        PlaylistCommand::Update { names }
+            => update(&mut index, names),
+        PlaylistCommand::Fix { playlists }
+            => fix(&mut index, playlists, yes),
+    };
+    index.save()?;
+    result
+}
+
+fn create(index : &mut Index, name : &str, select : &Selector, format : Format, yes : bool) -> common::Result<()> {
+    let files = select.files_or_library(index)?;
+    let playlist_path = find_path(name, format);
+    if playlist_path.exists() {
+        log::warn!("playlist already exists and will be replaced: {}", playlist_path.display());
+        if !(yes || common::ask_confirm()) {
+            return Ok(());
+        }
+    }
+    write_playlist(index, name, &playlist_path, format, files)
+}
+
+fn update(index : &mut Index, names : &[String]) -> common::Result<()> {
+    let mut playlists = find_smart_playlists()?;
+    if playlists.is_empty() {
+        log::warn!("no smart playlists found, add a `[[playlist]]` table to your `catty.toml`");
+        return Ok(());
+    }
+    if !names.is_empty() {
+        playlists.retain(|x| names.iter().any(|name| name.eq_ignore_ascii_case(&x.name)));
+        if playlists.is_empty() {
+            log::warn!("no smart playlists matched the names: {:?}", names);
+            return Ok(());
+        }
+    }
+    for playlist in &playlists {
+        let select = Selector { query : Some(playlist.query.clone()), ..Selector::default() };
+        let files = select.files(index)?;
+        let playlist_path = find_path(&playlist.name, playlist.format);
+        write_playlist(index, &playlist.name, &playlist_path, playlist.format, files)?;
+    }
+    Ok(())
+}
+
+/// Returns where the playlist with this name is written.
+fn find_path(name : &str, format : Format) -> path::PathBuf {
+    let file_name = sfn::sanitise_with_options(name,
+        &sfn::Options { trim_more_punctuation : false, ..sfn::Options::DEFAULT }
+    );
+    playlist::find_dir().join(format!("{}.{}", file_name, format.extension()))
+}
+
+fn write_playlist(
+    index : &mut Index,
+    name : &str,
+    playlist_path : &path::Path,
+    format : Format,
+    files : Vec<path::PathBuf>,
+) -> common::Result<()> {
+    let mut tracks = Vec::new();
+    for file in files {
+        let rel_path = match common::walk::rel_to_library(&file) {
+            Some(rel_path) => rel_path,
+            None => {
+                log::warn!("file is outside of the library, skipping: {}", file.display());
+                continue;
+            },
+        };
+        match index.parse(&file) {
+            Ok(meta) => tracks.push((rel_path, meta)),
+            Err(err) => log::warn!("failed to read file '{}'\nreason = {}", file.display(), err),
+        }
+    }
+    tracks.sort_by(|a, b| a.0.cmp(&b.0));
+    let items = tracks.iter()
+            .map(|(path, meta)| playlist::Item { path, meta })
+            .collect::<Vec<_>>();
+    let dir = playlist_path.parent().unwrap_or(path::Path::new(""));
+    fs::create_dir_all(dir)?;
+    // entries are relative to the playlist, so it must be inside of the library
+    let rel_dir = common::walk::rel_to_library(dir)
+            .ok_or_else(|| format!("playlist directory must be inside of the library: {}", dir.display()))?;
+    fs::write(playlist_path, playlist::write(format, name, &rel_dir, &items))?;
+    log::info!("wrote {} tracks to playlist: {}", items.len(), playlist_path.display());
+    Ok(())
+}
+
+/// Finds tracks in the library which a broken playlist entry may refer to.
+struct Matcher {
+    tracks : HashMap<String, Vec<path::PathBuf>>,
+}
+
+impl Matcher {
+    fn new(index : &mut Index) -> common::Result<Self> {
+        let mut matcher = Self { tracks : HashMap::new() };
+        common::library_foreach(|file| {
+            match index.parse(file) {
+                Ok(meta) => matcher.add(file, &meta),
+                Err(err) => log::warn!("failed to read file '{}'\nreason = {}", file.display(), err),
+            }
+            Ok(())
+        })?;
+        Ok(matcher)
+    }
+
+    fn add(&mut self, file : &path::Path, meta : &TrackMeta) {
+        let file = common::walk::rel_to_library(file).unwrap_or(file.to_path_buf());
+        let mut keys = Vec::new();
+        if let Some(stem) = file.file_stem() {
+            keys.push(format!("file\x1F{}", meta::normalise(&stem.to_string_lossy())));
+        }
+        if let Some(title) = &meta.title {
+            keys.push(format!("track\x1F{} {}", meta::normalise(&meta.artists.join(" ")), meta::normalise(title)));
+            keys.push(format!("title\x1F{}", meta::normalise(title)));
+        }
+        for key in keys {
+            self.tracks.entry(key).or_default().push(file.clone());
+        }
+    }
+
+    /// Returns the only track matching the entry, trying the most specific
+    /// matches first.
+    fn find(&self, entry : &M3uEntry) -> Option<&path::Path> {
+        let stem = entry.path.file_stem().map(|x| meta::normalise(&x.to_string_lossy()));
+        let title = entry.title.as_ref().map(|x| meta::normalise(x));
+        let keys = [
+            stem.as_ref().map(|x| format!("file\x1F{}", x)),
+            title.as_ref().map(|x| format!("track\x1F{}", x)),
+            stem.as_ref().map(|x| format!("track\x1F{}", x)),
+            title.as_ref().map(|x| format!("title\x1F{}", x)),
+        ];
+        for key in keys.iter().flatten() {
+            match self.tracks.get(key).map(|x| x.as_slice()) {
+                Some([file]) => return Some(file),
+                Some([]) | None => (),
+                Some(files) => {
+                    log::debug!("entry '{}' matches {} tracks, ignoring them", entry.path.display(), files.len());
+                },
+            }
+        }
+        None
+    }
+}
+
+fn fix(index : &mut Index, playlist_paths : &[String], yes : bool) -> common::Result<()> {
+    let playlist_paths = if playlist_paths.is_empty() {
+        playlist::find_m3u_playlists()?
+    } else {
+        playlist_paths.iter().map(path::PathBuf::from).collect()
+    };
+    let mut matcher = None;
+    let mut n_fixed = 0;
+    let mut n_broken = 0;
+    for playlist_path in playlist_paths {
+        let mut playlist = match M3u::read(&playlist_path) {
+            Ok(playlist) => playlist,
+            Err(err) => {
+                log::warn!("failed to read playlist '{}'\nreason = {}", playlist_path.display(), err);
+                continue;
+            },
+        };
+        let broken = playlist.entries(&playlist.dir())
+                .into_iter()
+                .filter(|x| !x.path.exists())
+                .collect::<Vec<_>>();
+        if broken.is_empty() {
+            log::debug!("playlist has no broken entries: {}", playlist_path.display());
+            continue;
+        }
+        // only scan the library once there is something to repair
+        let matcher = match &matcher {
+            Some(matcher) => matcher,
+            None => matcher.insert(Matcher::new(index)?),
+        };
+        let mut fixes = Vec::new();
+        for entry in &broken {
+            match matcher.find(entry) {
+                Some(file) => fixes.push((entry, file)),
+                None => log::warn!("no track found for broken entry '{}' in playlist: {}",
+                        entry.path.display(), playlist_path.display()),
+            }
+        }
+        n_broken += broken.len();
+        if fixes.is_empty() {
+            continue;
+        }
+        println!("{}", playlist_path.display());
+        for (entry, file) in &fixes {
+            println!("  {} => {}", entry.path.display(), file.display());
+        }
+        if !yes && !common::ask_confirm() {
+            continue;
+        }
+        for (entry, file) in &fixes {
+            playlist.set_entry(entry.line, file);
+        }
+        playlist.save()?;
+        n_fixed += fixes.len();
+    }
+    log::info!("fixed {} of {} broken playlist entries", n_fixed, n_broken);
+    Ok(())
+}
\ No newline at end of file
@@ -1,34 +1,52 @@
 use std::fs;
 use std::path;
 use crate::common;
+use crate::common::journal::Operation;
+use crate::common::select::Selector;
 
 use sanitise_file_name as sfn;
+#[allow(clippy::single_component_path_imports)]
 use log;
 
-pub fn run(
-    file_paths : &[String],
-    format : &str,
-    artist : bool,
-    album : bool,
-    number : bool,
-    title : bool,
-    yes : bool,
-) -> common::Result<()> {
-    common::glob_foreach_many(file_paths, |file| {
-        rename_file(file, format, artist, album, number, title, yes)
-    })
+/// How the new name of a file is built from its metadata.
+#[derive(Debug, Clone)]
+pub struct Options<'a> {
+    /// The parts of the name, in order, as described by `rename --format`.
+    pub format : &'a str,
+    pub artist : bool,
+    pub album : bool,
+    pub number : bool,
+    pub title : bool,
+    /// Replace extensions which don't match the contents of the file.
+    pub fix_extensions : bool,
 }
 
-fn rename_file(
+pub fn run(select : &Selector, options : &Options, yes : bool) -> common::Result<()> {
+    let mut index = common::index::Index::load();
+    let mut operation = Operation::new("rename");
+    let mut result = Ok(());
+    for file in select.files(&mut index)? {
+        result = rename_file(&mut index, &mut operation, &file, options, yes).map(|_| ());
+        if result.is_err() {
+            break;
+        }
+    }
+    // files which were already moved are recorded, even if a later one failed
+    operation.save()?;
+    index.save()?;
+    result
+}
+
+/// Renames a file according to the options, returning where the file is now.
+pub fn rename_file(
+    index : &mut common::index::Index,
+    operation : &mut Operation,
     file : &path::Path,
-    format : &str,
-    artist : bool,
-    album : bool,
-    number : bool,
-    title : bool,
+    options : &Options,
     yes : bool,
-) -> common::Result<()> {
-    let file_meta = common::meta::parse(file)?;
+) -> common::Result<path::PathBuf> {
+    let Options { format, artist, album, number, title, fix_extensions } = *options;
+    let file_meta = index.parse(file)?;
     log::debug!("{:?}", file_meta);
     // build new stem
     let mut new_stem = String::new();
@@ -61,31 +79,98 @@ fn rename_file(
                 if artist && !file_meta.features.is_empty() {
                     new_stem.push_str(" [feat. ");
                     new_stem.push_str(file_meta.features.join(", ").as_str());
-                    new_stem.push_str("]");
+                    new_stem.push(']');
                 }
                 first = false;
             },
+            'q' => {
+                if let Some(quality) = quality_label(&file_meta.audio) {
+                    if !new_stem.is_empty() { new_stem.push(' '); }
+                    new_stem.push_str(&quality);
+                    first = false;
+                }
+            },
             _ => (),
         }
     }
-    if let Some(ext) = file.extension().and_then(|x| x.to_str()) {
+    if let Some(ext) = file_extension(file, fix_extensions) {
         new_stem.push('.');
         new_stem.push_str(ext);
     }
     let new_stem = sfn::sanitise_with_options(&new_stem, 
         &sfn::Options { trim_more_punctuation : false, ..sfn::Options::DEFAULT }
     );
+    let new_file = file.with_file_name(new_stem);
+    if move_file(index, operation, file, &new_file, yes)? {
+        Ok(new_file)
+    } else {
+        Ok(file.to_path_buf())
+    }
+}
+
+/// Describes the quality of a track, e.g. `[mp3 320kbps]`.
+fn quality_label(audio : &common::probe::AudioProperties) -> Option<String> {
+    let codec = audio.codec.as_ref()?;
+    Some(match audio.bitrate {
+        Some(bitrate) => format!("[{} {}kbps]", codec, bitrate),
+        None => format!("[{}]", codec),
+    })
+}
+
+/// Moves a file and its companions after asking for confirmation, keeping the
+/// index up to date and recording the moves in the operation. Returns whether
+/// the file was moved.
+pub fn move_file(
+    index : &mut common::index::Index,
+    operation : &mut Operation,
+    file : &path::Path,
+    new_file : &path::Path,
+    yes : bool,
+) -> common::Result<bool> {
     // fix for windows files being case insensitive
-    let unchanged = new_stem.eq_ignore_ascii_case(file.file_name().and_then(|x| x.to_str()).unwrap());
+    let unchanged = new_file.as_os_str().eq_ignore_ascii_case(file.as_os_str());
     if unchanged {
         log::info!("file is unchanged, skipping: {}", file.display());
-    } else {
-        // confirm rename
-        let new_file = file.with_file_name(new_stem);
-        log::info!("renaming from    '{}'\n           to => '{}'", file.display(), new_file.display());
-        if yes || common::ask_confirm() {
-            fs::rename(file, new_file)?;
-        }
+        return Ok(false);
+    }
+    if new_file.exists() {
+        log::warn!("file already exists, skipping: {}", new_file.display());
+        return Ok(false);
+    }
+    // confirm rename
+    log::info!("renaming from    '{}'\n           to => '{}'", file.display(), new_file.display());
+    if !yes && !common::ask_confirm() {
+        return Ok(false);
+    }
+    if let Some(parent) = new_file.parent().filter(|x| !x.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(file, new_file)?;
+    index.rename(file, new_file);
+    operation.record_move(file, new_file);
+    common::companion::move_companions(operation, file, new_file)?;
+    Ok(true)
+}
+
+/// Chooses the extension of a renamed file. If the current extension doesn't
+/// match the contents of the file, it will be replaced when `fix` is enabled.
+fn file_extension(file : &path::Path, fix : bool) -> Option<&str> {
+    let ext = file.extension().and_then(|x| x.to_str());
+    if !fix {
+        return ext;
+    }
+    let kind = match common::index::cached_kind(file) {
+        Some(kind) => Ok(kind),
+        None => common::sniff::sniff(file),
+    };
+    let format = match kind {
+        Ok(common::sniff::Kind::Audio(format)) => format,
+        _ => return ext,
+    };
+    if ext.is_some_and(|x| format.accepts_extension(x)) {
+        return ext;
     }
-    Ok(())
+    let expected = format.extensions()[0];
+    log::info!("fixing extension of '{}' to '.{}'", file.display(), expected);
+    Some(expected)
 }
\ No newline at end of file
@@ -1,61 +1,137 @@
 use std::path;
 use std::fs;
 use std::env;
-use std::collections::{ HashMap, HashSet };
+use std::collections::{ BTreeMap, BTreeSet, HashMap, HashSet };
 use crate::common;
+use crate::common::journal::Operation;
+use crate::common::select::Selector;
 
 pub fn run(
-    file_paths : &[String],
+    select : &Selector,
     _clean_dirs : bool,
     _clean_files : bool,
     yes : bool,
 ) -> common::Result<()> {
-    sort_files(file_paths, yes)?;
+    sort_files(select, yes)?;
     Ok(())
 }
 
-fn sort_files(file_paths : &[String], yes : bool) -> common::Result<()> {
+fn sort_files(select : &Selector, yes : bool) -> common::Result<()> {
+    let mut index = common::index::Index::load();
+    let files = select.files(&mut index)?;
+    let working_dir = env::current_dir().and_then(fs::canonicalize)?;
+    let mut operation = Operation::new("sort");
+    let result = sort_paths(&mut index, &mut operation, &files, &working_dir, yes);
+    // files which were already moved are recorded, even if a later one failed
+    operation.save()?;
+    if result.is_ok() && writes_folder_images() {
+        write_folder_images(&mut index, &operation)?;
+    }
+    index.save()?;
+    result
+}
+
+/// Moves files to where they belong in the library, recording the moves in
+/// the operation. Directories holding an album whose tracks agree on its
+/// author are moved as a whole, except for those containing `keep_dir`,
+/// which has to be canonical.
+pub fn sort_paths(
+    index : &mut common::index::Index,
+    operation : &mut Operation,
+    files : &[path::PathBuf],
+    keep_dir : &path::Path,
+    yes : bool,
+) -> common::Result<()> {
     let mut collection_authors = HashMap::new();
     let mut file_meta_map = HashMap::new();
     let mut db = common::infer::Database::new();
-    common::glob_foreach_many(file_paths, |file| {
-        let file_meta = common::meta::parse(file)?;
+    for file in files {
+        let file = file.as_path();
+        let entry = index.get(file)?;
+        let file_meta = entry.meta.clone();
+        let collection_author = entry.collection_author.clone();
         let file_location = if let Some(x) = db.add_file(file) { x } else {
             log::warn!("failed to load file, skipping: {}", file.display());
-            return Ok(());
+            continue;
         };
         // register the authors of a collection
-        if let Some(album) = &file_meta.album {
-            if let Some(album_expect) = file.parent().and_then(|x| x.file_name()) {
-                if album_expect.eq_ignore_ascii_case(album) {
-                    if let Some(author) = &file_meta.album_author {
-                        let authors = collection_authors
-                                .entry(file_location.id_collection)
-                                .or_insert_with(|| HashSet::new());
-                        authors.insert(author.to_string());
-                    }
-                }
-            }
+        if let Some(author) = collection_author {
+            collection_authors
+                    .entry(file_location.id_collection)
+                    .or_insert_with(HashSet::new)
+                    .insert(author);
         }
         // keep track of file metadata
         file_meta_map.insert(file_location.id, file_meta);
-        Ok(())
-    })?;
+    }
     let (mut collections, files) = db.complete();
     collections.retain(|x| x.has_files);
     collections.sort_by_key(|x| x.depth);
+    let working_dir = env::current_dir().and_then(fs::canonicalize)?;
+    move_files(index, operation, &working_dir, keep_dir, &collections, &files,
+            &collection_authors, &file_meta_map, yes)
+}
+
+/// Returns whether albums which are moved should get a folder image, which
+/// can be disabled with `art.on-sort = false`.
+pub fn writes_folder_images() -> bool {
+    common::find_config_value("art.on-sort").is_none_or(|x| x.as_bool() != Some(false))
+}
+
+/// Writes the cover art of albums which were moved into their new directory,
+/// so that players can find it.
+pub fn write_folder_images(index : &mut common::index::Index, operation : &Operation) -> common::Result<()> {
+    let square = common::find_config_value("art.square").and_then(|x| x.as_bool()).unwrap_or(false);
+    let ffmpeg_path = if square { common::find_ffmpeg_path() } else { None };
+    let mut album_dirs = BTreeSet::new();
+    for move_ in &operation.moves {
+        if move_.to.is_dir() {
+            album_dirs.insert(move_.to.clone());
+        } else if common::is_audio_file(&move_.to)
+                // companion files, like lyrics and covers, don't belong in the index
+                && index.parse(&move_.to).is_ok_and(|x| x.album.is_some()) {
+            album_dirs.extend(move_.to.parent().map(path::Path::to_path_buf));
+        }
+    }
+    for dir in album_dirs {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let file = entry?.path();
+            if file.is_file() && common::is_audio_file(&file) {
+                files.push(file);
+            }
+        }
+        files.sort();
+        if let Err(err) = common::art::write_folder_image(&dir, &files, ffmpeg_path.as_deref()) {
+            log::warn!("failed to write folder image for '{}'\nreason = {}", dir.display(), err);
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn move_files(
+    index : &mut common::index::Index,
+    operation : &mut Operation,
+    working_dir : &path::Path,
+    keep_dir : &path::Path,
+    collections : &[common::infer::Collection],
+    files : &[common::infer::File],
+    collection_authors : &HashMap<common::infer::CollectionID, HashSet<String>>,
+    file_meta_map : &HashMap<common::infer::FileID, common::meta::TrackMeta>,
+    yes : bool,
+) -> common::Result<()> {
     let mut collection_moved = HashSet::new();
-    let working_dir = env::current_dir().and_then(|x| fs::canonicalize(x))?;
     // move entire collections
-    for collection in &collections {
+    for collection in collections {
         if let Some(id_parent) = &collection.id_parent {
             if collection_moved.contains(id_parent) {
                 collection_moved.insert(collection.id);
                 continue; // file has already been moved
             }
         }
-        if working_dir.starts_with(collection.path.as_path()) {
-            continue; // don't rename paths that contain the working directory
+        if working_dir.starts_with(collection.path.as_path()) || keep_dir.starts_with(collection.path.as_path()) {
+            continue; // don't rename paths that contain the working or kept directory
         }
         let author = if let Some(authors) = collection_authors.get(&collection.id) {
             if authors.len() == 1 {
@@ -71,7 +147,7 @@ fn sort_files(file_paths : &[String], yes : bool) -> common::Result<()> {
         dest_path.push(common::meta::get_category_name(author));
         dest_path.push(author);
         dest_path.push(collection_name);
-        let src_path = get_rel_path(&working_dir, &collection.path);
+        let src_path = get_rel_path(working_dir, &collection.path);
         // confirm rename
         let unchanged = dest_path.as_os_str().eq_ignore_ascii_case(src_path.as_os_str());
         if unchanged {
@@ -81,32 +157,23 @@ fn sort_files(file_paths : &[String], yes : bool) -> common::Result<()> {
                     src_path.display(), dest_path.display());
             if yes || common::ask_confirm() {
                 fs::create_dir_all(dest_path.parent().unwrap())?;
-                fs::rename(src_path, dest_path)?;
+                fs::rename(src_path, &dest_path)?;
+                index.rename(src_path, &dest_path);
+                operation.record_move(src_path, &dest_path);
                 collection_moved.insert(collection.id);
             }
         }
     }
-    // move individual files
-    for file in &files {
+    // move individual files, remembering where the tracks of each directory go
+    let mut dir_moves : BTreeMap<path::PathBuf, HashSet<path::PathBuf>> = BTreeMap::new();
+    for file in files {
         if collection_moved.contains(&file.id_collection) {
             continue; // file has already been moved
         }
         let file_meta = &file_meta_map[&file.id];
-        // add author
-        let mut dest_path = path::PathBuf::new();
-        if let Some(author) = file_meta.get_author() {
-            dest_path.push(common::meta::get_category_name(author));
-            dest_path.push(author);
-        } else {
-            dest_path.push(common::meta::DEFAULT_CATEGORY);
-            dest_path.push(".unknown");
-        }
-        // add album
-        if let Some(album) = &file_meta.album {
-            dest_path.push(album);
-        }
+        let mut dest_path = common::meta::get_sort_dir(file_meta);
         dest_path.push(file.path.file_name().unwrap());
-        let src_path = get_rel_path(&working_dir, &file.path);
+        let src_path = get_rel_path(working_dir, &file.path);
         // confirm rename
         let unchanged = dest_path.as_os_str().eq_ignore_ascii_case(src_path.as_os_str());
         if unchanged {
@@ -116,13 +183,26 @@ fn sort_files(file_paths : &[String], yes : bool) -> common::Result<()> {
                     src_path.display(), dest_path.display());
             if yes || common::ask_confirm() {
                 fs::create_dir_all(dest_path.parent().unwrap())?;
-                fs::rename(src_path, dest_path)?;
+                fs::rename(src_path, &dest_path)?;
+                index.rename(src_path, &dest_path);
+                operation.record_move(src_path, &dest_path);
+                common::companion::move_companions(operation, src_path, &dest_path)?;
+                if let (Some(src_dir), Some(dest_dir)) = (src_path.parent(), dest_path.parent()) {
+                    dir_moves.entry(src_dir.to_path_buf()).or_default().insert(dest_dir.to_path_buf());
+                }
             }
         }
     }
+    // album files follow their tracks, as long as they all went to one place
+    for (src_dir, dest_dirs) in &dir_moves {
+        if dest_dirs.len() == 1 {
+            let dest_dir = dest_dirs.iter().next().unwrap();
+            common::companion::move_album_files(operation, src_dir, dest_dir)?;
+        }
+    }
     Ok(())
 }
 
 fn get_rel_path<'a>(cwd : &path::Path, file : &'a path::Path) -> &'a path::Path {
-    file.strip_prefix(cwd).unwrap_or(&file)
+    file.strip_prefix(cwd).unwrap_or(file)
 }
\ No newline at end of file
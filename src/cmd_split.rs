@@ -0,0 +1,145 @@
+use crate::common;
+use crate::common::select::Selector;
+use crate::common::source;
+use crate::common::split;
+
+use std::fs;
+use std::path;
+use std::process;
+
+use sanitise_file_name as sfn;
+
+pub fn run(select : &Selector, tracklist_path : Option<&str>, yes : bool) -> common::Result<()> {
+    let ffmpeg_path = if let Some(x) = common::find_ffmpeg_path() { x } else {
+        log::error!("an executable to `ffmpeg` is required for this command, aborting");
+        log::info!("make sure `ffmpeg` is in your PATH\n\
+                    alternatively, add `ffmpeg = <path>` to your `catty.toml`");
+        return Ok(());
+    };
+    let mut index = common::index::Index::load();
+    let files = select.files(&mut index)?;
+    if tracklist_path.is_some() && files.len() > 1 {
+        return Err(format!("a tracklist can only be used to split a single file, \
+                but {} files were selected", files.len()).into());
+    }
+    for file in files {
+        let tracklist = match tracklist_path {
+            Some(tracklist_path) => Some(read_tracklist(path::Path::new(tracklist_path))?),
+            None => None,
+        };
+        split_file(&ffmpeg_path, &file, tracklist, yes)?;
+    }
+    index.save()
+}
+
+/// Reads a tracklist from a file, which is either a `.cue` sheet or a list of
+/// timestamps.
+fn read_tracklist(tracklist_path : &path::Path) -> common::Result<split::Tracklist> {
+    let source = fs::read_to_string(tracklist_path)?;
+    let is_cue = tracklist_path.extension()
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| x.eq_ignore_ascii_case("cue"));
+    Ok(if is_cue { split::parse_cue(&source) } else { split::parse_description(&source) })
+}
+
+/// Splits a single audio file into separate tracks inside of an album folder
+/// next to the original file. If no tracklist is given, one is searched for
+/// using `split::find_tracklist`.
+pub fn split_file(
+    ffmpeg_path : &path::Path,
+    file : &path::Path,
+    tracklist : Option<split::Tracklist>,
+    yes : bool,
+) -> common::Result<()> {
+    let tracklist = tracklist.or_else(|| split::find_tracklist(ffmpeg_path, file));
+    let tracklist = match tracklist {
+        Some(x) if x.chapters.len() > 1 => x,
+        _ => {
+            log::info!("no tracklist found, skipping: {}", file.display());
+            return Ok(());
+        },
+    };
+    let file_meta = common::meta::parse(file)?;
+    let album = tracklist.album.as_deref()
+            .or(file_meta.title.as_deref())
+            .unwrap_or(common::meta::DEFAULT_TITLE)
+            .to_string();
+    let album_author = tracklist.album_author.as_deref()
+            .or(file_meta.get_author())
+            .map(String::from);
+    let ext = file.extension().and_then(|x| x.to_str()).unwrap_or("");
+    let album_dir = file.with_file_name(sanitise(&album));
+    log::info!("splitting '{}' into {} tracks\n         to => '{}'",
+            file.display(), tracklist.chapters.len(), album_dir.display());
+    if !(yes || common::ask_confirm()) {
+        return Ok(());
+    }
+    fs::create_dir_all(&album_dir)?;
+    let track_count = tracklist.chapters.len();
+    let mut track_files = Vec::new();
+    for (i, chapter) in tracklist.chapters.iter().enumerate() {
+        let track_number = i + 1;
+        let title = if chapter.title.is_empty() {
+            format!("Track {}", track_number)
+        } else {
+            chapter.title.clone()
+        };
+        let mut track_name = format!("{:0>2} - {}", track_number, title);
+        if !ext.is_empty() {
+            track_name.push('.');
+            track_name.push_str(ext);
+        }
+        let track_file = album_dir.join(sanitise(&track_name));
+        let mut proc = process::Command::new(ffmpeg_path);
+        proc.args(["-v", "error", "-y"]);
+        proc.args(["-ss", format!("{:.3}", chapter.start).as_str()]);
+        if let Some(end) = chapter.end {
+            proc.args(["-t", format!("{:.3}", end - chapter.start).as_str()]);
+        }
+        proc.arg("-i");
+        proc.arg(file);
+        // keep the cover art, but not the chapters of the original file
+        proc.args(["-map", "0:a", "-map", "0:v?", "-c", "copy", "-map_chapters", "-1"]);
+        proc.args(["-metadata", format!("title={}", title).as_str()]);
+        proc.args(["-metadata", format!("album={}", album).as_str()]);
+        proc.args(["-metadata", format!("track={}/{}", track_number, track_count).as_str()]);
+        if let Some(author) = &album_author {
+            proc.args(["-metadata", format!("album_artist={}", author).as_str()]);
+        }
+        if let Some(artist) = chapter.artist.as_ref().or(album_author.as_ref()) {
+            proc.args(["-metadata", format!("artist={}", artist).as_str()]);
+        }
+        proc.arg(&track_file);
+        proc.stdin(process::Stdio::null());
+        log::debug!("running process with args: {:?}", proc.get_args());
+        let output = proc.output()?;
+        if !output.status.success() {
+            return Err(format!("failed to split track {} of '{}'\n{}", track_number,
+                    file.display(), String::from_utf8_lossy(&output.stderr)).into());
+        }
+        log::info!("created track [{} / {}]: {}", track_number, track_count, track_file.display());
+        track_files.push(track_file);
+    }
+    // the tracks were downloaded from the same place as the original
+    if let Some(file_source) = source::read(file).or_else(|| source::find_in_log(file)) {
+        for track_file in &track_files {
+            source::record(track_file, &file_source)?;
+        }
+    }
+    log::info!("removing original file: {}", file.display());
+    if yes || common::ask_confirm() {
+        fs::remove_file(file)?;
+        // keep the description of the upload with the tracks
+        let description = file.with_extension("description");
+        if let Some(file_name) = description.file_name().filter(|_| description.is_file()) {
+            fs::rename(&description, album_dir.join(file_name))?;
+        }
+    }
+    Ok(())
+}
+
+fn sanitise(name : &str) -> String {
+    sfn::sanitise_with_options(name,
+        &sfn::Options { trim_more_punctuation : false, ..sfn::Options::DEFAULT }
+    )
+}
\ No newline at end of file
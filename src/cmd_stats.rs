@@ -0,0 +1,224 @@
+use std::collections::{ BTreeMap, HashMap, HashSet };
+use crate::common;
+use crate::common::meta;
+use crate::common::sniff;
+
+/// Labels of the bitrate ranges counted for each codec, in kbit/s.
+const BITRATE_RANGES : &[&str] = &["<128", "128-191", "192-255", "256-319", "320+", "lossless", "unknown"];
+
+/// Returns which of the `BITRATE_RANGES` a track belongs to.
+fn bitrate_range(bitrate : Option<u32>, is_lossless : bool) -> usize {
+    match bitrate {
+        _ if is_lossless => 5,
+        Some(0..128) => 0,
+        Some(128..192) => 1,
+        Some(192..256) => 2,
+        Some(256..320) => 3,
+        Some(_) => 4,
+        None => 6,
+    }
+}
+
+/// Totals for a single category bucket, e.g. `A-F`.
+#[derive(Debug, Default)]
+struct Bucket {
+    tracks : usize,
+    albums : HashSet<(String, String)>,
+    artists : HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    tracks : usize,
+    size : u64,
+    albums : HashSet<(String, String)>,
+    artists : HashMap<String, (String, usize)>,
+    buckets : BTreeMap<String, Bucket>,
+    codecs : BTreeMap<String, usize>,
+    /// How many tracks of each codec fall into each of the `BITRATE_RANGES`.
+    bitrates : BTreeMap<String, [usize; BITRATE_RANGES.len()]>,
+    sample_rates : BTreeMap<u32, usize>,
+    duration : f64,
+    bitrate_total : (u64, usize),
+    other : usize,
+    unknown : usize,
+    missing : BTreeMap<&'static str, usize>,
+}
+
+impl Stats {
+    fn add(&mut self, file_meta : &meta::TrackMeta, has_source : bool, codec : &str, is_lossless : bool, size : u64) {
+        self.tracks += 1;
+        self.size += size;
+        *self.codecs.entry(codec.to_string()).or_default() += 1;
+        let audio = &file_meta.audio;
+        self.bitrates.entry(codec.to_string()).or_default()[bitrate_range(audio.bitrate, is_lossless)] += 1;
+        if let Some(sample_rate) = audio.sample_rate {
+            *self.sample_rates.entry(sample_rate).or_default() += 1;
+        }
+        self.duration += audio.duration.unwrap_or_default();
+        if let Some(bitrate) = audio.bitrate {
+            self.bitrate_total.0 += bitrate as u64;
+            self.bitrate_total.1 += 1;
+        }
+        let author = file_meta.get_author();
+        let category = author.map_or(meta::DEFAULT_CATEGORY, meta::get_category_name);
+        let bucket = self.buckets.entry(category.to_string()).or_default();
+        bucket.tracks += 1;
+        if author.is_none() {
+            self.unknown += 1;
+        } else if category == meta::DEFAULT_CATEGORY {
+            self.other += 1;
+        }
+        if let Some(album) = &file_meta.album {
+            let key = (author.unwrap_or_default().to_lowercase(), album.to_lowercase());
+            bucket.albums.insert(key.clone());
+            self.albums.insert(key);
+        }
+        for artist in &file_meta.artists {
+            bucket.artists.insert(artist.to_lowercase());
+            let count = self.artists.entry(artist.to_lowercase())
+                    .or_insert_with(|| (artist.clone(), 0));
+            count.1 += 1;
+        }
+        let missing = [
+            ("artist", file_meta.artists.is_empty()),
+            ("title", file_meta.title.is_none()),
+            ("album", file_meta.album.is_none()),
+            ("track", file_meta.track_number.is_none()),
+            ("year", file_meta.year.is_none()),
+            ("source", !has_source),
+        ];
+        for (field, is_missing) in missing {
+            if is_missing {
+                *self.missing.entry(field).or_default() += 1;
+            }
+        }
+    }
+
+    /// Returns the mean bitrate of the tracks whose bitrate is known, in kbit/s.
+    fn average_bitrate(&self) -> Option<u64> {
+        let (total, count) = self.bitrate_total;
+        if count == 0 { None } else { Some(total / count as u64) }
+    }
+
+    /// Returns the artists with the most tracks, breaking ties by name.
+    fn top_artists(&self, count : usize) -> Vec<(&str, usize)> {
+        let mut artists = self.artists.values()
+                .map(|(name, tracks)| (name.as_str(), *tracks))
+                .collect::<Vec<_>>();
+        artists.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.to_lowercase().cmp(&b.0.to_lowercase())));
+        artists.truncate(count);
+        artists
+    }
+
+    fn to_json(&self, top : usize) -> serde_json::Value {
+        let buckets = self.buckets.iter()
+                .map(|(name, bucket)| (name.clone(), serde_json::json!({
+                    "tracks" : bucket.tracks,
+                    "albums" : bucket.albums.len(),
+                    "artists" : bucket.artists.len(),
+                })))
+                .collect::<serde_json::Map<_, _>>();
+        let bitrates = self.bitrates.iter()
+                .map(|(codec, counts)| (codec.clone(), BITRATE_RANGES.iter()
+                        .zip(counts)
+                        .map(|(range, count)| (range.to_string(), serde_json::json!(count)))
+                        .collect::<serde_json::Map<_, _>>().into()))
+                .collect::<serde_json::Map<_, _>>();
+        let top_artists = self.top_artists(top).iter()
+                .map(|(name, tracks)| serde_json::json!({ "artist" : name, "tracks" : tracks }))
+                .collect::<Vec<_>>();
+        serde_json::json!({
+            "tracks" : self.tracks,
+            "albums" : self.albums.len(),
+            "artists" : self.artists.len(),
+            "size_bytes" : self.size,
+            "duration_seconds" : self.duration.round() as u64,
+            "average_bitrate" : self.average_bitrate(),
+            "sample_rates" : self.sample_rates.iter()
+                    .map(|(rate, count)| (rate.to_string(), serde_json::json!(count)))
+                    .collect::<serde_json::Map<_, _>>(),
+            "categories" : buckets,
+            "codecs" : self.codecs,
+            "bitrates" : bitrates,
+            "top_artists" : top_artists,
+            "other" : self.other,
+            "unknown" : self.unknown,
+            "missing" : self.missing,
+        })
+    }
+
+    fn print(&self, top : usize) {
+        println!("tracks        {}", self.tracks);
+        println!("albums        {}", self.albums.len());
+        println!("artists       {}", self.artists.len());
+        println!("size          {}", common::format_size(self.size));
+        println!("duration      {}", common::format_duration(self.duration));
+        println!("bitrate       {}", self.average_bitrate().map_or("?".to_string(), |x| format!("{} kbps average", x)));
+        println!("unsorted      {} in {}, {} in .unknown", self.other, meta::DEFAULT_CATEGORY, self.unknown);
+        println!();
+        println!("{:<12}{:>8}{:>8}{:>8}", "category", "tracks", "albums", "artists");
+        for (name, bucket) in &self.buckets {
+            println!("{:<12}{:>8}{:>8}{:>8}", name, bucket.tracks, bucket.albums.len(), bucket.artists.len());
+        }
+        println!();
+        print!("{:<12}{:>8}", "codec", "tracks");
+        for range in BITRATE_RANGES {
+            print!("{:>10}", range);
+        }
+        println!();
+        for (codec, count) in &self.codecs {
+            print!("{:<12}{:>8}", codec, count);
+            for range_count in &self.bitrates[codec] {
+                print!("{:>10}", range_count);
+            }
+            println!();
+        }
+        println!();
+        println!("{:<12}{:>8}", "sample rate", "tracks");
+        for (sample_rate, count) in &self.sample_rates {
+            println!("{:<12}{:>8}", sample_rate, count);
+        }
+        println!();
+        println!("{:<12}{:>8}", "missing", "tracks");
+        for (field, count) in &self.missing {
+            println!("{:<12}{:>8}", field, count);
+        }
+        println!();
+        println!("top artists");
+        for (name, tracks) in self.top_artists(top) {
+            println!("{:>6}  {}", tracks, name);
+        }
+    }
+}
+
+pub fn run(top : usize, json : bool) -> common::Result<()> {
+    let mut index = common::index::Index::load();
+    let mut stats = Stats::default();
+    common::library_foreach(|file| {
+        let entry = match index.get(file) {
+            Ok(entry) => entry,
+            Err(err) => {
+                log::warn!("failed to read file '{}'\nreason = {}", file.display(), err);
+                return Ok(());
+            },
+        };
+        // the container is the best guess at the codec if it wasn't probed
+        let codec = match (&entry.meta.audio.codec, entry.kind) {
+            (Some(codec), _) => codec.as_str(),
+            (None, sniff::Kind::Audio(format)) => format.name(),
+            _ => "unknown",
+        };
+        let is_lossless = entry.meta.audio.is_lossless()
+                || matches!(entry.kind, sniff::Kind::Audio(format) if format.is_lossless());
+        stats.add(&entry.meta, entry.source.is_some(), codec, is_lossless, entry.stat.size);
+        Ok(())
+    })?;
+    index.save()?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats.to_json(top))?);
+    } else {
+        stats.print(top);
+    }
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,127 @@
+use crate::common;
+use crate::cmd_add;
+
+use std::env;
+use std::fs;
+
+const ARCHIVE_NAME : &str = "archive.txt";
+
+/// A channel, playlist or label followed by the library, declared in
+/// `catty.toml` as a `[[subscription]]` table.
+#[derive(Debug)]
+struct Subscription {
+    name : String,
+    uri : String,
+    is_playlist : bool,
+    split : bool,
+    destination : Option<String>,
+    title : Option<String>,
+    min_duration : Option<i64>,
+    max_duration : Option<i64>,
+}
+
+impl Subscription {
+    fn from_table(table : &toml::Table) -> common::Result<Self> {
+        let uri = table.get("uri").and_then(|x| x.as_str())
+                .ok_or("subscription is missing a `uri` field")?
+                .to_string();
+        let name = table.get("name").and_then(|x| x.as_str())
+                .unwrap_or(&uri)
+                .to_string();
+        let title = table.get("title").and_then(|x| x.as_str()).map(String::from);
+        if let Some(pattern) = &title {
+            if let Err(err) = regex::Regex::new(pattern) {
+                return Err(format!("invalid title filter for subscription '{}'\n{}",
+                        name, err).into());
+            }
+        }
+        Ok(Self {
+            is_playlist : table.get("playlist").and_then(|x| x.as_bool()).unwrap_or(false),
+            split : table.get("split").and_then(|x| x.as_bool()).unwrap_or(false),
+            destination : table.get("destination").and_then(|x| x.as_str()).map(String::from),
+            min_duration : table.get("min-duration").and_then(|x| x.as_integer()),
+            max_duration : table.get("max-duration").and_then(|x| x.as_integer()),
+            name,
+            uri,
+            title,
+        })
+    }
+
+    /// Builds a `yt-dlp` match filter from the subscription filters.
+    fn match_filter(&self) -> Option<String> {
+        let mut filters = Vec::new();
+        if let Some(pattern) = &self.title {
+            // `&` separates conditions, so it has to be escaped inside of values
+            filters.push(format!("title~='{}'", pattern.replace('\'', "\\'").replace('&', "\\&")));
+        }
+        if let Some(duration) = self.min_duration {
+            filters.push(format!("duration>={}", duration));
+        }
+        if let Some(duration) = self.max_duration {
+            filters.push(format!("duration<={}", duration));
+        }
+        if filters.is_empty() { None } else { Some(filters.join(" & ")) }
+    }
+}
+
+fn find_subscriptions() -> common::Result<Vec<Subscription>> {
+    let mut subscriptions = Vec::new();
+    if let Some(value) = common::find_config_value("subscription") {
+        let tables = value.as_array().ok_or("expected `subscription` to be an array of tables")?;
+        for table in tables {
+            let table = table.as_table().ok_or("expected `subscription` to be a table")?;
+            subscriptions.push(Subscription::from_table(table)?);
+        }
+    }
+    Ok(subscriptions)
+}
+
+pub fn run(names : &[String], yes : bool) -> common::Result<()> {
+    let mut subscriptions = find_subscriptions()?;
+    if subscriptions.is_empty() {
+        log::warn!("no subscriptions found, add a `[[subscription]]` table to your `catty.toml`");
+        return Ok(());
+    }
+    if !names.is_empty() {
+        subscriptions.retain(|x| names.iter().any(|name| name.eq_ignore_ascii_case(&x.name)));
+        if subscriptions.is_empty() {
+            log::warn!("no subscriptions matched the names: {:?}", names);
+            return Ok(());
+        }
+    }
+    if let Some(ytdlp_path) = common::find_ytdlp_path() {
+        let policy = cmd_add::CodecPolicy::from_config()?;
+        fs::create_dir_all(common::DATA_DIR)?;
+        let archive_path = env::current_dir()?.join(common::DATA_DIR).join(ARCHIVE_NAME);
+        log::info!("syncing subscriptions using installation: {}", ytdlp_path.display());
+        let subscriptions_n = subscriptions.len();
+        for (i, subscription) in subscriptions.iter().enumerate() {
+            log::info!("syncing [{} / {}] {}", i + 1, subscriptions_n, subscription.name);
+            let mut proc = cmd_add::fetch_command(&ytdlp_path, &policy,
+                    &subscription.uri, subscription.is_playlist);
+            // only fetch items that haven't been downloaded before
+            proc.arg("--download-archive");
+            proc.arg(&archive_path);
+            if let Some(filter) = subscription.match_filter() {
+                proc.args(["--match-filter", filter.as_str()]);
+            }
+            if let Some(destination) = &subscription.destination {
+                proc.args(["--paths", destination.as_str()]);
+            }
+            if subscription.split {
+                cmd_add::add_split_args(&mut proc);
+            }
+            let files = cmd_add::run_command(proc)?;
+            log::info!("downloaded {} new files", files.len());
+            cmd_add::record_sources(&files)?;
+            if subscription.split {
+                cmd_add::split_files(&files, yes)?;
+            }
+        }
+    } else {
+        log::error!("an executable to `yt-dlp` is required for this command, aborting");
+        log::info!("make sure `yt-dlp` or `youtube-dl` is in your PATH\n\
+                    alternatively, add `yt-dlp = <path>` to your `catty.toml`");
+    }
+    Ok(())
+}
\ No newline at end of file
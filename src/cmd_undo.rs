@@ -0,0 +1,124 @@
+use std::fs;
+use std::path;
+use std::collections::BTreeMap;
+use crate::common;
+use crate::common::journal;
+use crate::common::playlist;
+use crate::common::tags;
+
+pub fn run(yes : bool) -> common::Result<()> {
+    let operation = match journal::read()?.pop() {
+        Some(operation) => operation,
+        None => {
+            log::warn!("there is nothing to undo");
+            return Ok(());
+        },
+    };
+    log::info!("undoing `{}`, which moved {} files and changed {} tags",
+            operation.command, operation.moves.len(), operation.retags.len());
+    for move_ in operation.moves.iter().rev() {
+        println!("{} => {}", move_.to.display(), move_.from.display());
+    }
+    for retag in operation.retags.iter().rev() {
+        println!("{} {}: {} => {}",
+                retag.file.display(),
+                retag.field.name(),
+                retag.new.as_deref().unwrap_or("-"),
+                retag.old.as_deref().unwrap_or("-"));
+    }
+    if !yes && !common::ask_confirm() {
+        return Ok(());
+    }
+    let mut index = common::index::Index::load();
+    let mut undone = Vec::new();
+    let mut failed = None;
+    for (i, move_) in operation.moves.iter().enumerate().rev() {
+        match undo_move(&mut index, move_) {
+            Ok(Some(reverse)) => undone.push(reverse),
+            Ok(None) => {},
+            Err(err) => {
+                failed = Some((i, err));
+                break;
+            },
+        }
+    }
+    // the moves which weren't undone stay in the journal, so that undo can
+    // be run again once the problem is fixed
+    journal::pop()?;
+    if let Some((i, _)) = &failed {
+        let mut rest = journal::Operation::new(&operation.command);
+        rest.moves = operation.moves[..=*i].to_vec();
+        rest.retags = operation.retags.clone();
+        rest.append()?;
+    }
+    if let Err(err) = playlist::follow_moves(&undone) {
+        log::warn!("failed to update playlists\nreason = {}", err);
+    }
+    if let Some((i, err)) = failed {
+        index.save()?;
+        let move_ = &operation.moves[i];
+        return Err(format!("failed to move '{}' back to '{}', {} files are left to undo\nreason = {}",
+                move_.to.display(), move_.from.display(), i + 1, err).into());
+    }
+    // tags were recorded at the paths the files had before they were moved
+    let n_restored = restore_tags(&operation.retags);
+    index.save()?;
+    log::info!("moved {} of {} files back", undone.len(), operation.moves.len());
+    if !operation.retags.is_empty() {
+        log::info!("restored the tags of {} files", n_restored);
+    }
+    Ok(())
+}
+
+/// Moves a file back to where it was, returning the move which was made, or
+/// `None` if it was skipped.
+fn undo_move(index : &mut common::index::Index, move_ : &journal::Move) -> common::Result<Option<journal::Move>> {
+    let reverse = move_.reverse();
+    if !reverse.from.exists() {
+        log::warn!("file no longer exists, skipping: {}", reverse.from.display());
+        return Ok(None);
+    }
+    if reverse.to.exists() {
+        log::warn!("file already exists, skipping: {}", reverse.to.display());
+        return Ok(None);
+    }
+    if let Some(parent) = reverse.to.parent().filter(|x| !x.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(&reverse.from, &reverse.to)?;
+    index.rename(&reverse.from, &reverse.to);
+    remove_empty_dirs(&reverse.from);
+    Ok(Some(reverse))
+}
+
+/// Writes back the old value of each changed tag, returning the number of
+/// files which were restored.
+fn restore_tags(retags : &[journal::Retag]) -> usize {
+    let mut files = BTreeMap::<_, Vec<_>>::new();
+    for retag in retags.iter().rev() {
+        files.entry(&retag.file).or_default().push(tags::Change {
+            field : retag.field,
+            old : retag.new.clone(),
+            new : retag.old.clone(),
+        });
+    }
+    let mut n_restored = 0;
+    for (file, changes) in files {
+        let result = tags::read_tag(file).and_then(|mut tag| tags::apply_changes(tag.as_mut(), file, &changes));
+        match result {
+            Ok(()) => n_restored += 1,
+            Err(err) => log::warn!("failed to restore tags of '{}'\nreason = {}", file.display(), err),
+        }
+    }
+    n_restored
+}
+
+/// Removes the directories which were only created to hold a moved file.
+fn remove_empty_dirs(file_path : &path::Path) {
+    for dir in file_path.ancestors().skip(1) {
+        if dir.as_os_str().is_empty() || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        log::debug!("removed empty directory: {}", dir.display());
+    }
+}
\ No newline at end of file
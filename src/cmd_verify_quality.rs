@@ -0,0 +1,80 @@
+use crate::common;
+use crate::common::select::Selector;
+use crate::common::spectrum::{ self, Verdict };
+
+/// Analyses the spectrum of every lossless file, printing each result. Returns
+/// the number of files which were likely transcoded from lossy audio, so that
+/// the exit code can reflect it.
+pub fn run(select : &Selector, force : bool, json : bool) -> common::Result<usize> {
+    let mut index = common::index::Index::load();
+    let mut files = select.files_or_library(&mut index)?;
+    files.sort();
+    let mut lossless = Vec::new();
+    for file in files {
+        match index.get(&file) {
+            Ok(entry) if entry.meta.audio.is_lossless() => lossless.push((file, entry.meta.audio.sample_rate)),
+            Ok(_) => (),
+            Err(err) => log::warn!("failed to read file '{}'\nreason = {}", file.display(), err),
+        }
+    }
+    if lossless.is_empty() {
+        index.save()?;
+        log::info!("no lossless files to verify");
+        return Ok(0);
+    }
+    let ffmpeg_path = common::find_ffmpeg_path()
+            .ok_or("an executable to `ffmpeg` is required to analyse audio, \
+                    add `ffmpeg = <path>` to your `catty.toml`")?;
+    let mut cache = spectrum::Cache::load();
+    let mut results = Vec::new();
+    for (n, (file, sample_rate)) in lossless.iter().enumerate() {
+        let sample_rate = if let Some(x) = sample_rate { *x } else {
+            log::warn!("sample rate is unknown, skipping: {}", file.display());
+            continue;
+        };
+        match cache.get_or_analyse(&mut index, &ffmpeg_path, file, sample_rate, force) {
+            Ok((analysis, is_new)) => {
+                if is_new {
+                    log::info!("analysed [{} / {}] {}", n + 1, lossless.len(), file.display());
+                }
+                results.push((file, analysis));
+            },
+            Err(err) => log::warn!("failed to analyse file '{}'\nreason = {}", file.display(), err),
+        }
+    }
+    cache.save()?;
+    index.save()?;
+    if json {
+        let report = results.iter()
+                .map(|(file, analysis)| serde_json::json!({
+                    "path" : file.to_string_lossy(),
+                    "cutoff" : analysis.cutoff.round(),
+                    "sample_rate" : analysis.sample_rate,
+                    "lossy" : analysis.is_lossy(),
+                    "upsampled" : analysis.verdict() == Verdict::Upsampled,
+                    "likely_source" : if analysis.is_lossy() { Some(analysis.likely_source()) } else { None },
+                }))
+                .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for (file, analysis) in &results {
+            println!("{:<10}{:>9}  {}",
+                    analysis.verdict().name(),
+                    format!("{:.1} kHz", analysis.cutoff / 1000.0),
+                    file.display());
+        }
+    }
+    let n_lossy = results.iter().filter(|x| x.1.is_lossy()).count();
+    let n_upsampled = results.iter().filter(|x| x.1.verdict() == Verdict::Upsampled).count();
+    if n_lossy > 0 {
+        log::warn!("{} of {} lossless files were likely transcoded from lossy audio", n_lossy, results.len());
+    }
+    // upsampled audio is still lossless, so it isn't counted as a fake
+    if n_upsampled > 0 {
+        log::info!("{} of {} lossless files were likely upsampled from a lower sample rate", n_upsampled, results.len());
+    }
+    if n_lossy == 0 && n_upsampled == 0 {
+        log::info!("all {} lossless files have a full spectrum", results.len());
+    }
+    Ok(n_lossy)
+}
\ No newline at end of file
@@ -0,0 +1,312 @@
+use std::env;
+use std::fs;
+use std::path;
+use std::time;
+use std::collections::HashMap;
+use crate::cmd_rename;
+use crate::cmd_sort;
+use crate::common;
+use crate::common::index::FileStat;
+use crate::common::journal::{ self, Operation };
+use crate::common::meta::TrackMeta;
+use crate::common::tags::{ self, Change, Field };
+use crate::common::watch::{ self, Watcher };
+
+/// Extensions of the files that downloaders write into, before renaming them
+/// once the download has finished.
+const PARTIAL_EXTENSIONS : &[&str] = &[
+    "part", "ytdl", "crdownload", "download", "partial", "tmp",
+];
+
+/// How long a file has to stay unchanged before it is processed, in seconds.
+const DEFAULT_SETTLE : f64 = 5.0;
+
+/// How often the files which are waiting to settle are checked.
+const TICK : time::Duration = time::Duration::from_secs(1);
+
+/// What happens to new files, as configured in the `[watch]` table.
+struct Options {
+    settle : time::Duration,
+    write_tags : bool,
+    rename : bool,
+    format : String,
+    sort : bool,
+}
+
+impl Options {
+    fn load() -> Self {
+        let flag = |key : &str| common::find_config_value(key).and_then(|x| x.as_bool()).unwrap_or(true);
+        let settle = common::find_config_value("watch.settle")
+                .and_then(|x| x.as_float().or_else(|| x.as_integer().map(|x| x as f64)))
+                .filter(|x| x.is_finite() && *x >= 0.0)
+                .unwrap_or(DEFAULT_SETTLE);
+        Self {
+            settle : time::Duration::from_secs_f64(settle),
+            write_tags : flag("watch.write-tags"),
+            rename : flag("watch.rename"),
+            format : common::find_config("watch.format").unwrap_or_else(|| "at".to_string()),
+            sort : flag("watch.sort"),
+        }
+    }
+}
+
+pub fn run(dir : &str, once : bool) -> common::Result<()> {
+    let inbox = inbox_path(dir)?;
+    let options = Options::load();
+    if once {
+        let files = scan(&inbox).into_iter().filter(|x| common::is_audio_file(x)).collect::<Vec<_>>();
+        log::info!("processing {} files in '{}'", files.len(), inbox.display());
+        process(&inbox, &files, &options)?;
+        return Ok(());
+    }
+    let mut watcher = Watcher::new(&inbox)?;
+    let mut pending = Pending::default();
+    for file in scan(&inbox) {
+        pending.add(&file);
+    }
+    log::info!("watching '{}' for new files", inbox.display());
+    loop {
+        for changed in watcher.wait(TICK)? {
+            if changed.is_dir() {
+                scan(&changed).iter().for_each(|x| pending.add(x));
+            } else {
+                pending.add(&changed);
+            }
+        }
+        let files = pending.take_settled(options.settle);
+        if files.is_empty() {
+            continue;
+        }
+        // files which stay in the inbox are only processed again once they change
+        match process(&inbox, &files, &options) {
+            Ok(left) => pending.mark_done(&left),
+            Err(err) => {
+                log::error!("failed to process new files\nreason = {}", err);
+                pending.mark_done(&files);
+            },
+        }
+    }
+}
+
+/// Returns the path of the inbox relative to the library root, so that it
+/// matches the paths in the index.
+fn inbox_path(dir : &str) -> common::Result<path::PathBuf> {
+    let working_dir = env::current_dir().and_then(fs::canonicalize)?;
+    let inbox = fs::canonicalize(dir).map_err(|err| format!("cannot watch '{}': {}", dir, err))?;
+    if !inbox.is_dir() {
+        return Err(format!("cannot watch '{}': not a directory", dir).into());
+    }
+    let inbox = inbox.strip_prefix(&working_dir)
+            .map_err(|_| format!("cannot watch '{}': the inbox must be inside of the library", dir))?;
+    if inbox.as_os_str().is_empty() {
+        return Err("cannot watch the library root, since sorted files would be seen as new".into());
+    }
+    Ok(inbox.to_path_buf())
+}
+
+/// Returns whether a file may be a finished download.
+fn is_candidate(file_path : &path::Path) -> bool {
+    let is_partial = file_path.extension()
+            .and_then(|x| x.to_str())
+            .is_some_and(|ext| PARTIAL_EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(ext)));
+    !is_partial && !watch::is_hidden(file_path) && file_path.is_file()
+}
+
+/// Finds every file inside of a directory which may be a finished download.
+fn scan(dir : &path::Path) -> Vec<path::PathBuf> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let file_path = entry.path();
+        if file_path.is_dir() && !watch::is_hidden(&file_path) {
+            files.extend(scan(&file_path));
+        } else if is_candidate(&file_path) {
+            files.push(file_path);
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Files which were seen recently, and are waiting for their writes to settle.
+#[derive(Default)]
+struct Pending {
+    files : HashMap<path::PathBuf, (FileStat, time::Instant)>,
+    /// Files which were already looked at, but are still in the inbox.
+    done : HashMap<path::PathBuf, FileStat>,
+}
+
+impl Pending {
+    fn add(&mut self, file_path : &path::Path) {
+        if self.files.contains_key(file_path) || !is_candidate(file_path) {
+            return;
+        }
+        let stat = if let Ok(x) = FileStat::read(file_path) { x } else { return };
+        if self.done.get(file_path) == Some(&stat) {
+            return;
+        }
+        log::debug!("waiting for writes to settle: {}", file_path.display());
+        self.files.insert(file_path.to_path_buf(), (stat, time::Instant::now()));
+    }
+
+    fn mark_done(&mut self, files : &[path::PathBuf]) {
+        for file_path in files {
+            if let Ok(stat) = FileStat::read(file_path) {
+                self.done.insert(file_path.clone(), stat);
+            }
+        }
+    }
+
+    /// Stops tracking the audio files which haven't changed for the settle
+    /// time, and returns them.
+    fn take_settled(&mut self, settle : time::Duration) -> Vec<path::PathBuf> {
+        let now = time::Instant::now();
+        let mut settled = Vec::new();
+        self.files.retain(|file_path, (stat, since)| match FileStat::read(file_path) {
+            // the file was deleted or moved away
+            Err(_) => false,
+            Ok(new_stat) if new_stat != *stat => {
+                *stat = new_stat;
+                *since = now;
+                true
+            },
+            Ok(_) if now.duration_since(*since) >= settle => {
+                settled.push(file_path.clone());
+                false
+            },
+            Ok(_) => true,
+        });
+        settled.sort();
+        let (audio, other) = settled.into_iter().partition::<Vec<_>, _>(|x| common::is_audio_file(x));
+        for file_path in &other {
+            log::debug!("skipping non-audio file: {}", file_path.display());
+        }
+        self.mark_done(&other);
+        audio
+    }
+}
+
+/// Runs new files through tag inference, rename and sort, recording every
+/// change as a single operation in the journal. Returns the files which are
+/// still inside of the inbox.
+fn process(inbox : &path::Path, files : &[path::PathBuf], options : &Options) -> common::Result<Vec<path::PathBuf>> {
+    let mut index = common::index::Index::load();
+    let mut operation = Operation::new("watch");
+    let mut left = Vec::new();
+    let mut renamed = Vec::new();
+    for file in files {
+        log::info!("processing new file: {}", file.display());
+        match process_file(&mut index, &mut operation, file, options) {
+            Ok(new_file) => renamed.push(new_file),
+            Err(err) => {
+                log::warn!("failed to process file '{}'\nreason = {}", file.display(), err);
+                left.push(file.clone());
+            },
+        }
+    }
+    // files are sorted together, so that albums are moved like `sort` would
+    let n_moves = operation.moves.len();
+    let result = if options.sort && !renamed.is_empty() {
+        let keep_dir = fs::canonicalize(inbox)?;
+        cmd_sort::sort_paths(&mut index, &mut operation, &renamed, &keep_dir, true)
+    } else {
+        Ok(())
+    };
+    let sort_moves = operation.moves[n_moves..].to_vec();
+    for file in &renamed {
+        let new_file = journal::apply_moves(&sort_moves, file);
+        if new_file.starts_with(inbox) {
+            left.push(new_file);
+        }
+        if let Some(src_dir) = file.parent() {
+            remove_empty_dirs(inbox, src_dir);
+        }
+    }
+    // files which were already moved are recorded, even if a later one failed
+    operation.save()?;
+    if result.is_ok() && options.sort && cmd_sort::writes_folder_images() {
+        cmd_sort::write_folder_images(&mut index, &operation)?;
+    }
+    index.save()?;
+    result.map(|_| left)
+}
+
+/// Fills in the missing tags of a file and renames it, returning where it
+/// ended up.
+fn process_file(
+    index : &mut common::index::Index,
+    operation : &mut Operation,
+    file : &path::Path,
+    options : &Options,
+) -> common::Result<path::PathBuf> {
+    if options.write_tags {
+        if let Err(err) = write_inferred_tags(index, operation, file) {
+            log::warn!("failed to write tags to '{}'\nreason = {}", file.display(), err);
+        }
+    }
+    if !options.rename {
+        return Ok(file.to_path_buf());
+    }
+    let rename_options = cmd_rename::Options {
+        format : &options.format,
+        artist : true,
+        album : true,
+        number : true,
+        title : true,
+        fix_extensions : true,
+    };
+    cmd_rename::rename_file(index, operation, file, &rename_options, true)
+}
+
+/// Fills in the standard tags which are missing from a file, using the values
+/// inferred from its name, location and download info.
+fn write_inferred_tags(
+    index : &mut common::index::Index,
+    operation : &mut Operation,
+    file : &path::Path,
+) -> common::Result<()> {
+    let file_meta = index.parse(file)?;
+    let mut tag = tags::read_tag(file)?;
+    let changes = Field::ALL.into_iter()
+            .filter(|field| field.read(tag.as_ref()).is_none())
+            .filter_map(|field| Some(Change { field, old : None, new : Some(inferred(field, &file_meta)?) }))
+            .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return Ok(());
+    }
+    tags::print_changes(file, &changes);
+    tags::apply_changes(tag.as_mut(), file, &changes)?;
+    operation.record_tags(file, &changes);
+    Ok(())
+}
+
+/// Returns the value inferred for a field, if there is one that can be stored.
+fn inferred(field : Field, meta : &TrackMeta) -> Option<String> {
+    let value = match field {
+        Field::Artists => Some(meta.artists.join(", "))
+                .filter(|x| !x.is_empty() && !x.eq_ignore_ascii_case(common::meta::DEFAULT_AUTHOR)),
+        Field::Album => meta.album.clone(),
+        Field::AlbumArtist => meta.album_author.clone(),
+        Field::Title => meta.title.clone(),
+        Field::Track => meta.track_number.as_ref().map(|x| x.0.to_string()),
+        Field::Year => meta.year.map(|x| x.to_string()),
+    }?;
+    field.validate(&value).ok()?;
+    Some(value)
+}
+
+/// Removes the directories inside of the inbox which were emptied.
+fn remove_empty_dirs(inbox : &path::Path, dir : &path::Path) {
+    for dir in dir.ancestors() {
+        if dir == inbox || !dir.starts_with(inbox) {
+            break;
+        }
+        // the directory itself may have been moved as an album
+        if !dir.exists() {
+            continue;
+        }
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+        log::debug!("removed empty directory: {}", dir.display());
+    }
+}
\ No newline at end of file
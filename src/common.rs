@@ -1,5 +1,25 @@
 pub mod meta;
 pub mod infer;
+pub mod split;
+pub mod tags;
+pub mod source;
+pub mod index;
+pub mod query;
+pub mod select;
+pub mod walk;
+pub mod sniff;
+pub mod hash;
+pub mod csv;
+pub mod playlist;
+pub mod journal;
+pub mod art;
+pub mod companion;
+pub mod lyrics;
+pub mod loudness;
+pub mod probe;
+pub mod spectrum;
+pub mod watch;
+pub mod vorbis;
 
 use std::fs;
 use std::io::{stdout, Write};
@@ -7,50 +27,108 @@ use std::path;
 
 use which::which;
 use glob::glob;
+#[allow(clippy::single_component_path_imports)]
 use toml;
+#[allow(clippy::single_component_path_imports)]
 use log;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-const CONFIG_PATH : &'static str = "catty.toml";
+pub const CONFIG_PATH : &str = "catty.toml";
 
-pub fn find_config(key : &str) -> Option<String> {
+/// Directory inside of the library where catty keeps its own state.
+pub const DATA_DIR : &str = ".catty";
+
+pub fn read_config() -> Option<toml::Table> {
     let file = fs::read_to_string(CONFIG_PATH).ok()?;
-    let value = file.parse::<toml::Table>().ok()?;
-    let toml_value = value.get(key)?.as_str()?;
+    match file.parse::<toml::Table>() {
+        Ok(table) => Some(table),
+        Err(err) => {
+            log::warn!("failed to parse config file: {}\n{}", CONFIG_PATH, err);
+            None
+        },
+    }
+}
+
+/// Looks up a config value by its key. Keys inside of tables are separated
+/// by a `.`, e.g. `add.formats`.
+pub fn find_config_value(key : &str) -> Option<toml::Value> {
+    let mut table = read_config()?;
+    let mut parts = key.split('.').peekable();
+    while let Some(part) = parts.next() {
+        let value = table.remove(part)?;
+        if parts.peek().is_none() {
+            return Some(value);
+        }
+        if let toml::Value::Table(inner) = value {
+            table = inner;
+        } else {
+            return None;
+        }
+    }
+    None
+}
+
+#[allow(clippy::needless_return)]
+pub fn find_config(key : &str) -> Option<String> {
+    let value = find_config_value(key)?;
+    let toml_value = value.as_str()?;
     return Some(toml_value.to_owned());
 }
 
-pub fn glob_foreach_many(patterns : &[String], mut f : impl FnMut(&path::Path) -> Result<()>) -> Result<()> {
+pub fn find_config_list(key : &str) -> Option<Vec<String>> {
+    let value = find_config_value(key)?;
+    let mut list = Vec::new();
+    for item in value.as_array()? {
+        list.push(item.as_str()?.to_owned());
+    }
+    Some(list)
+}
+
+pub fn glob_foreach_many(
+    walker : &mut walk::Walker,
+    patterns : &[String],
+    mut f : impl FnMut(&path::Path) -> Result<()>,
+) -> Result<()> {
     if patterns.is_empty() {
-        log::info!("no paths supplied, defaulting to all files in the working directory");
-        return glob_foreach("*", f);
+        if walker.is_recursive() {
+            log::info!("no paths supplied, defaulting to all files in the working directory and its subdirectories");
+        } else {
+            log::info!("no paths supplied, defaulting to all files in the working directory");
+        }
+        return glob_foreach(walker, "*", f);
     }
     let n_count = patterns.len();
     let mut n = 0;
     for pattern in patterns {
         n += 1;
         log::info!("processing batch [{} / {}]", n, n_count);
-        glob_foreach(pattern, &mut f)?;
+        glob_foreach(walker, pattern, &mut f)?;
     }
     Ok(())
 }
 
-pub fn glob_foreach(pattern : &str, mut f : impl FnMut(&path::Path) -> Result<()>) -> Result<()> {
+pub fn glob_foreach(
+    walker : &mut walk::Walker,
+    pattern : &str,
+    mut f : impl FnMut(&path::Path) -> Result<()>,
+) -> Result<()> {
     let mut has_matches = false;
+    let is_recursive = walker.is_recursive();
     for file in glob(pattern)? {
         has_matches = true;
         let file = file?;
-        if file.is_dir() {
-            continue; // skip directories
-        }
-        if let Some(ext) = file.extension().and_then(|x| x.to_str()) {
-            if ext_is_audio_file(ext) {
-                f(file.as_path())?;
-                continue;
+        walker.visit(&file, &mut |file| {
+            if is_audio_file(file) {
+                return f(file);
             }
-        }
-        log::info!("skipping non-audio file: {}", file.display());
+            if is_recursive {
+                log::debug!("skipping non-audio file: {}", file.display());
+            } else {
+                log::info!("skipping non-audio file: {}", file.display());
+            }
+            Ok(())
+        })?;
     }
     if !has_matches {
         log::warn!("pattern matched no files: {:?}", pattern);
@@ -58,6 +136,35 @@ pub fn glob_foreach(pattern : &str, mut f : impl FnMut(&path::Path) -> Result<()
     Ok(())
 }
 
+/// Calls `f` for every audio file in the library, respecting any
+/// `.cattyignore` files.
+pub fn library_foreach(f : impl FnMut(&path::Path) -> Result<()>) -> Result<()> {
+    glob_foreach(&mut walk::Walker::new(true, None), ".", f)
+}
+
+/// Formats a number of bytes using the largest unit that fits, e.g. `3.2 MiB`.
+pub fn format_size(size : u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+
+/// Formats a duration in seconds as hours, minutes and seconds, e.g. `1:02:03`.
+pub fn format_duration(seconds : f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
 pub fn ask_confirm() -> bool {
     log::warn!("do you accept? [Y/n]");
     let mut input = String::new();
@@ -76,6 +183,7 @@ pub fn ask_confirm() -> bool {
     }
 }
 
+#[allow(clippy::needless_return)]
 pub fn find_ytdlp_path() -> Option<path::PathBuf> {
     if let Some(ytdlp_config_path) = find_config("yt-dlp") {
         match fs::exists(&ytdlp_config_path) {
@@ -97,7 +205,8 @@ pub fn find_ytdlp_path() -> Option<path::PathBuf> {
     return None;
 }
 
-pub fn _find_ffmpeg_path() -> Option<path::PathBuf> {
+#[allow(clippy::needless_return)]
+pub fn find_ffmpeg_path() -> Option<path::PathBuf> {
     if let Some(ffmpeg_config_path) = find_config("ffmpeg") {
         match fs::exists(&ffmpeg_config_path) {
             Ok(exists) => if exists {
@@ -108,13 +217,85 @@ pub fn _find_ffmpeg_path() -> Option<path::PathBuf> {
         log::warn!("installation does not exist at: {}\n\
                     looking for installation in PATH", ffmpeg_config_path);
     }
-    if let Ok(ytdlp_path) = which("ffmpeg") {
-        return Some(ytdlp_path);
+    if let Ok(ffmpeg_path) = which("ffmpeg") {
+        return Some(ffmpeg_path);
     }
     log::warn!("cannot find executable to `ffmpeg`, some behaviour may be degraded");
     return None;
 }
 
+/// Finds `ffprobe`, which is usually installed alongside `ffmpeg`. Nothing is
+/// logged if it's missing, since it's only used as a fallback.
+pub fn find_ffprobe_path() -> Option<path::PathBuf> {
+    if let Some(ffprobe_config_path) = find_config("ffprobe") {
+        if path::Path::new(&ffprobe_config_path).exists() {
+            return Some(ffprobe_config_path.into());
+        }
+        log::warn!("installation does not exist at: {}\n\
+                    looking for installation in PATH", ffprobe_config_path);
+    }
+    if let Some(ffmpeg_config_path) = find_config("ffmpeg") {
+        let ffmpeg_config_path = path::Path::new(&ffmpeg_config_path);
+        let name = match ffmpeg_config_path.extension() {
+            Some(ext) => format!("ffprobe.{}", ext.to_string_lossy()),
+            None => "ffprobe".to_string(),
+        };
+        let ffprobe_path = ffmpeg_config_path.with_file_name(name);
+        if ffprobe_path.exists() {
+            return Some(ffprobe_path);
+        }
+    }
+    which("ffprobe").ok()
+}
+
+/// Extensions of audio formats whose containers can also hold video, so
+/// files using them have to be read to tell which they are.
+const AMBIGUOUS_EXTENSIONS : &[&str] = &["3gp", "movpkg"];
+
+/// Returns whether a file contains audio. Files with the extension of an
+/// audio-only format are trusted, and so are those of companion files, but
+/// anything else is identified by its contents. Files which can't be
+/// identified fall back to checking their extension.
+pub fn is_audio_file(file_path : &path::Path) -> bool {
+    if let Some(ext) = file_path.extension().and_then(|x| x.to_str()) {
+        if ext_is_audio_file(ext) && !AMBIGUOUS_EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(ext)) {
+            return true;
+        }
+        if companion::is_companion_file(file_path) {
+            return false;
+        }
+    }
+    let kind = match index::cached_kind(file_path) {
+        Some(kind) => Ok(kind),
+        None => sniff::sniff(file_path),
+    };
+    match kind {
+        Ok(sniff::Kind::Audio(_)) => true,
+        Ok(sniff::Kind::Video) => {
+            log::debug!("file contains video: {}", file_path.display());
+            false
+        },
+        Ok(sniff::Kind::Unknown) => file_path.extension()
+                .and_then(|x| x.to_str())
+                .is_some_and(ext_is_audio_file),
+        Err(err) => {
+            log::warn!("failed to read file '{}'\nreason = {}", file_path.display(), err);
+            false
+        },
+    }
+}
+
+/// Finds the command used to edit text files, which can be configured with the
+/// `editor` key, and otherwise comes from `$VISUAL` or `$EDITOR`.
+pub fn find_editor() -> String {
+    find_config("editor")
+            .or_else(|| std::env::var("VISUAL").ok())
+            .or_else(|| std::env::var("EDITOR").ok())
+            .filter(|x| !x.trim().is_empty())
+            .unwrap_or_else(|| "vi".to_string())
+}
+
+#[allow(clippy::match_like_matches_macro)]
 pub fn ext_is_audio_file(ext : &str) -> bool {
     let ext = ext.to_ascii_lowercase();
     match ext.as_str() {
@@ -123,7 +304,7 @@ pub fn ext_is_audio_file(ext : &str) -> bool {
         | "iklax" | "ivs" | "m4a" | "m4b" | "m4p" | "mmf" | "movpkg"
         | "mp3" | "mpc" | "msv" | "nmf" | "ogg" | "opus" | "ra" | "raw"
         | "rf64" | "sln" | "tta" | "voc" | "vox" | "wav" | "wma" | "wv"
-        | "webm" | "8svx" | "cda"
+        | "8svx" | "cda"
         => true,
         _ => false,
     }
@@ -0,0 +1,188 @@
+use std::fs;
+use std::path;
+use std::process;
+use std::collections::HashMap;
+use crate::common;
+use crate::common::tags::{ self, Image };
+
+use sha2::{Digest, Sha256};
+
+/// Names of the images which players look for next to an album, in order of
+/// preference.
+const FOLDER_IMAGE_STEMS : &[&str] = &["cover", "folder", "front", "album"];
+const FOLDER_IMAGE_EXTENSIONS : &[&str] = &["jpg", "jpeg", "png"];
+
+/// Name of the image written into album directories, unless `art.file-name`
+/// is set in the config. The extension is replaced to match the image.
+pub const DEFAULT_FILE_NAME : &str = "cover.jpg";
+
+pub fn extension(mime_type : &str) -> Option<&'static str> {
+    match mime_type {
+        "image/jpeg" | "image/jpg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "image/bmp" => Some("bmp"),
+        "image/gif" => Some("gif"),
+        _ => None,
+    }
+}
+
+pub fn mime_type(file_path : &path::Path) -> Option<&'static str> {
+    let ext = file_path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "bmp" => Some("image/bmp"),
+        "gif" => Some("image/gif"),
+        _ => None,
+    }
+}
+
+/// Finds the image which represents the album stored in a directory, such as
+/// `cover.jpg` or `folder.png`.
+pub fn find_folder_image(dir : &path::Path) -> Option<path::PathBuf> {
+    let dir = if dir.as_os_str().is_empty() { path::Path::new(".") } else { dir };
+    let mut images = Vec::new();
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let file_path = entry.path();
+        let stem = file_path.file_stem().and_then(|x| x.to_str()).map(|x| x.to_ascii_lowercase());
+        let ext = file_path.extension().and_then(|x| x.to_str()).map(|x| x.to_ascii_lowercase());
+        let (stem, ext) = if let (Some(stem), Some(ext)) = (stem, ext) { (stem, ext) } else { continue };
+        let rank = FOLDER_IMAGE_STEMS.iter().position(|x| *x == stem);
+        if rank.is_some() && FOLDER_IMAGE_EXTENSIONS.contains(&ext.as_str()) && file_path.is_file() {
+            images.push((rank, file_path.strip_prefix(".").map(|x| x.to_path_buf()).unwrap_or(file_path)));
+        }
+    }
+    images.sort();
+    images.into_iter().next().map(|x| x.1)
+}
+
+pub fn read_folder_image(file_path : &path::Path) -> common::Result<Image> {
+    let mime_type = mime_type(file_path)
+            .ok_or_else(|| format!("unsupported image type: {}", file_path.display()))?;
+    Ok(Image { mime_type : mime_type.to_string(), data : fs::read(file_path)? })
+}
+
+/// Returns the width and height of a JPEG or PNG image.
+pub fn image_size(data : &[u8]) -> Option<(u32, u32)> {
+    if data.starts_with(b"\x89PNG\r\n\x1A\n") && data.len() >= 24 && &data[12..16] == b"IHDR" {
+        let width = u32::from_be_bytes(data[16..20].try_into().ok()?);
+        let height = u32::from_be_bytes(data[20..24].try_into().ok()?);
+        return Some((width, height));
+    }
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        if marker == 0xFF {
+            pos += 1; // padding
+            continue;
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        // start of frame markers, excluding DHT, JPG and DAC
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let frame = data.get(pos + 4..pos + 9)?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+            let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+            return Some((width, height));
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+/// Returns whether an image has the 16:9 shape of a video thumbnail.
+pub fn is_widescreen(image : &Image) -> bool {
+    match image_size(&image.data) {
+        Some((width, height)) if height > 0 => {
+            let ratio = width as f64 / height as f64;
+            (ratio - 16.0 / 9.0).abs() < 0.05
+        },
+        _ => false,
+    }
+}
+
+/// Crops an image to a square around its centre, using `ffmpeg`.
+pub fn crop_square(ffmpeg_path : &path::Path, image : &Image) -> common::Result<Image> {
+    let ext = extension(&image.mime_type).ok_or("unsupported image type")?;
+    fs::create_dir_all(common::DATA_DIR)?;
+    let in_path = path::Path::new(common::DATA_DIR).join(format!("crop-in.{}", ext));
+    let out_path = path::Path::new(common::DATA_DIR).join(format!("crop-out.{}", ext));
+    fs::write(&in_path, &image.data)?;
+    let mut proc = process::Command::new(ffmpeg_path);
+    proc.args(["-v", "error", "-y", "-i"]);
+    proc.arg(&in_path);
+    proc.args(["-vf", "crop=min(iw\\,ih):min(iw\\,ih)"]);
+    proc.arg(&out_path);
+    proc.stdin(process::Stdio::null());
+    log::debug!("running process with args: {:?}", proc.get_args());
+    let output = proc.output();
+    let data = fs::read(&out_path);
+    let _ = fs::remove_file(&in_path);
+    let _ = fs::remove_file(&out_path);
+    let output = output?;
+    if !output.status.success() {
+        return Err(format!("failed to crop image\n{}", String::from_utf8_lossy(&output.stderr)).into());
+    }
+    Ok(Image { mime_type : image.mime_type.clone(), data : data? })
+}
+
+/// Identifies an image by its contents.
+pub fn image_hash(image : &Image) -> String {
+    format!("{:x}", Sha256::digest(&image.data))
+}
+
+/// Chooses the cover art shared by the most tracks.
+pub fn most_common_cover(files : &[path::PathBuf]) -> Option<Image> {
+    let mut covers : HashMap<String, (usize, Image)> = HashMap::new();
+    for file in files {
+        match tags::read_cover(file) {
+            Ok(Some(image)) => covers.entry(image_hash(&image)).or_insert((0, image)).0 += 1,
+            Ok(None) => (),
+            Err(err) => log::debug!("failed to read cover art of '{}'\nreason = {}", file.display(), err),
+        }
+    }
+    // ties are broken by the hash, so the choice doesn't depend on file order
+    covers.into_iter()
+            .max_by(|a, b| a.1.0.cmp(&b.1.0).then(b.0.cmp(&a.0)))
+            .map(|x| x.1.1)
+}
+
+/// Writes the most common cover art of the tracks into their album directory,
+/// unless it already has a folder image. Returns the path of the new image.
+pub fn write_folder_image(
+    dir : &path::Path,
+    files : &[path::PathBuf],
+    ffmpeg_path : Option<&path::Path>,
+) -> common::Result<Option<path::PathBuf>> {
+    if let Some(existing) = find_folder_image(dir) {
+        log::debug!("album already has a folder image: {}", existing.display());
+        return Ok(None);
+    }
+    let mut image = match most_common_cover(files) {
+        Some(image) => image,
+        None => return Ok(None),
+    };
+    if let Some(ffmpeg_path) = ffmpeg_path {
+        if is_widescreen(&image) {
+            image = crop_square(ffmpeg_path, &image)?;
+        }
+    }
+    // players only look for folder images in some formats
+    let ext = match extension(&image.mime_type) {
+        Some(ext) if FOLDER_IMAGE_EXTENSIONS.contains(&ext) => ext,
+        _ => {
+            log::warn!("unsupported cover art type '{}' in: {}", image.mime_type, dir.display());
+            return Ok(None);
+        },
+    };
+    let file_name = common::find_config("art.file-name").unwrap_or(DEFAULT_FILE_NAME.to_string());
+    let image_path = dir.join(path::Path::new(&file_name).with_extension(ext));
+    fs::write(&image_path, &image.data)?;
+    log::info!("wrote folder image: {}", image_path.display());
+    Ok(Some(image_path))
+}
\ No newline at end of file
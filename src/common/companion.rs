@@ -0,0 +1,167 @@
+use std::fs;
+use std::path;
+use std::collections::HashMap;
+use crate::common;
+use crate::common::journal::Operation;
+
+/// Non-audio files which are expected to live alongside tracks, such as
+/// lyrics, cue sheets, download info, covers and booklets.
+pub const EXTENSIONS : &[&str] = &[
+    "cue", "description", "lrc", "txt", "nfo", "log", "m3u", "m3u8", "json",
+    "pdf", "jpg", "jpeg", "png", "webp", "gif",
+];
+
+/// Returns whether a file is the kind of file that is kept alongside tracks.
+pub fn is_companion_file(file_path : &path::Path) -> bool {
+    file_path.extension()
+            .and_then(|x| x.to_str())
+            .is_some_and(|ext| EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(ext)))
+}
+
+/// The files of a directory which tracks are moved out of.
+#[derive(Debug, Default)]
+struct Listing {
+    /// The stems of the audio files.
+    stems : Vec<String>,
+    /// The names of the other files.
+    others : Vec<String>,
+}
+
+impl Listing {
+    fn read(dir : &path::Path) -> Self {
+        let mut listing = Self::default();
+        let read_dir = if dir.as_os_str().is_empty() { path::Path::new(".") } else { dir };
+        for entry in fs::read_dir(read_dir).into_iter().flatten().flatten() {
+            let other = dir.join(entry.file_name());
+            if !other.is_file() {
+                continue;
+            }
+            if common::is_audio_file(&other) {
+                listing.stems.extend(other.file_stem().and_then(|x| x.to_str()).map(String::from));
+            } else if let Some(name) = entry.file_name().to_str() {
+                listing.others.push(name.to_string());
+            }
+        }
+        listing
+    }
+}
+
+/// The directories which tracks were moved out of by an operation, so that
+/// each one is only listed and sniffed once, rather than for every track.
+#[derive(Debug, Default)]
+pub struct Dirs {
+    listings : HashMap<path::PathBuf, Listing>,
+}
+
+impl Dirs {
+    /// Finds the non-audio files next to a track which share its name, such
+    /// as `song.lrc` or `song.info.json` for `song.mp3`. Files which share
+    /// the name of a different track more closely, e.g. `song.live.lrc` next
+    /// to `song.live.mp3`, belong to that track instead. The companions are
+    /// forgotten, since they are expected to be moved along with the track.
+    fn take_companions(&mut self, file_path : &path::Path) -> Vec<path::PathBuf> {
+        let stem = match file_path.file_stem().and_then(|x| x.to_str()) {
+            Some(stem) => stem,
+            None => return Vec::new(),
+        };
+        let dir = file_path.parent().unwrap_or(path::Path::new(""));
+        let listing = self.listings.entry(dir.to_path_buf()).or_insert_with(|| Listing::read(dir));
+        let file_name = file_path.file_name().and_then(|x| x.to_str()).unwrap_or_default();
+        let is_companion = |name : &String| {
+            let owner = listing.stems.iter()
+                    .map(String::as_str)
+                    .chain([stem])
+                    .filter(|x| name.strip_prefix(x).is_some_and(|rest| rest.starts_with('.')))
+                    .max_by_key(|x| x.len());
+            name != file_name && owner == Some(stem)
+        };
+        let (companions, others) = listing.others.drain(..).partition::<Vec<_>, _>(is_companion);
+        listing.others = others;
+        let mut companions = companions.into_iter().map(|x| dir.join(x)).collect::<Vec<_>>();
+        companions.sort();
+        companions
+    }
+
+    /// Keeps the listing of a directory up to date after a file was moved
+    /// into it.
+    fn add(&mut self, file_path : &path::Path, is_audio : bool) {
+        let listing = match file_path.parent().and_then(|x| self.listings.get_mut(x)) {
+            Some(listing) => listing,
+            None => return,
+        };
+        let name = if is_audio { file_path.file_stem() } else { file_path.file_name() };
+        if let Some(name) = name.and_then(|x| x.to_str()) {
+            if is_audio {
+                listing.stems.push(name.to_string());
+            } else {
+                listing.others.push(name.to_string());
+            }
+        }
+    }
+}
+
+/// Moves the companions of a track which was moved from `file_path` to
+/// `new_file_path`, renaming them to match it.
+pub fn move_companions(
+    operation : &mut Operation,
+    file_path : &path::Path,
+    new_file_path : &path::Path,
+) -> common::Result<()> {
+    let old_stem = file_path.file_stem().and_then(|x| x.to_str()).unwrap_or_default();
+    let new_stem = new_file_path.file_stem().and_then(|x| x.to_str()).unwrap_or_default();
+    let companions = operation.companion_dirs.take_companions(file_path);
+    operation.companion_dirs.add(new_file_path, true);
+    for companion in companions {
+        let file_name = companion.file_name().and_then(|x| x.to_str()).unwrap_or_default();
+        let suffix = &file_name[old_stem.len()..];
+        let new_companion = new_file_path.with_file_name(format!("{}{}", new_stem, suffix));
+        if move_file(operation, &companion, &new_companion)? {
+            operation.companion_dirs.add(&new_companion, false);
+        }
+    }
+    Ok(())
+}
+
+/// Moves the files left in a directory after all of its tracks were moved to
+/// `new_dir`, such as covers, booklets and rip logs.
+pub fn move_album_files(
+    operation : &mut Operation,
+    dir : &path::Path,
+    new_dir : &path::Path,
+) -> common::Result<()> {
+    if dir.as_os_str().is_empty() || dir == path::Path::new(".") {
+        return Ok(()); // never move files out of the library root
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let file_path = entry?.path();
+        if !file_path.is_file() {
+            continue;
+        }
+        if common::is_audio_file(&file_path) {
+            return Ok(()); // the directory still belongs to other tracks
+        }
+        // anything else, like archives or videos, is left for the user
+        if is_companion_file(&file_path) {
+            files.push(file_path);
+        }
+    }
+    files.sort();
+    for file_path in files {
+        let new_file_path = new_dir.join(file_path.file_name().unwrap());
+        move_file(operation, &file_path, &new_file_path)?;
+    }
+    Ok(())
+}
+
+/// Moves a file along with a track, returning whether it was moved.
+fn move_file(operation : &mut Operation, file_path : &path::Path, new_file_path : &path::Path) -> common::Result<bool> {
+    if new_file_path.exists() {
+        log::warn!("file already exists, leaving behind: {}", file_path.display());
+        return Ok(false);
+    }
+    log::info!("moving along    '{}'\n          to => '{}'", file_path.display(), new_file_path.display());
+    fs::rename(file_path, new_file_path)?;
+    operation.record_move(file_path, new_file_path);
+    Ok(true)
+}
\ No newline at end of file
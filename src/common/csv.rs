@@ -0,0 +1,67 @@
+use crate::common;
+
+/// Separates the items of a list inside of a single cell.
+pub const LIST_SEPARATOR : &str = "; ";
+
+/// Quotes a cell if it contains any characters with special meaning.
+pub fn escape(cell : &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Splits a cell containing a list back into its items.
+pub fn split_list(cell : &str) -> Vec<String> {
+    cell.split(LIST_SEPARATOR.trim())
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(String::from)
+            .collect()
+}
+
+/// Parses comma separated values into rows of cells. Quoted cells may contain
+/// commas, newlines, and doubled quotes.
+pub fn parse(source : &str) -> common::Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut in_quotes = false;
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+    while let Some(chr) = chars.next() {
+        match chr {
+            '"' if in_quotes => if chars.peek() == Some(&'"') {
+                chars.next();
+                cell.push('"');
+            } else {
+                in_quotes = false;
+            },
+            '"' if cell.is_empty() => in_quotes = true,
+            ',' if !in_quotes => row.push(std::mem::take(&mut cell)),
+            '\r' if !in_quotes => (),
+            '\n' if !in_quotes => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+                line += 1;
+            },
+            _ => {
+                if chr == '\n' {
+                    line += 1;
+                }
+                cell.push(chr);
+            },
+        }
+    }
+    if in_quotes {
+        return Err(format!("unterminated quote in CSV, starting before line {}", line).into());
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    // skip blank lines
+    rows.retain(|x| x.len() > 1 || x.first().is_some_and(|x| !x.is_empty()));
+    Ok(rows)
+}
\ No newline at end of file
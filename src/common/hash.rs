@@ -0,0 +1,133 @@
+use std::fs;
+use std::path;
+use crate::common;
+use crate::common::sniff::{self, Format, Kind};
+
+use sha2::{Digest, Sha256};
+
+/// Hashes the audio stream of a file, skipping over any tags so that copies of
+/// the same audio with different metadata have the same hash. Files in formats
+/// that aren't understood are hashed in full.
+pub fn audio_hash(file_path : &path::Path) -> common::Result<String> {
+    let data = fs::read(file_path)?;
+    let mut hasher = Sha256::new();
+    match sniff::sniff(file_path)? {
+        Kind::Audio(Format::Mp3 | Format::Aac) => hasher.update(mpeg_stream(&data)),
+        Kind::Audio(Format::Flac) => hasher.update(flac_stream(&data)),
+        Kind::Audio(Format::Mp4) => {
+            for stream in mp4_streams(&data) {
+                hasher.update(stream);
+            }
+        },
+        Kind::Audio(Format::Vorbis | Format::Opus | Format::Speex | Format::OggFlac) => {
+            for page in ogg_audio_pages(&data) {
+                hasher.update(page);
+            }
+        },
+        Kind::Audio(Format::Wav) => hasher.update(riff_chunk(&data, b"data", false).unwrap_or(&data)),
+        Kind::Audio(Format::Aiff) => hasher.update(riff_chunk(&data, b"SSND", true).unwrap_or(&data)),
+        _ => hasher.update(&data),
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Strips ID3v2 tags from the start of an MPEG stream, and ID3v1 and APE tags
+/// from the end of it.
+fn mpeg_stream(data : &[u8]) -> &[u8] {
+    let start = sniff::id3_prefix_len(data).min(data.len());
+    let mut end = data.len();
+    if end - start >= 128 && data[end - 128..].starts_with(b"TAG") {
+        end -= 128;
+    }
+    if end - start >= 32 && &data[end - 32..end - 24] == b"APETAGEX" {
+        let footer = &data[end - 32..end];
+        let size = u32::from_le_bytes(footer[12..16].try_into().unwrap()) as usize;
+        let flags = u32::from_le_bytes(footer[20..24].try_into().unwrap());
+        let has_header = flags & 0x8000_0000 != 0;
+        let size = size + if has_header { 32 } else { 0 };
+        end -= size.min(end - start);
+    }
+    &data[start..end]
+}
+
+/// Skips past the metadata blocks of a FLAC file.
+fn flac_stream(data : &[u8]) -> &[u8] {
+    let mut pos = sniff::id3_prefix_len(data) + 4;
+    while pos + 4 <= data.len() {
+        let header = &data[pos..pos + 4];
+        let is_last = header[0] & 0x80 != 0;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        pos += 4 + len;
+        if is_last {
+            break;
+        }
+    }
+    &data[pos.min(data.len())..]
+}
+
+/// Finds every `mdat` box of an MP4 file, which hold the encoded audio.
+fn mp4_streams(data : &[u8]) -> Vec<&[u8]> {
+    let mut streams = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let mut size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let mut header_len = 8;
+        if size == 1 && pos + 16 <= data.len() {
+            size = u64::from_be_bytes(data[pos + 8..pos + 16].try_into().unwrap()) as usize;
+            header_len = 16;
+        } else if size == 0 {
+            size = data.len() - pos;
+        }
+        if size < header_len {
+            break;
+        }
+        // the size is untrusted, so a corrupt file may claim to be huge
+        let end = pos.saturating_add(size).min(data.len());
+        if &data[pos + 4..pos + 8] == b"mdat" {
+            streams.push(&data[pos + header_len..end]);
+        }
+        pos = end;
+    }
+    streams
+}
+
+/// Collects the bodies of the Ogg pages that hold audio, skipping the header
+/// pages which hold the stream info and comments.
+fn ogg_audio_pages(data : &[u8]) -> Vec<&[u8]> {
+    let mut pages = Vec::new();
+    let mut in_audio = false;
+    let mut pos = 0;
+    while pos + 27 <= data.len() && &data[pos..pos + 4] == b"OggS" {
+        let granule = i64::from_le_bytes(data[pos + 6..pos + 14].try_into().unwrap());
+        let n_segments = data[pos + 26] as usize;
+        let body_start = pos + 27 + n_segments;
+        if body_start > data.len() {
+            break;
+        }
+        let body_len = data[pos + 27..body_start].iter().map(|x| *x as usize).sum::<usize>();
+        let body_end = (body_start + body_len).min(data.len());
+        // header packets always have a granule position of 0
+        in_audio = in_audio || granule > 0;
+        if in_audio {
+            pages.push(&data[body_start..body_end]);
+        }
+        pos = body_end;
+    }
+    pages
+}
+
+/// Finds a chunk inside of a RIFF (WAV) or IFF (AIFF) file.
+fn riff_chunk<'a>(data : &'a [u8], id : &[u8], big_endian : bool) -> Option<&'a [u8]> {
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let size : [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap();
+        let size = if big_endian { u32::from_be_bytes(size) } else { u32::from_le_bytes(size) } as usize;
+        let end = (pos + 8 + size).min(data.len());
+        if &data[pos..pos + 4] == id {
+            return Some(&data[pos + 8..end]);
+        }
+        // chunks are padded to an even length
+        pos = end + (size & 1);
+    }
+    None
+}
\ No newline at end of file
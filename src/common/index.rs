@@ -0,0 +1,410 @@
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path;
+use std::time;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use crate::common;
+use crate::common::meta::TrackMeta;
+use crate::common::sniff::{ self, Kind };
+use crate::common::source::Source;
+
+const INDEX_NAME : &str = "index.tsv";
+const INDEX_HEADER : &str = "catty-index 6";
+const LIST_SEPARATOR : char = '\x1F';
+
+/// The size and modification time of a file, used to tell whether a cached
+/// entry is still valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    pub size : u64,
+    pub mtime_secs : u64,
+    pub mtime_nanos : u32,
+}
+
+impl FileStat {
+    pub fn read(file_path : &path::Path) -> common::Result<Self> {
+        let metadata = fs::metadata(file_path)?;
+        let mtime = metadata.modified()?
+                .duration_since(time::UNIX_EPOCH)
+                .unwrap_or_default();
+        Ok(Self {
+            size : metadata.len(),
+            mtime_secs : mtime.as_secs(),
+            mtime_nanos : mtime.subsec_nanos(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub stat : FileStat,
+    /// What the contents of the file were identified as.
+    pub kind : Kind,
+    pub meta : TrackMeta,
+    pub source : Option<Source>,
+    /// The album artist that the file gives to the collection it is stored
+    /// in. Collections are identified by their directory, which is already
+    /// part of the key, so only the author needs to be stored.
+    pub collection_author : Option<String>,
+    /// The hash of the audio stream, which is only worked out when needed
+    /// since it means reading the whole file.
+    pub audio_hash : Option<String>,
+}
+
+impl Entry {
+    pub fn to_json(&self, file_path : &path::Path) -> serde_json::Value {
+        let meta = &self.meta;
+        let source = self.source.as_ref();
+        serde_json::json!({
+            "path" : file_path.to_string_lossy(),
+            "artists" : meta.artists,
+            "features" : meta.features,
+            "album" : meta.album,
+            "album_artist" : meta.album_author,
+            "track" : meta.track_number.as_ref().map(|x| x.0),
+            "year" : meta.year,
+            "title" : meta.title,
+            "size" : self.stat.size,
+            "duration" : meta.audio.duration,
+            "bitrate" : meta.audio.bitrate,
+            "sample_rate" : meta.audio.sample_rate,
+            "channels" : meta.audio.channels,
+            "codec" : meta.audio.codec,
+            "source" : source.map(|x| &x.url),
+            "extractor" : source.and_then(|x| x.extractor.as_ref()),
+            "downloaded" : source.and_then(|x| x.date.as_ref()),
+        })
+    }
+}
+
+/// The kind of every file in the library index when it was loaded, so that
+/// walking the library doesn't have to read each file to identify it.
+static KINDS : OnceLock<HashMap<path::PathBuf, (FileStat, Kind)>> = OnceLock::new();
+
+/// Returns the kind of a file stored in the library index, as long as the file
+/// is unchanged since then.
+pub fn cached_kind(file_path : &path::Path) -> Option<Kind> {
+    let (stat, kind) = KINDS.get()?.get(&index_key(file_path))?;
+    let is_valid = FileStat::read(file_path).is_ok_and(|x| x == *stat);
+    if is_valid { Some(*kind) } else { None }
+}
+
+/// A persistent cache of the metadata inferred for each file in the library,
+/// stored inside of the `.catty` directory. Entries are reused as long as the
+/// size and modification time of the file are unchanged.
+#[derive(Debug)]
+pub struct Index {
+    entries : HashMap<path::PathBuf, Entry>,
+    dirty : bool,
+    hits : usize,
+    misses : usize,
+}
+
+impl Index {
+    pub fn new() -> Self {
+        Self {
+            entries : HashMap::new(),
+            dirty : true,
+            hits : 0,
+            misses : 0,
+        }
+    }
+
+    fn index_path() -> path::PathBuf {
+        path::Path::new(common::DATA_DIR).join(INDEX_NAME)
+    }
+
+    /// Loads the index of the library in the working directory. If the index
+    /// does not exist or cannot be read, an empty index is returned instead.
+    pub fn load() -> Self {
+        let mut index = Self::new();
+        let text = match fs::read_to_string(Self::index_path()) {
+            Ok(text) => text,
+            Err(_) => return index,
+        };
+        let mut lines = text.lines();
+        if lines.next() != Some(INDEX_HEADER) {
+            log::warn!("library index is from a different version of catty, ignoring it");
+            return index;
+        }
+        index.dirty = false;
+        for line in lines {
+            if let Some((key, entry)) = parse_entry(line) {
+                index.entries.insert(key, entry);
+            } else {
+                log::warn!("skipping malformed library index entry: {:?}", line);
+                index.dirty = true;
+            }
+        }
+        log::debug!("loaded {} entries from the library index", index.entries.len());
+        let kinds = index.entries.iter().map(|(key, x)| (key.clone(), (x.stat, x.kind))).collect();
+        let _ = KINDS.set(kinds);
+        index
+    }
+
+    pub fn save(&mut self) -> common::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        fs::create_dir_all(common::DATA_DIR)?;
+        let tmp_path = Self::index_path().with_extension("tmp");
+        {
+            let mut out = BufWriter::new(fs::File::create(&tmp_path)?);
+            writeln!(out, "{}", INDEX_HEADER)?;
+            let mut keys = self.entries.keys().collect::<Vec<_>>();
+            keys.sort();
+            for key in keys {
+                writeln!(out, "{}", write_entry(key, &self.entries[key]))?;
+            }
+            out.flush()?;
+        }
+        fs::rename(tmp_path, Self::index_path())?;
+        log::debug!("saved {} entries to the library index ({} reused, {} parsed)",
+                self.entries.len(), self.hits, self.misses);
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the cached entry for a file, parsing the file again if it has
+    /// changed since it was last indexed.
+    pub fn get(&mut self, file_path : &path::Path) -> common::Result<&Entry> {
+        let key = index_key(file_path);
+        let stat = FileStat::read(file_path)?;
+        let is_valid = self.entries.get(&key).is_some_and(|x| x.stat == stat);
+        if is_valid {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            let meta = common::meta::parse(file_path)?;
+            let entry = Entry {
+                stat,
+                kind : sniff::sniff(file_path)?,
+                collection_author : common::infer::collection_author(file_path, &meta),
+                meta,
+                source : common::source::read(file_path),
+                audio_hash : None,
+            };
+            self.entries.insert(key.clone(), entry);
+            self.dirty = true;
+        }
+        Ok(&self.entries[&key])
+    }
+
+    /// Returns the hash of the audio stream of a file, using the cached hash if
+    /// the file is unchanged.
+    pub fn audio_hash(&mut self, file_path : &path::Path) -> common::Result<String> {
+        self.get(file_path)?;
+        let entry = self.entries.get_mut(&index_key(file_path)).unwrap();
+        if let Some(hash) = &entry.audio_hash {
+            return Ok(hash.clone());
+        }
+        let hash = common::hash::audio_hash(file_path)?;
+        entry.audio_hash = Some(hash.clone());
+        self.dirty = true;
+        Ok(hash)
+    }
+
+    /// Returns the metadata for a file, using the cached metadata if the file
+    /// is unchanged.
+    pub fn parse(&mut self, file_path : &path::Path) -> common::Result<TrackMeta> {
+        Ok(self.get(file_path)?.meta.clone())
+    }
+
+    /// Updates the index after a file or directory has been moved. Metadata
+    /// is inferred from the path of a file, so it is worked out again, but
+    /// everything read from the contents of the file is kept.
+    pub fn rename(&mut self, from : &path::Path, to : &path::Path) {
+        let from = index_key(from);
+        let to = index_key(to);
+        let moved = self.entries.keys()
+                .filter(|x| x.starts_with(&from))
+                .cloned()
+                .collect::<Vec<_>>();
+        for key in moved {
+            let mut entry = self.entries.remove(&key).unwrap();
+            self.dirty = true;
+            let rel_path = key.strip_prefix(&from).unwrap();
+            let new_key = if rel_path.as_os_str().is_empty() { to.clone() } else { to.join(rel_path) };
+            entry.meta = match common::meta::parse(&new_key) {
+                Ok(meta) => meta,
+                Err(err) => {
+                    log::debug!("failed to parse moved file '{}'\nreason = {}", new_key.display(), err);
+                    continue;
+                },
+            };
+            // the file may have left the directory named after its album
+            entry.collection_author = common::infer::collection_author(&new_key, &entry.meta);
+            self.entries.insert(new_key, entry);
+        }
+    }
+
+    /// Removes any entries for files which no longer exist.
+    pub fn prune(&mut self) -> usize {
+        let count = self.entries.len();
+        self.entries.retain(|key, _| key.exists());
+        let removed = count - self.entries.len();
+        if removed > 0 {
+            self.dirty = true;
+        }
+        removed
+    }
+}
+
+/// Converts a file path into a key relative to the library root, without
+/// touching the file system (the file may have already been moved).
+pub fn index_key(file_path : &path::Path) -> path::PathBuf {
+    let mut file_path = file_path;
+    let roots = [
+        std::env::current_dir().ok(),
+        std::env::current_dir().and_then(fs::canonicalize).ok(),
+    ];
+    if file_path.is_absolute() {
+        for root in roots.iter().flatten() {
+            if let Ok(rel_path) = file_path.strip_prefix(root) {
+                file_path = rel_path;
+                break;
+            }
+        }
+    }
+    file_path.components()
+            .filter(|x| !matches!(x, path::Component::CurDir))
+            .collect()
+}
+
+/// Escapes a value so that it can be stored in a tab-separated file.
+pub fn escape(value : &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for chr in value.chars() {
+        match chr {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(chr),
+        }
+    }
+    out
+}
+
+pub fn unescape(value : &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            out.push(chr);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => (),
+        }
+    }
+    out
+}
+
+fn write_option(value : Option<&str>) -> String {
+    value.map(escape).unwrap_or_default()
+}
+
+fn read_option(value : &str) -> Option<String> {
+    if value.is_empty() { None } else { Some(unescape(value)) }
+}
+
+fn write_list(values : &[String]) -> String {
+    let separator = LIST_SEPARATOR.to_string();
+    escape(&values.join(&separator))
+}
+
+fn read_list(value : &str) -> Vec<String> {
+    if value.is_empty() {
+        return Vec::new();
+    }
+    unescape(value).split(LIST_SEPARATOR).map(String::from).collect()
+}
+
+fn write_number<T : ToString>(value : Option<T>) -> String {
+    value.map(|x| x.to_string()).unwrap_or_default()
+}
+
+fn read_number<T : std::str::FromStr>(value : &str) -> Option<Option<T>> {
+    if value.is_empty() { Some(None) } else { value.parse().ok().map(Some) }
+}
+
+fn write_entry(key : &path::Path, entry : &Entry) -> String {
+    let meta = &entry.meta;
+    let source = entry.source.as_ref();
+    let fields = [
+        escape(&key.to_string_lossy()),
+        entry.stat.size.to_string(),
+        entry.stat.mtime_secs.to_string(),
+        entry.stat.mtime_nanos.to_string(),
+        write_list(&meta.artists),
+        write_list(&meta.features),
+        write_option(meta.album.as_deref()),
+        write_option(meta.album_author.as_deref()),
+        meta.track_number.as_ref().map(|x| x.0.to_string()).unwrap_or_default(),
+        meta.year.map(|x| x.to_string()).unwrap_or_default(),
+        write_option(meta.title.as_deref()),
+        write_option(meta.file_name.as_deref()),
+        write_option(source.map(|x| x.url.as_str())),
+        write_option(source.and_then(|x| x.extractor.as_deref())),
+        write_option(source.and_then(|x| x.date.as_deref())),
+        write_number(meta.audio.duration),
+        write_number(meta.audio.bitrate),
+        write_number(meta.audio.sample_rate),
+        write_number(meta.audio.channels),
+        write_option(meta.audio.codec.as_deref()),
+        write_option(entry.collection_author.as_deref()),
+        entry.kind.name().to_string(),
+        write_option(entry.audio_hash.as_deref()),
+    ];
+    fields.join("\t")
+}
+
+fn parse_entry(line : &str) -> Option<(path::PathBuf, Entry)> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    if fields.len() != 23 {
+        return None;
+    }
+    let stat = FileStat {
+        size : fields[1].parse().ok()?,
+        mtime_secs : fields[2].parse().ok()?,
+        mtime_nanos : fields[3].parse().ok()?,
+    };
+    let mut meta = TrackMeta::empty();
+    meta.artists = read_list(fields[4]);
+    meta.features = read_list(fields[5]);
+    meta.album = read_option(fields[6]);
+    meta.album_author = read_option(fields[7]);
+    if !fields[8].is_empty() {
+        meta.set_track_number(fields[8].parse().ok()?);
+    }
+    if !fields[9].is_empty() {
+        meta.year = Some(fields[9].parse().ok()?);
+    }
+    meta.title = read_option(fields[10]);
+    meta.file_name = read_option(fields[11]);
+    meta.audio.duration = read_number(fields[15])?;
+    meta.audio.bitrate = read_number(fields[16])?;
+    meta.audio.sample_rate = read_number(fields[17])?;
+    meta.audio.channels = read_number(fields[18])?;
+    meta.audio.codec = read_option(fields[19]);
+    let source = read_option(fields[12]).map(|url| Source {
+        url,
+        extractor : read_option(fields[13]),
+        date : read_option(fields[14]),
+    });
+    let collection_author = read_option(fields[20]);
+    let kind = Kind::from_name(fields[21])?;
+    let audio_hash = read_option(fields[22]);
+    let entry = Entry { stat, kind, meta, source, collection_author, audio_hash };
+    Some((path::PathBuf::from(unescape(fields[0])), entry))
+}
\ No newline at end of file
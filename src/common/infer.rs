@@ -1,6 +1,7 @@
 use std::fs;
 use std::path;
 use std::collections::HashMap;
+use crate::common::meta::TrackMeta;
 
 use log;
 
@@ -35,7 +36,7 @@ impl Database {
         self.lookup.insert(path_buf.clone(), id);
         let collection = Collection {
             path : path_buf,
-            id : id,
+            id,
             id_parent : parent_id,
             depth : parent_depth + 1,
             has_files : false,
@@ -44,16 +45,6 @@ impl Database {
         Some(&mut self.collections[id])
     }
 
-    pub fn add_collection(&mut self, path : &path::Path) -> Option<&mut Collection> {
-        match fs::canonicalize(path) {
-            Ok(canon_path) => self.add_collection_canon(canon_path.as_path()),
-            Err(err) => {
-                log::warn!("failed to canonicalise directory: {}\n{}", path.display(), err);
-                None
-            },
-        }
-    }
-
     pub fn add_file_canon(&mut self, path : &path::Path) -> Option<&mut File> {
         let collection = path.parent().and_then(|x| self.add_collection_canon(x)).unwrap();
         collection.has_files = true;
@@ -61,7 +52,7 @@ impl Database {
         let id = self.files.len();
         let file = File {
             path : path.to_path_buf(),
-            id : id,
+            id,
             id_collection : collection_id,
         };
         self.files.push(file);
@@ -83,6 +74,19 @@ impl Database {
     }
 }
 
+/// Returns the album artist that a file gives to the collection it's stored
+/// in. Only files inside of a directory named after their album count, so
+/// that loose tracks don't decide where a whole directory is sorted.
+pub fn collection_author(file_path : &path::Path, meta : &TrackMeta) -> Option<String> {
+    let album = meta.album.as_ref()?;
+    let album_expect = file_path.parent()?.file_name()?;
+    if album_expect.eq_ignore_ascii_case(album) {
+        meta.album_author.clone()
+    } else {
+        None
+    }
+}
+
 pub type CollectionID = usize;
 
 #[derive(Debug)]
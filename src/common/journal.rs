@@ -0,0 +1,231 @@
+use std::fs;
+use std::io::Write;
+use std::path;
+use std::time;
+use crate::common;
+use crate::common::companion;
+use crate::common::index;
+use crate::common::playlist;
+use crate::common::tags;
+
+const JOURNAL_NAME : &str = "journal.jsonl";
+
+/// A file or directory which was moved, relative to the library root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub from : path::PathBuf,
+    pub to : path::PathBuf,
+}
+
+impl Move {
+    /// Returns where a path ended up after this move, if it was moved.
+    pub fn apply(&self, file_path : &path::Path) -> Option<path::PathBuf> {
+        let rel_path = file_path.strip_prefix(&self.from).ok()?;
+        if rel_path.as_os_str().is_empty() {
+            Some(self.to.clone())
+        } else {
+            Some(self.to.join(rel_path))
+        }
+    }
+
+    /// Returns the move which undoes this one.
+    pub fn reverse(&self) -> Self {
+        Self { from : self.to.clone(), to : self.from.clone() }
+    }
+}
+
+/// Returns where a path ended up after a sequence of moves.
+pub fn apply_moves(moves : &[Move], file_path : &path::Path) -> path::PathBuf {
+    let mut file_path = file_path.to_path_buf();
+    for move_ in moves {
+        if let Some(new_path) = move_.apply(&file_path) {
+            file_path = new_path;
+        }
+    }
+    file_path
+}
+
+/// A standard tag which was changed, on the file at the path it had at the
+/// time, relative to the library root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retag {
+    pub file : path::PathBuf,
+    pub field : tags::Field,
+    pub old : Option<String>,
+    pub new : Option<String>,
+}
+
+/// The changes made to the library by a single command, which are recorded
+/// in the journal inside of the `.catty` directory so they can be undone.
+#[derive(Debug)]
+pub struct Operation {
+    pub id : u64,
+    pub command : String,
+    pub moves : Vec<Move>,
+    pub retags : Vec<Retag>,
+    /// The directories which tracks were moved out of, so that their
+    /// companion files are only looked for once.
+    pub companion_dirs : companion::Dirs,
+}
+
+impl Operation {
+    pub fn new(command : &str) -> Self {
+        Self {
+            id : 0,
+            command : command.to_string(),
+            moves : Vec::new(),
+            retags : Vec::new(),
+            companion_dirs : companion::Dirs::default(),
+        }
+    }
+
+    pub fn record_move(&mut self, from : &path::Path, to : &path::Path) {
+        self.moves.push(Move { from : index::index_key(from), to : index::index_key(to) });
+    }
+
+    /// Records tag changes which were written to a file. They must be
+    /// recorded before the file is moved, so that they are undone after it
+    /// has been moved back.
+    pub fn record_tags(&mut self, file : &path::Path, changes : &[tags::Change]) {
+        for change in changes {
+            self.retags.push(Retag {
+                file : index::index_key(file),
+                field : change.field,
+                old : change.old.clone(),
+                new : change.new.clone(),
+            });
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty() && self.retags.is_empty()
+    }
+
+    /// Appends the operation to the journal, then updates any playlists
+    /// which refer to moved files. The files have already been moved, so
+    /// failing to update a playlist doesn't fail the operation.
+    pub fn save(&mut self) -> common::Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.append()?;
+        if let Err(err) = playlist::follow_moves(&self.moves) {
+            log::warn!("failed to update playlists\nreason = {}", err);
+        }
+        Ok(())
+    }
+
+    /// Appends the operation to the journal as it is, without updating any
+    /// playlists.
+    pub fn append(&mut self) -> common::Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let operations = read()?;
+        self.id = operations.last().map_or(1, |x| x.id + 1);
+        let time = time::SystemTime::now()
+                .duration_since(time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+        fs::create_dir_all(common::DATA_DIR)?;
+        let mut out = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(journal_path())?;
+        for move_ in &self.moves {
+            let record = serde_json::json!({
+                "id" : self.id,
+                "time" : time,
+                "command" : self.command,
+                "action" : "move",
+                "from" : move_.from.to_string_lossy(),
+                "to" : move_.to.to_string_lossy(),
+            });
+            writeln!(out, "{}", record)?;
+        }
+        for retag in &self.retags {
+            let record = serde_json::json!({
+                "id" : self.id,
+                "time" : time,
+                "command" : self.command,
+                "action" : "tag",
+                "path" : retag.file.to_string_lossy(),
+                "field" : retag.field.key(),
+                "old" : retag.old,
+                "new" : retag.new,
+            });
+            writeln!(out, "{}", record)?;
+        }
+        log::debug!("recorded {} moves and {} tag changes in the journal as operation {}",
+                self.moves.len(), self.retags.len(), self.id);
+        Ok(())
+    }
+}
+
+fn journal_path() -> path::PathBuf {
+    path::Path::new(common::DATA_DIR).join(JOURNAL_NAME)
+}
+
+/// Reads every operation in the journal, oldest first.
+pub fn read() -> common::Result<Vec<Operation>> {
+    let text = match fs::read_to_string(journal_path()) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut operations : Vec<Operation> = Vec::new();
+    for line in text.lines().filter(|x| !x.trim().is_empty()) {
+        let record = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(record) => record,
+            Err(err) => {
+                log::warn!("skipping malformed journal entry: {:?}\n{}", line, err);
+                continue;
+            },
+        };
+        let id = record["id"].as_u64().unwrap_or_default();
+        let command = record["command"].as_str().unwrap_or_default();
+        let operation = match operations.last_mut() {
+            Some(operation) if operation.id == id => operation,
+            _ => {
+                operations.push(Operation::new(command));
+                operations.last_mut().unwrap().id = id;
+                operations.last_mut().unwrap()
+            },
+        };
+        let text = |key : &str| record[key].as_str();
+        let field = text("field").and_then(tags::Field::from_name);
+        match (text("action"), text("from"), text("to"), text("path"), field) {
+            (Some("move"), Some(from), Some(to), _, _) => operation.moves.push(Move {
+                from : path::PathBuf::from(from),
+                to : path::PathBuf::from(to),
+            }),
+            (Some("tag"), _, _, Some(file), Some(field)) => operation.retags.push(Retag {
+                file : path::PathBuf::from(file),
+                field,
+                old : text("old").map(String::from),
+                new : text("new").map(String::from),
+            }),
+            _ => log::debug!("skipping journal entry: {}", line),
+        }
+    }
+    Ok(operations)
+}
+
+/// Removes the most recent operation from the journal, returning it.
+pub fn pop() -> common::Result<Option<Operation>> {
+    let mut operations = read()?;
+    let last = match operations.pop() {
+        Some(last) => last,
+        None => return Ok(None),
+    };
+    let text = fs::read_to_string(journal_path())?;
+    let kept = text.lines()
+            .filter(|line| {
+                let record = serde_json::from_str::<serde_json::Value>(line).ok();
+                record.is_some_and(|x| x["id"].as_u64() != Some(last.id))
+            })
+            .map(|x| format!("{}\n", x))
+            .collect::<String>();
+    fs::write(journal_path(), kept)?;
+    Ok(Some(last))
+}
\ No newline at end of file
@@ -0,0 +1,175 @@
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path;
+use std::process;
+use std::collections::HashMap;
+use crate::common;
+
+const CACHE_NAME : &str = "loudness.tsv";
+const CACHE_HEADER : &str = "catty-loudness 1";
+
+/// The loudness that ReplayGain 2.0 adjusts tracks to, in LUFS.
+pub const REFERENCE_LOUDNESS : f64 = -18.0;
+
+/// The loudness that the R128 gain tags of Opus files adjust tracks to, in
+/// LUFS.
+pub const R128_REFERENCE_LOUDNESS : f64 = -23.0;
+
+/// The EBU R128 loudness of a track or album.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Analysis {
+    /// Integrated loudness, in LUFS.
+    pub loudness : f64,
+    /// True peak, in dBFS.
+    pub peak : f64,
+    /// Duration, in seconds.
+    pub duration : f64,
+}
+
+impl Analysis {
+    /// Returns the gain which brings the audio to the reference loudness, in dB.
+    pub fn gain(&self) -> f64 {
+        REFERENCE_LOUDNESS - self.loudness
+    }
+
+    /// Returns the gain which brings the audio to the R128 reference loudness,
+    /// as the Q7.8 fixed point number of dB stored in Opus files.
+    pub fn r128_gain(&self) -> i16 {
+        ((R128_REFERENCE_LOUDNESS - self.loudness) * 256.0).round()
+                .clamp(i16::MIN as f64, i16::MAX as f64) as i16
+    }
+
+    /// Returns the peak as a fraction of full scale, as stored by ReplayGain.
+    pub fn peak_ratio(&self) -> f64 {
+        10f64.powf(self.peak / 20.0)
+    }
+}
+
+/// Combines the analysis of the tracks of an album. Loudness is averaged by
+/// energy, weighted by the duration of each track.
+pub fn combine(tracks : &[Analysis]) -> Option<Analysis> {
+    if tracks.is_empty() {
+        return None;
+    }
+    let total_duration = tracks.iter().map(|x| x.duration).sum::<f64>();
+    let weight = |track : &Analysis| if total_duration > 0.0 { track.duration } else { 1.0 };
+    let total_weight = tracks.iter().map(weight).sum::<f64>();
+    let energy = tracks.iter()
+            .map(|x| weight(x) * 10f64.powf(x.loudness / 10.0))
+            .sum::<f64>() / total_weight;
+    Some(Analysis {
+        loudness : 10.0 * energy.log10(),
+        peak : tracks.iter().map(|x| x.peak).fold(f64::NEG_INFINITY, f64::max),
+        duration : total_duration,
+    })
+}
+
+/// Measures the loudness of a file by decoding it with the `ebur128` filter
+/// of `ffmpeg`.
+pub fn analyse(ffmpeg_path : &path::Path, file_path : &path::Path) -> common::Result<Analysis> {
+    let mut proc = process::Command::new(ffmpeg_path);
+    proc.args(["-hide_banner", "-nostats", "-i"]);
+    proc.arg(file_path);
+    // per-frame measurements are only logged at the verbose level
+    proc.args(["-map", "0:a:0", "-filter:a", "ebur128=peak=true:framelog=verbose", "-f", "null", "-"]);
+    proc.stdin(process::Stdio::null());
+    log::debug!("running process with args: {:?}", proc.get_args());
+    let output = proc.output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!("failed to measure loudness of '{}'\n{}", file_path.display(), stderr).into());
+    }
+    parse_ebur128(&stderr).ok_or_else(|| format!("failed to read the loudness of '{}' from ffmpeg", file_path.display()).into())
+}
+
+fn parse_ebur128(output : &str) -> Option<Analysis> {
+    let duration = output.lines()
+            .find_map(|x| x.trim().strip_prefix("Duration:"))
+            .and_then(|x| x.split(',').next())
+            .and_then(parse_duration)
+            .unwrap_or(0.0);
+    let (_, summary) = output.rsplit_once("Summary:")?;
+    let value = |key : &str| summary.lines()
+            .find_map(|x| x.trim().strip_prefix(key))
+            .and_then(|x| x.split_whitespace().next())
+            .and_then(|x| x.parse::<f64>().ok());
+    let loudness = value("I:")?;
+    // silence has no loudness, which is reported as `-70.0` or `-inf`
+    let peak = value("Peak:").unwrap_or(f64::NEG_INFINITY);
+    Some(Analysis { loudness, peak, duration })
+}
+
+/// Parses a duration in the form `HH:MM:SS.xx`.
+fn parse_duration(text : &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    for part in text.trim().split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+/// A persistent cache of the loudness of each file, keyed by the hash of its
+/// audio so that changing its tags doesn't require decoding it again.
+#[derive(Debug)]
+pub struct Cache {
+    entries : HashMap<String, Analysis>,
+    dirty : bool,
+}
+
+impl Cache {
+    fn cache_path() -> path::PathBuf {
+        path::Path::new(common::DATA_DIR).join(CACHE_NAME)
+    }
+
+    pub fn load() -> Self {
+        let mut cache = Self { entries : HashMap::new(), dirty : false };
+        let text = match fs::read_to_string(Self::cache_path()) {
+            Ok(text) => text,
+            Err(_) => return cache,
+        };
+        let mut lines = text.lines();
+        if lines.next() != Some(CACHE_HEADER) {
+            log::warn!("loudness cache is from a different version of catty, ignoring it");
+            return cache;
+        }
+        for line in lines {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let values = fields.get(1..4)
+                    .and_then(|x| x.iter().map(|x| x.parse::<f64>().ok()).collect::<Option<Vec<_>>>());
+            match (fields.first(), values.as_deref()) {
+                (Some(hash), Some(&[loudness, peak, duration])) => {
+                    cache.entries.insert(hash.to_string(), Analysis { loudness, peak, duration });
+                },
+                _ => log::warn!("skipping malformed loudness cache entry: {:?}", line),
+            }
+        }
+        cache
+    }
+
+    pub fn save(&mut self) -> common::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        fs::create_dir_all(common::DATA_DIR)?;
+        let mut out = BufWriter::new(fs::File::create(Self::cache_path())?);
+        writeln!(out, "{}", CACHE_HEADER)?;
+        let mut hashes = self.entries.keys().collect::<Vec<_>>();
+        hashes.sort();
+        for hash in hashes {
+            let analysis = &self.entries[hash];
+            writeln!(out, "{}\t{}\t{}\t{}", hash, analysis.loudness, analysis.peak, analysis.duration)?;
+        }
+        out.flush()?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn get(&self, hash : &str) -> Option<Analysis> {
+        self.entries.get(hash).copied()
+    }
+
+    pub fn insert(&mut self, hash : String, analysis : Analysis) {
+        self.entries.insert(hash, analysis);
+        self.dirty = true;
+    }
+}
\ No newline at end of file
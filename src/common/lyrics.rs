@@ -0,0 +1,51 @@
+use std::path;
+use std::sync::OnceLock;
+
+/// Returns where the `.lrc` file of a track is kept.
+pub fn lrc_path(file_path : &path::Path) -> path::PathBuf {
+    file_path.with_extension("lrc")
+}
+
+fn timestamp_regex() -> &'static regex::Regex {
+    static RE : OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"^\[(\d+):(\d{1,2})(?:[.:](\d{1,3}))?\]").unwrap())
+}
+
+/// Parses the timed lines of LRC lyrics, in milliseconds, sorted by time.
+/// Lines can have several timestamps, e.g. `[00:12.00][01:30.00]chorus`.
+/// Returns nothing if the lyrics aren't synchronised.
+pub fn parse_synced(text : &str) -> Vec<(u32, String)> {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+        while let Some(captures) = timestamp_regex().captures(rest) {
+            let minutes = captures[1].parse::<u32>().unwrap_or_default();
+            let seconds = captures[2].parse::<u32>().unwrap_or_default();
+            // fractions can be in tenths, hundredths or thousandths
+            let millis = captures.get(3).map_or(0, |x| {
+                let digits = x.as_str();
+                digits.parse::<u32>().unwrap_or_default() * 10u32.pow(3 - digits.len() as u32)
+            });
+            times.push(minutes * 60_000 + seconds * 1000 + millis);
+            rest = &rest[captures[0].len()..];
+        }
+        for time in times {
+            lines.push((time, rest.trim().to_string()));
+        }
+    }
+    lines.sort_by_key(|x| x.0);
+    lines
+}
+
+/// Formats timed lines as LRC lyrics.
+pub fn format_synced(lines : &[(u32, String)]) -> String {
+    let mut text = String::new();
+    for (time, line) in lines {
+        let minutes = time / 60_000;
+        let seconds = time / 1000 % 60;
+        let hundredths = time % 1000 / 10;
+        text.push_str(&format!("[{:02}:{:02}.{:02}]{}\n", minutes, seconds, hundredths, line));
+    }
+    text
+}
\ No newline at end of file
@@ -1,12 +1,14 @@
 use std::path;
 use std::collections::HashSet;
+use std::sync::OnceLock;
 use crate::common;
+use crate::common::probe::AudioProperties;
 
 use audiotags;
 use log;
 use regex;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TrackMeta {
     re_split : regex::Regex,
     re_split_fallback : regex::Regex,
@@ -19,12 +21,14 @@ pub struct TrackMeta {
     pub album : Option<String>,
     pub album_author : Option<String>,
     pub track_number : Option<(usize, String)>,
+    pub year : Option<i32>,
     pub title : Option<String>,
     pub file_name : Option<String>,
+    pub audio : AudioProperties,
 }
 
 impl TrackMeta {
-    pub fn get_author<'a>(&'a self) -> Option<&'a str> {
+    pub fn get_author(&self) -> Option<&str> {
         if let Some(album_author) = &self.album_author {
             return Some(album_author);
         }
@@ -41,11 +45,25 @@ impl TrackMeta {
         None
     }
 
+    /// Creates empty track metadata, to be filled in with values which have
+    /// already been inferred, e.g. those stored in the library index.
+    pub fn empty() -> Self {
+        Self::new()
+    }
+
+    pub fn set_track_number(&mut self, track_number : usize) {
+        self.track_number = None;
+        self.from_track_number(track_number);
+    }
 }
 
+#[allow(clippy::redundant_static_lifetimes)]
 pub const DEFAULT_CATEGORY : &'static str = ".other";
+#[allow(clippy::redundant_static_lifetimes)]
 pub const DEFAULT_AUTHOR : &'static str = "unknown";
+#[allow(clippy::redundant_static_lifetimes)]
 pub const DEFAULT_AUTHOR_ID : &'static str = "id"; // "in development"
+#[allow(clippy::redundant_static_lifetimes)]
 pub const DEFAULT_TITLE : &'static str = "untitled";
 
 pub fn get_category_name(author : &str) -> &'static str {
@@ -62,6 +80,35 @@ pub fn get_category_name(author : &str) -> &'static str {
     }
 }
 
+/// Returns the directory that `sort` moves a track into, based on its author
+/// and album.
+pub fn get_sort_dir(meta : &TrackMeta) -> path::PathBuf {
+    let mut dir = path::PathBuf::new();
+    // add author
+    if let Some(author) = meta.get_author() {
+        dir.push(get_category_name(author));
+        dir.push(author);
+    } else {
+        dir.push(DEFAULT_CATEGORY);
+        dir.push(".unknown");
+    }
+    // add album
+    if let Some(album) = &meta.album {
+        dir.push(album);
+    }
+    dir
+}
+
+/// Normalises text for comparison, so that differences in case and
+/// punctuation are ignored.
+pub fn normalise(text : &str) -> String {
+    text.to_lowercase()
+            .split(|x : char| !x.is_alphanumeric())
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+}
+
 macro_rules! impl_metadata {
     ($from:expr, $into:expr) => {
         if $from.is_none() {
@@ -70,8 +117,16 @@ macro_rules! impl_metadata {
     }
 }
 
+// the `from_*` methods fill in whichever fields are still missing
+#[allow(clippy::wrong_self_convention)]
 impl TrackMeta {
     fn new() -> Self {
+        // compiling the regexes is expensive, so only do it once
+        static TEMPLATE : OnceLock<TrackMeta> = OnceLock::new();
+        TEMPLATE.get_or_init(Self::new_template).clone()
+    }
+
+    fn new_template() -> Self {
         Self {
             // i considered having '—' be a separator, but i think they're used
             // too commonly in japanese text to make it reliable
@@ -98,8 +153,10 @@ impl TrackMeta {
             album : None,
             album_author : None,
             track_number : None,
+            year : None,
             title : None,
             file_name : None,
+            audio : AudioProperties::default(),
         }
     }
 
@@ -196,6 +253,7 @@ pub fn parse(file_path : &path::Path) -> common::Result<TrackMeta> {
     if let Some(file_name) = file_path.file_name().and_then(|x| x.to_str()) {
         meta.from_file_name(file_name);
     }
+    meta.audio = common::probe::probe(file_path);
     // parse audio tags
     let dirty_tag = audiotags::Tag::new().read_from_path(file_path);
     let mut tag_artist = None;
@@ -208,8 +266,9 @@ pub fn parse(file_path : &path::Path) -> common::Result<TrackMeta> {
             tag_title = tag.title().map(String::from);
             // these tags can be added immediately, because the file stem is
             // unlikely to contain them
-            tag.album_artist().map(|x| meta.from_album_author(x));
-            tag.track_number().map(|x| meta.from_track_number(x as usize));
+            if let Some(x) = tag.album_artist() { meta.from_album_author(x) }
+            if let Some(x) = tag.track_number() { meta.from_track_number(x as usize) }
+            meta.year = tag.year();
         }
         Err(audiotags::Error::IOError(err)) => return Err(Box::new(err)),
         Err(err) => {
@@ -288,12 +347,12 @@ pub fn parse(file_path : &path::Path) -> common::Result<TrackMeta> {
         }
     }
     // now apply metadata
-    tag_album.as_ref().map(|x| meta.from_album(x));
-    stem_album.as_ref().map(|x| meta.from_album(x));
-    tag_title.as_ref().map(|x| meta.from_title(x));
-    stem_title.as_ref().map(|x| meta.from_title(x));
-    stem_artist.as_ref().map(|x| meta.from_artist(x)); // order is important here!
-    tag_artist.as_ref().map(|x| meta.from_artist(x));
+    if let Some(x) = tag_album.as_ref() { meta.from_album(x) }
+    if let Some(x) = stem_album.as_ref() { meta.from_album(x) }
+    if let Some(x) = tag_title.as_ref() { meta.from_title(x) }
+    if let Some(x) = stem_title.as_ref() { meta.from_title(x) }
+    if let Some(x) = stem_artist.as_ref() { meta.from_artist(x) } // order is important here!
+    if let Some(x) = tag_artist.as_ref() { meta.from_artist(x) }
     Ok(meta)
 }
 
@@ -0,0 +1,316 @@
+use std::fs;
+use std::path;
+use crate::common;
+use crate::common::index;
+use crate::common::journal::{ self, Move };
+use crate::common::meta::TrackMeta;
+use crate::common::walk;
+
+use clap::ValueEnum;
+
+/// Directory inside of the library where playlists are written, unless
+/// `playlist-dir` is set in the config.
+pub const DEFAULT_DIR : &str = "playlists";
+
+/// Extensions of the playlists which are kept up to date when files move.
+const M3U_EXTENSIONS : &[&str] = &["m3u", "m3u8"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// An extended M3U playlist, encoded as UTF-8.
+    M3u8,
+    /// An XML Shareable Playlist.
+    Xspf,
+}
+
+impl Format {
+    pub fn from_name(name : &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "m3u8" | "m3u" => Some(Self::M3u8),
+            "xspf" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::M3u8 => "m3u8",
+            Self::Xspf => "xspf",
+        }
+    }
+}
+
+/// A track listed in a playlist.
+pub struct Item<'a> {
+    /// The path of the track, relative to the library root.
+    pub path : &'a path::Path,
+    pub meta : &'a TrackMeta,
+}
+
+impl Item<'_> {
+    fn title(&self) -> String {
+        match &self.meta.title {
+            Some(title) => title.clone(),
+            None => self.path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+        }
+    }
+}
+
+/// Returns the directory playlists are written to.
+pub fn find_dir() -> path::PathBuf {
+    path::PathBuf::from(common::find_config("playlist-dir").unwrap_or(DEFAULT_DIR.to_string()))
+}
+
+/// Returns the path to `file_path` relative to the directory `from_dir`. Both
+/// paths must be relative to the library root.
+pub fn relative_path(from_dir : &path::Path, file_path : &path::Path) -> path::PathBuf {
+    let from = from_dir.components()
+            .filter(|x| !matches!(x, path::Component::CurDir))
+            .collect::<Vec<_>>();
+    let to = file_path.components()
+            .filter(|x| !matches!(x, path::Component::CurDir))
+            .collect::<Vec<_>>();
+    let common_len = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut rel_path = path::PathBuf::new();
+    for _ in common_len..from.len() {
+        rel_path.push("..");
+    }
+    for component in &to[common_len..] {
+        rel_path.push(component);
+    }
+    rel_path
+}
+
+/// Writes the items as a playlist stored inside of `dir`, so that entries can
+/// be relative to it.
+pub fn write(format : Format, name : &str, dir : &path::Path, items : &[Item]) -> String {
+    match format {
+        Format::M3u8 => write_m3u8(name, dir, items),
+        Format::Xspf => write_xspf(name, dir, items),
+    }
+}
+
+fn write_m3u8(name : &str, dir : &path::Path, items : &[Item]) -> String {
+    let mut out = String::new();
+    out.push_str("#EXTM3U\n");
+    out.push_str(&format!("#PLAYLIST:{}\n", name));
+    for item in items {
+        let title = if item.meta.artists.is_empty() {
+            item.title()
+        } else {
+            format!("{} - {}", item.meta.artists.join(", "), item.title())
+        };
+        // players accept `-1` when the duration isn't known
+        let duration = item.meta.audio.duration.map_or(-1, |x| x.round() as i64);
+        out.push_str(&format!("#EXTINF:{},{}\n", duration, title.replace('\n', " ")));
+        out.push_str(&relative_path(dir, item.path).to_string_lossy());
+        out.push('\n');
+    }
+    out
+}
+
+fn write_xspf(name : &str, dir : &path::Path, items : &[Item]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+    out.push_str(&format!("  <title>{}</title>\n", escape_xml(name)));
+    out.push_str("  <trackList>\n");
+    for item in items {
+        let location = encode_uri_path(&relative_path(dir, item.path).to_string_lossy());
+        out.push_str("    <track>\n");
+        out.push_str(&format!("      <location>{}</location>\n", escape_xml(&location)));
+        out.push_str(&format!("      <title>{}</title>\n", escape_xml(&item.title())));
+        if !item.meta.artists.is_empty() {
+            out.push_str(&format!("      <creator>{}</creator>\n", escape_xml(&item.meta.artists.join(", "))));
+        }
+        if let Some(album) = &item.meta.album {
+            out.push_str(&format!("      <album>{}</album>\n", escape_xml(album)));
+        }
+        if let Some((track_number, _)) = &item.meta.track_number {
+            out.push_str(&format!("      <trackNum>{}</trackNum>\n", track_number));
+        }
+        if let Some(duration) = item.meta.audio.duration {
+            out.push_str(&format!("      <duration>{}</duration>\n", (duration * 1000.0).round() as u64));
+        }
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n");
+    out.push_str("</playlist>\n");
+    out
+}
+
+fn escape_xml(text : &str) -> String {
+    text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&apos;")
+}
+
+/// Percent-encodes a relative path so it can be used as a URI.
+fn encode_uri_path(rel_path : &str) -> String {
+    let mut out = String::new();
+    for byte in rel_path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+pub fn is_m3u_file(file_path : &path::Path) -> bool {
+    file_path.extension()
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| M3U_EXTENSIONS.iter().any(|ext| x.eq_ignore_ascii_case(ext)))
+}
+
+/// Finds every M3U playlist in the library, respecting any `.cattyignore`
+/// files.
+pub fn find_m3u_playlists() -> common::Result<Vec<path::PathBuf>> {
+    let mut playlists = Vec::new();
+    walk::Walker::new(true, None).visit(path::Path::new("."), &mut |file| {
+        if is_m3u_file(file) {
+            playlists.push(file.to_path_buf());
+        }
+        Ok(())
+    })?;
+    Ok(playlists)
+}
+
+/// A file listed in an M3U playlist.
+#[derive(Debug)]
+pub struct M3uEntry {
+    /// The line the entry was read from.
+    pub line : usize,
+    /// The path of the file, relative to the library root.
+    pub path : path::PathBuf,
+    /// The title from the `#EXTINF` line before the entry, if there is one.
+    pub title : Option<String>,
+}
+
+/// An M3U playlist, kept line by line so that entries can be rewritten
+/// without losing anything else in the file.
+#[derive(Debug)]
+pub struct M3u {
+    pub path : path::PathBuf,
+    lines : Vec<String>,
+    crlf : bool,
+}
+
+impl M3u {
+    pub fn read(file_path : &path::Path) -> common::Result<Self> {
+        let text = fs::read_to_string(file_path)?;
+        let text = text.strip_prefix('\u{FEFF}').unwrap_or(&text);
+        Ok(Self {
+            path : file_path.to_path_buf(),
+            lines : text.lines().map(String::from).collect(),
+            crlf : text.contains("\r\n"),
+        })
+    }
+
+    /// Returns the directory of the playlist, relative to the library root.
+    pub fn dir(&self) -> path::PathBuf {
+        let dir = self.path.parent().unwrap_or(path::Path::new(""));
+        index::index_key(dir)
+    }
+
+    /// Returns every entry which refers to a file inside of the library, with
+    /// relative entries resolved against `dir`.
+    pub fn entries(&self, dir : &path::Path) -> Vec<M3uEntry> {
+        let mut entries = Vec::new();
+        let mut title = None;
+        for (n, line) in self.lines.iter().enumerate() {
+            let line = line.trim();
+            if let Some(info) = line.strip_prefix("#EXTINF:") {
+                title = info.split_once(',').map(|x| x.1.trim().to_string());
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(file_path) = resolve_entry(dir, line) {
+                entries.push(M3uEntry { line : n, path : file_path, title : title.take() });
+            }
+            title = None;
+        }
+        entries
+    }
+
+    /// Points an entry at a new file, relative to the playlist unless the
+    /// entry was absolute. Returns whether the entry changed.
+    pub fn set_entry(&mut self, line : usize, file_path : &path::Path) -> bool {
+        let old_entry = self.lines[line].trim();
+        let new_entry = if path::Path::new(old_entry).is_absolute() {
+            match std::env::current_dir() {
+                Ok(cwd) => cwd.join(file_path),
+                Err(_) => return false,
+            }
+        } else {
+            relative_path(&self.dir(), file_path)
+        };
+        let new_entry = new_entry.to_string_lossy().into_owned();
+        if new_entry == old_entry {
+            return false;
+        }
+        self.lines[line] = new_entry;
+        true
+    }
+
+    pub fn save(&self) -> common::Result<()> {
+        let newline = if self.crlf { "\r\n" } else { "\n" };
+        let mut text = self.lines.join(newline);
+        text.push_str(newline);
+        fs::write(&self.path, text)?;
+        Ok(())
+    }
+}
+
+/// Resolves an entry of a playlist stored in `dir` to a path relative to the
+/// library root. Entries outside of the library, such as URLs, are skipped.
+fn resolve_entry(dir : &path::Path, entry : &str) -> Option<path::PathBuf> {
+    if entry.contains("://") {
+        return None;
+    }
+    let entry = path::Path::new(entry);
+    if entry.is_absolute() {
+        let rel_path = index::index_key(entry);
+        return if rel_path.is_absolute() { None } else { Some(rel_path) };
+    }
+    walk::rel_to_library(&dir.join(entry))
+}
+
+/// Rewrites the entries of every M3U playlist in the library which refer to
+/// files that have moved, including playlists which were moved themselves.
+pub fn follow_moves(moves : &[Move]) -> common::Result<()> {
+    let reverse = moves.iter().rev().map(Move::reverse).collect::<Vec<_>>();
+    for playlist_path in find_m3u_playlists()? {
+        let mut playlist = match M3u::read(&playlist_path) {
+            Ok(playlist) => playlist,
+            Err(err) => {
+                log::warn!("failed to read playlist '{}'\nreason = {}", playlist_path.display(), err);
+                continue;
+            },
+        };
+        // relative entries point to where the playlist was before it moved
+        let old_dir = journal::apply_moves(&reverse, &playlist.dir());
+        let has_moved = old_dir != playlist.dir();
+        let mut n_changed = 0;
+        for entry in playlist.entries(&old_dir) {
+            let new_path = journal::apply_moves(moves, &entry.path);
+            // leave entries alone unless they would no longer point at the file
+            let is_relative = !path::Path::new(playlist.lines[entry.line].trim()).is_absolute();
+            if new_path == entry.path && !(has_moved && is_relative) {
+                continue;
+            }
+            if playlist.set_entry(entry.line, &new_path) {
+                n_changed += 1;
+            }
+        }
+        if n_changed > 0 {
+            playlist.save()?;
+            log::info!("updated {} entries in playlist: {}", n_changed, playlist_path.display());
+        }
+    }
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,357 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path;
+use std::process;
+use std::sync::OnceLock;
+use crate::common;
+use crate::common::sniff::{self, Format, Kind};
+
+/// How many bytes after any ID3v2 tags are searched for the first MPEG frame.
+const MPEG_SEARCH_SIZE : usize = 16 * 1024;
+
+/// The technical properties of the audio in a file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioProperties {
+    /// Duration, in seconds.
+    pub duration : Option<f64>,
+    /// Average bitrate, in kbit/s.
+    pub bitrate : Option<u32>,
+    /// Sample rate, in Hz.
+    pub sample_rate : Option<u32>,
+    pub channels : Option<u32>,
+    /// The name of the codec as `ffprobe` names it, e.g. `mp3`, `aac`, `flac`
+    /// or `opus`. Uncompressed audio is always `pcm`.
+    pub codec : Option<String>,
+}
+
+impl AudioProperties {
+    /// Returns whether the codec is lossless.
+    pub fn is_lossless(&self) -> bool {
+        self.codec.as_deref().is_some_and(|x| matches!(x, "flac" | "alac" | "pcm" | "wavpack" | "ape" | "tta"))
+    }
+
+    fn is_complete(&self) -> bool {
+        self.duration.is_some() && self.bitrate.is_some() && self.sample_rate.is_some()
+                && self.channels.is_some() && self.codec.is_some()
+    }
+
+    /// Fills in any properties which are missing using another probe.
+    fn merge(&mut self, other : AudioProperties) {
+        self.duration = self.duration.or(other.duration);
+        self.bitrate = self.bitrate.or(other.bitrate);
+        self.sample_rate = self.sample_rate.or(other.sample_rate);
+        self.channels = self.channels.or(other.channels);
+        self.codec = self.codec.take().or(other.codec);
+    }
+
+    /// Fills in the bitrate from the size of the file, for formats which
+    /// don't store it.
+    fn estimate_bitrate(&mut self, audio_size : u64) {
+        if let (None, Some(duration)) = (self.bitrate, self.duration) {
+            if duration > 0.0 {
+                self.bitrate = Some((audio_size as f64 * 8.0 / duration / 1000.0).round() as u32);
+            }
+        }
+    }
+}
+
+/// Reads the technical properties of a file. Common formats are parsed
+/// directly, and everything else is handed to `ffprobe` if it's installed.
+/// Properties which can't be found are left empty.
+pub fn probe(file_path : &path::Path) -> AudioProperties {
+    let format = match sniff::sniff(file_path) {
+        Ok(Kind::Audio(format)) => Some(format),
+        _ => None,
+    };
+    let native = match format {
+        Some(Format::Mp3) => probe_mpeg(file_path),
+        Some(Format::Flac) => probe_flac(file_path),
+        Some(Format::Mp4) => probe_mp4(file_path),
+        Some(Format::Wav) => probe_wav(file_path),
+        _ => Ok(AudioProperties::default()),
+    };
+    let mut properties = native.unwrap_or_else(|err| {
+        log::debug!("failed to read audio properties of '{}'\nreason = {}", file_path.display(), err);
+        AudioProperties::default()
+    });
+    if !properties.is_complete() {
+        if let Some(ffprobe_path) = ffprobe_path() {
+            match probe_ffprobe(ffprobe_path, file_path) {
+                Ok(other) => properties.merge(other),
+                Err(err) => log::debug!("failed to probe '{}'\nreason = {}", file_path.display(), err),
+            }
+        }
+    }
+    if properties.codec.is_none() {
+        properties.codec = format.and_then(codec_name).map(String::from);
+    }
+    properties
+}
+
+/// The codec that a format always uses, if there is only one.
+fn codec_name(format : Format) -> Option<&'static str> {
+    Some(match format {
+        Format::Mp3 => "mp3",
+        Format::Aac => "aac",
+        Format::Flac | Format::OggFlac => "flac",
+        Format::Vorbis => "vorbis",
+        Format::Opus => "opus",
+        Format::Speex => "speex",
+        Format::Ape => "ape",
+        Format::WavPack => "wavpack",
+        Format::Wav | Format::Aiff => "pcm",
+        Format::Mp4 | Format::Matroska | Format::WebM => return None,
+    })
+}
+
+/// `ffprobe` is only looked for once, since a missing installation would
+/// otherwise be searched for again for every file.
+fn ffprobe_path() -> Option<&'static path::Path> {
+    static PATH : OnceLock<Option<path::PathBuf>> = OnceLock::new();
+    PATH.get_or_init(common::find_ffprobe_path).as_deref()
+}
+
+fn probe_ffprobe(ffprobe_path : &path::Path, file_path : &path::Path) -> common::Result<AudioProperties> {
+    let mut proc = process::Command::new(ffprobe_path);
+    proc.args(["-v", "error", "-of", "json", "-show_entries",
+            "format=duration,bit_rate:stream=codec_type,codec_name,sample_rate,channels,bit_rate"]);
+    proc.arg(file_path);
+    proc.stdin(process::Stdio::null());
+    log::debug!("running process with args: {:?}", proc.get_args());
+    let output = proc.output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned().into());
+    }
+    let json = serde_json::from_slice::<serde_json::Value>(&output.stdout)?;
+    let stream = json["streams"].as_array()
+            .and_then(|x| x.iter().find(|x| x["codec_type"] == "audio"))
+            .ok_or("file has no audio streams")?;
+    // numbers are written as strings, except for the channel count
+    let number = |value : &serde_json::Value| match value {
+        serde_json::Value::String(text) => text.parse::<f64>().ok(),
+        other => other.as_f64(),
+    };
+    let bitrate = number(&stream["bit_rate"]).or_else(|| number(&json["format"]["bit_rate"]));
+    Ok(AudioProperties {
+        duration : number(&json["format"]["duration"]),
+        bitrate : bitrate.map(|x| (x / 1000.0).round() as u32),
+        sample_rate : number(&stream["sample_rate"]).map(|x| x as u32),
+        channels : number(&stream["channels"]).map(|x| x as u32),
+        codec : stream["codec_name"].as_str()
+                .map(|x| if x.starts_with("pcm_") { "pcm" } else { x })
+                .map(String::from),
+    })
+}
+
+/// Reads the first MPEG audio frame, and the Xing or VBRI header inside of it
+/// which variable bitrate encoders use to store the number of frames.
+fn probe_mpeg(file_path : &path::Path) -> common::Result<AudioProperties> {
+    let mut file = fs::File::open(file_path)?;
+    let file_len = file.metadata()?.len();
+    let mut id3_header = [0u8; 10];
+    let id3_len = match file.read_exact(&mut id3_header) {
+        Ok(()) => sniff::id3_prefix_len(&id3_header) as u64,
+        Err(_) => 0,
+    };
+    let mut end = file_len;
+    if end >= id3_len + 128 {
+        let mut id3v1 = [0u8; 3];
+        file.seek(SeekFrom::Start(end - 128))?;
+        file.read_exact(&mut id3v1)?;
+        if &id3v1 == b"TAG" {
+            end -= 128;
+        }
+    }
+    file.seek(SeekFrom::Start(id3_len))?;
+    let mut data = Vec::with_capacity(MPEG_SEARCH_SIZE);
+    file.take(MPEG_SEARCH_SIZE as u64).read_to_end(&mut data)?;
+    let (offset, header) = (0..data.len())
+            .find_map(|i| MpegHeader::parse(&data[i..]).map(|x| (i, x)))
+            .ok_or("cannot find an MPEG audio frame")?;
+    let audio_size = end.saturating_sub(id3_len + offset as u64);
+    let frame = &data[offset..];
+    let mut properties = AudioProperties {
+        sample_rate : Some(header.sample_rate),
+        channels : Some(header.channels),
+        codec : Some("mp3".to_string()),
+        ..AudioProperties::default()
+    };
+    if let Some(n_frames) = header.vbr_frames(frame) {
+        properties.duration = Some(n_frames as f64 * header.samples_per_frame as f64 / header.sample_rate as f64);
+        properties.estimate_bitrate(audio_size);
+    } else {
+        // without a VBR header, the first frame's bitrate is used for all of them
+        properties.duration = Some(audio_size as f64 * 8.0 / (header.bitrate as f64 * 1000.0));
+        properties.bitrate = Some(header.bitrate);
+    }
+    Ok(properties)
+}
+
+#[derive(Debug)]
+struct MpegHeader {
+    is_mpeg1 : bool,
+    bitrate : u32,
+    sample_rate : u32,
+    channels : u32,
+    samples_per_frame : u32,
+}
+
+impl MpegHeader {
+    /// Parses the header of a layer III frame.
+    fn parse(data : &[u8]) -> Option<Self> {
+        if data.len() < 4 || data[0] != 0xFF || data[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+        let version = (data[1] >> 3) & 0x03;
+        let layer = (data[1] >> 1) & 0x03;
+        let bitrate_index = (data[2] >> 4) as usize;
+        let rate_index = ((data[2] >> 2) & 0x03) as usize;
+        if version == 0x01 || layer != 0x01 || bitrate_index == 0
+                || bitrate_index == 0x0F || rate_index == 0x03 {
+            return None;
+        }
+        const BITRATES_V1 : [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+        const BITRATES_V2 : [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+        const RATES : [u32; 3] = [44100, 48000, 32000];
+        let is_mpeg1 = version == 0x03;
+        let (bitrate, sample_rate) = match version {
+            0x03 => (BITRATES_V1[bitrate_index], RATES[rate_index]),
+            0x02 => (BITRATES_V2[bitrate_index], RATES[rate_index] / 2),
+            _ => (BITRATES_V2[bitrate_index], RATES[rate_index] / 4),
+        };
+        Some(Self {
+            is_mpeg1,
+            bitrate,
+            sample_rate,
+            channels : if data[3] >> 6 == 0x03 { 1 } else { 2 },
+            samples_per_frame : if is_mpeg1 { 1152 } else { 576 },
+        })
+    }
+
+    /// Returns the number of frames stored in a Xing, Info or VBRI header.
+    fn vbr_frames(&self, frame : &[u8]) -> Option<u32> {
+        let read_u32 = |pos : usize| frame.get(pos..pos + 4).map(|x| u32::from_be_bytes(x.try_into().unwrap()));
+        let side_info_len = match (self.is_mpeg1, self.channels) {
+            (true, 1) => 17,
+            (true, _) => 32,
+            (false, 1) => 9,
+            (false, _) => 17,
+        };
+        let xing = 4 + side_info_len;
+        if matches!(frame.get(xing..xing + 4), Some(b"Xing" | b"Info")) {
+            let flags = read_u32(xing + 4)?;
+            return if flags & 0x01 != 0 { read_u32(xing + 8).filter(|x| *x > 0) } else { None };
+        }
+        let vbri = 4 + 32;
+        if frame.get(vbri..vbri + 4) == Some(b"VBRI") {
+            return read_u32(vbri + 14).filter(|x| *x > 0);
+        }
+        None
+    }
+}
+
+fn probe_flac(file_path : &path::Path) -> common::Result<AudioProperties> {
+    let tag = metaflac::Tag::read_from_path(file_path)?;
+    let info = tag.get_streaminfo().ok_or("file has no stream info")?;
+    let mut properties = AudioProperties {
+        sample_rate : Some(info.sample_rate),
+        channels : Some(info.num_channels as u32),
+        codec : Some("flac".to_string()),
+        ..AudioProperties::default()
+    };
+    // streams of unknown length store a total of 0 samples
+    if info.total_samples > 0 && info.sample_rate > 0 {
+        properties.duration = Some(info.total_samples as f64 / info.sample_rate as f64);
+    }
+    properties.estimate_bitrate(fs::metadata(file_path)?.len());
+    Ok(properties)
+}
+
+fn probe_mp4(file_path : &path::Path) -> common::Result<AudioProperties> {
+    let tag = mp4ameta::Tag::read_from_path(file_path)?;
+    let mut file = fs::File::open(file_path)?;
+    let codec = sniff::read_mp4_moov(&mut file)?.and_then(|moov| mp4_codec(&moov));
+    let mut properties = AudioProperties {
+        duration : tag.duration().map(|x| x.as_secs_f64()),
+        bitrate : tag.avg_bitrate().filter(|x| *x > 0).map(|x| (x as f64 / 1000.0).round() as u32),
+        sample_rate : tag.sample_rate().map(|x| x.hz()),
+        channels : tag.channel_config().map(|x| x.channel_count() as u32),
+        codec,
+    };
+    properties.estimate_bitrate(file.metadata()?.len());
+    Ok(properties)
+}
+
+/// Finds the codec of the first track inside of a `moov` box, using its
+/// sample description.
+fn mp4_codec(data : &[u8]) -> Option<String> {
+    let mut data = data;
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        let size = if size == 0 { data.len() } else { size };
+        if size < 8 || size > data.len() {
+            return None;
+        }
+        let body = &data[8..size];
+        match &data[4..8] {
+            b"trak" | b"mdia" | b"minf" | b"stbl" => {
+                if let Some(codec) = mp4_codec(body) {
+                    return Some(codec);
+                }
+            },
+            // skips the version, entry count and size of the first entry
+            b"stsd" if body.len() >= 16 => return Some(match &body[12..16] {
+                b"mp4a" => "aac".to_string(),
+                b"alac" => "alac".to_string(),
+                b"fLaC" => "flac".to_string(),
+                b"Opus" => "opus".to_string(),
+                b".mp3" => "mp3".to_string(),
+                b"ac-3" => "ac3".to_string(),
+                b"ec-3" => "eac3".to_string(),
+                other => String::from_utf8_lossy(other).trim().to_lowercase(),
+            }),
+            _ => (),
+        }
+        data = &data[size..];
+    }
+    None
+}
+
+/// Reads the `fmt ` and `data` chunks of a WAV file, without reading the
+/// audio itself.
+fn probe_wav(file_path : &path::Path) -> common::Result<AudioProperties> {
+    let mut file = fs::File::open(file_path)?;
+    let mut pos = 12;
+    let mut format = None;
+    let mut data_size = None;
+    let file_len = file.metadata()?.len();
+    while pos + 8 <= file_len && (format.is_none() || data_size.is_none()) {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+        let size = u32::from_le_bytes(header[4..].try_into().unwrap()) as u64;
+        match &header[..4] {
+            b"fmt " if size >= 16 => {
+                let mut fmt = [0u8; 16];
+                file.read_exact(&mut fmt)?;
+                format = Some(fmt);
+            },
+            // RF64 files store the real size elsewhere, so use the rest of the file
+            b"data" => data_size = Some(if size == u32::MAX as u64 { file_len - pos - 8 } else { size }),
+            _ => (),
+        }
+        // chunks are padded to an even length
+        pos += 8 + size + (size & 1);
+    }
+    let fmt = format.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "file has no `fmt ` chunk"))?;
+    let channels = u16::from_le_bytes(fmt[2..4].try_into().unwrap()) as u32;
+    let sample_rate = u32::from_le_bytes(fmt[4..8].try_into().unwrap());
+    let byte_rate = u32::from_le_bytes(fmt[8..12].try_into().unwrap());
+    Ok(AudioProperties {
+        duration : data_size.filter(|_| byte_rate > 0).map(|x| x as f64 / byte_rate as f64),
+        bitrate : Some((byte_rate as f64 * 8.0 / 1000.0).round() as u32),
+        sample_rate : Some(sample_rate),
+        channels : Some(channels),
+        codec : Some("pcm".to_string()),
+    })
+}
\ No newline at end of file
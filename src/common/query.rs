@@ -0,0 +1,255 @@
+use std::path;
+use std::cmp::Ordering;
+use crate::common;
+use crate::common::index::Entry;
+
+/// A metadata field that can be queried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Artist,
+    Feature,
+    Album,
+    AlbumArtist,
+    Title,
+    Track,
+    Year,
+    Path,
+    File,
+    Source,
+    Extractor,
+    Duration,
+    Bitrate,
+    SampleRate,
+    Channels,
+    Codec,
+}
+
+impl Field {
+    pub fn from_name(name : &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        Some(match name.as_str() {
+            "artist" | "artists" => Self::Artist,
+            "feature" | "features" | "feat" => Self::Feature,
+            "album" => Self::Album,
+            "album_artist" | "albumartist" | "album_author" | "author" => Self::AlbumArtist,
+            "title" => Self::Title,
+            "track" | "track_number" | "number" => Self::Track,
+            "year" => Self::Year,
+            "path" => Self::Path,
+            "file" | "file_name" | "name" => Self::File,
+            "source" | "url" => Self::Source,
+            "extractor" => Self::Extractor,
+            "duration" | "length" => Self::Duration,
+            "bitrate" => Self::Bitrate,
+            "sample_rate" | "samplerate" => Self::SampleRate,
+            "channels" => Self::Channels,
+            "codec" => Self::Codec,
+            _ => return None,
+        })
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Self::Track | Self::Year | Self::Duration | Self::Bitrate | Self::SampleRate | Self::Channels)
+    }
+
+    /// Returns every value of this field for a track.
+    pub fn values(self, file_path : &path::Path, entry : &Entry) -> Vec<String> {
+        let meta = &entry.meta;
+        let source = entry.source.as_ref();
+        match self {
+            Self::Artist => meta.artists.clone(),
+            Self::Feature => meta.features.clone(),
+            Self::Album => meta.album.iter().cloned().collect(),
+            Self::AlbumArtist => meta.album_author.iter().cloned().collect(),
+            Self::Title => meta.title.iter().cloned().collect(),
+            Self::Track => meta.track_number.iter().map(|x| x.0.to_string()).collect(),
+            Self::Year => meta.year.iter().map(|x| x.to_string()).collect(),
+            Self::Path => vec![file_path.to_string_lossy().into_owned()],
+            Self::File => file_path.file_name().iter().map(|x| x.to_string_lossy().into_owned()).collect(),
+            Self::Source => source.iter().map(|x| x.url.clone()).collect(),
+            Self::Extractor => source.and_then(|x| x.extractor.clone()).into_iter().collect(),
+            // durations are compared in whole seconds
+            Self::Duration => meta.audio.duration.iter().map(|x| (x.round() as i64).to_string()).collect(),
+            Self::Bitrate => meta.audio.bitrate.iter().map(|x| x.to_string()).collect(),
+            Self::SampleRate => meta.audio.sample_rate.iter().map(|x| x.to_string()).collect(),
+            Self::Channels => meta.audio.channels.iter().map(|x| x.to_string()).collect(),
+            Self::Codec => meta.audio.codec.iter().cloned().collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Condition {
+    /// A bare word, which matches if any text field contains it.
+    Text(String),
+    Equals(Field, String),
+    Matches(Field, regex::Regex),
+    Compare(Field, Ordering, bool, i64),
+    Missing(Field),
+}
+
+#[derive(Debug)]
+struct Term {
+    negate : bool,
+    condition : Condition,
+}
+
+/// A query over the metadata of the library, written as a list of terms
+/// which must all match:
+///
+///  artist:"t+pazolite" album:~remix year>=2015 missing:album_artist
+///
+///  - `field:value` matches a field exactly, ignoring case.
+///  - `field:~pattern` matches a field against a regular expression.
+///  - `field>=N`, `field<N`, etc. compare numeric fields (`track`, `year`,
+///    `duration` in seconds, `bitrate` in kbit/s, `sample_rate`, `channels`).
+///  - `missing:field` and `has:field` check whether a field has a value.
+///  - Words without a field match if any text field contains them.
+///  - Terms prefixed with `-` are negated, and `OR` separates alternatives.
+#[derive(Debug)]
+pub struct Query {
+    alternatives : Vec<Vec<Term>>,
+}
+
+impl Query {
+    pub fn parse(source : &str) -> common::Result<Self> {
+        let mut alternatives = vec![Vec::new()];
+        for token in tokenise(source)? {
+            if token == "OR" || token == "|" {
+                alternatives.push(Vec::new());
+                continue;
+            }
+            alternatives.last_mut().unwrap().push(parse_term(&token)?);
+        }
+        if alternatives.iter().any(|x| x.is_empty()) && alternatives.len() > 1 {
+            return Err("`OR` must have a term on either side of it".into());
+        }
+        Ok(Self { alternatives })
+    }
+
+    pub fn matches(&self, file_path : &path::Path, entry : &Entry) -> bool {
+        self.alternatives.iter().any(|terms| {
+            terms.iter().all(|term| term.matches(file_path, entry) != term.negate)
+        })
+    }
+}
+
+impl Term {
+    fn matches(&self, file_path : &path::Path, entry : &Entry) -> bool {
+        match &self.condition {
+            Condition::Text(text) => {
+                let text = text.to_lowercase();
+                [Field::Artist, Field::Feature, Field::Album, Field::AlbumArtist, Field::Title, Field::File]
+                        .iter()
+                        .flat_map(|x| x.values(file_path, entry))
+                        .any(|x| x.to_lowercase().contains(&text))
+            },
+            Condition::Equals(field, value) => {
+                field.values(file_path, entry).iter().any(|x| {
+                    if field.is_numeric() {
+                        x.parse::<i64>().ok() == value.parse::<i64>().ok()
+                    } else {
+                        x.to_lowercase() == value.to_lowercase()
+                    }
+                })
+            },
+            Condition::Matches(field, pattern) => {
+                field.values(file_path, entry).iter().any(|x| pattern.is_match(x))
+            },
+            Condition::Compare(field, ordering, or_equal, value) => {
+                field.values(file_path, entry).iter().any(|x| {
+                    if let Ok(x) = x.parse::<i64>() {
+                        let cmp = x.cmp(value);
+                        cmp == *ordering || (*or_equal && cmp == Ordering::Equal)
+                    } else {
+                        false
+                    }
+                })
+            },
+            Condition::Missing(field) => field.values(file_path, entry).is_empty(),
+        }
+    }
+}
+
+/// Splits a query into terms, keeping quoted strings together.
+fn tokenise(source : &str) -> common::Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_quotes = false;
+    let mut has_token = false;
+    for chr in source.chars() {
+        match chr {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_token = true;
+            },
+            _ if chr.is_whitespace() && !in_quotes => {
+                if has_token {
+                    tokens.push(std::mem::take(&mut token));
+                    has_token = false;
+                }
+            },
+            _ => {
+                token.push(chr);
+                has_token = true;
+            },
+        }
+    }
+    if in_quotes {
+        return Err(format!("unterminated quote in query: {}", source).into());
+    }
+    if has_token {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_field(name : &str) -> common::Result<Field> {
+    Field::from_name(name).ok_or_else(|| format!("unknown query field '{}'", name).into())
+}
+
+fn parse_term(token : &str) -> common::Result<Term> {
+    let (negate, token) = match token.strip_prefix('-') {
+        Some(rest) if !rest.is_empty() => (true, rest),
+        _ => (false, token),
+    };
+    let re_term = regex::Regex::new(r"^([A-Za-z_]+)(:|>=|<=|>|<|=)(.*)$").unwrap();
+    let caps = if let Some(x) = re_term.captures(token) { x } else {
+        return Ok(Term { negate, condition : Condition::Text(token.to_string()) });
+    };
+    let (name, op, value) = (&caps[1], &caps[2], &caps[3]);
+    let condition = match (name.to_ascii_lowercase().as_str(), op) {
+        ("missing", ":") => Condition::Missing(parse_field(value)?),
+        ("has", ":") => return Ok(Term {
+            negate : !negate,
+            condition : Condition::Missing(parse_field(value)?),
+        }),
+        (_, ":" | "=") => {
+            let field = parse_field(name)?;
+            if let Some(pattern) = value.strip_prefix('~') {
+                let pattern = regex::RegexBuilder::new(pattern)
+                        .case_insensitive(true)
+                        .build()?;
+                Condition::Matches(field, pattern)
+            } else {
+                Condition::Equals(field, value.to_string())
+            }
+        },
+        (_, op) => {
+            let field = parse_field(name)?;
+            if !field.is_numeric() {
+                return Err(format!("field '{}' cannot be compared using '{}'", name, op).into());
+            }
+            let value = value.parse::<i64>()
+                    .map_err(|_| format!("expected a number after '{}{}'", name, op))?;
+            let (ordering, or_equal) = match op {
+                ">=" => (Ordering::Greater, true),
+                ">" => (Ordering::Greater, false),
+                "<=" => (Ordering::Less, true),
+                _ => (Ordering::Less, false),
+            };
+            Condition::Compare(field, ordering, or_equal, value)
+        },
+    };
+    Ok(Term { negate, condition })
+}
\ No newline at end of file
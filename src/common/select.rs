@@ -0,0 +1,134 @@
+use std::fs;
+use std::io::Read;
+use std::path;
+use std::collections::HashSet;
+use crate::common;
+use crate::common::index::Index;
+use crate::common::query::Query;
+use crate::common::walk::Walker;
+
+use clap::Args;
+
+/// Chooses which files a command operates on. Files can be selected by GLOB
+/// patterns, by a list of paths, and filtered by a metadata query.
+#[derive(Args, Debug, Default, Clone)]
+pub struct Selector {
+    /// The list of files to operate on (supports GLOB file path syntax).
+    pub file_paths : Vec<String>,
+    /// Only select files whose metadata matches this query, e.g.
+    /// `--where 'artist:"t+pazolite" missing:title'`. Searches the whole
+    /// library if no files are given.
+    #[arg(short = 'w', long = "where", value_name = "QUERY")]
+    pub query : Option<String>,
+    /// Read the files to operate on from this file, one path per line. Use `-`
+    /// to read from stdin (prompts will then need to be skipped with `--yes`).
+    #[arg(long, value_name = "FILE")]
+    pub from_list : Option<String>,
+    /// Search directories for files, including their subdirectories.
+    #[arg(short, long)]
+    pub recursive : bool,
+    /// Limit how many directories deep a recursive search can go (implies
+    /// `--recursive`).
+    #[arg(long, value_name = "DEPTH")]
+    pub max_depth : Option<usize>,
+}
+
+impl Selector {
+    /// Returns whether no files were explicitly selected.
+    pub fn is_empty(&self) -> bool {
+        self.file_paths.is_empty() && self.query.is_none() && self.from_list.is_none()
+                && !self.recursive && self.max_depth.is_none()
+    }
+
+    /// Collects every selected audio file, or every audio file in the library
+    /// if none were selected.
+    pub fn files_or_library(&self, index : &mut Index) -> common::Result<Vec<path::PathBuf>> {
+        if !self.is_empty() {
+            return self.files(index);
+        }
+        log::info!("no paths supplied, using every file in the library");
+        let mut files = Vec::new();
+        common::library_foreach(|file| {
+            files.push(file.to_path_buf());
+            Ok(())
+        })?;
+        Ok(files)
+    }
+
+    /// Collects every selected audio file. The index is used to look up the
+    /// metadata of files when filtering by a query.
+    pub fn files(&self, index : &mut Index) -> common::Result<Vec<path::PathBuf>> {
+        let query = match &self.query {
+            Some(query) => Some(Query::parse(query)?),
+            None => None,
+        };
+        let mut files = Vec::new();
+        let mut seen = HashSet::new();
+        let mut add_file = |file : &path::Path| {
+            if seen.insert(file.to_path_buf()) {
+                files.push(file.to_path_buf());
+            }
+            Ok(())
+        };
+        if let Some(list_path) = &self.from_list {
+            read_list(list_path, &mut add_file)?;
+        }
+        let mut walker = Walker::new(self.recursive, self.max_depth);
+        if !self.file_paths.is_empty() {
+            common::glob_foreach_many(&mut walker, &self.file_paths, &mut add_file)?;
+        } else if self.from_list.is_none() {
+            if query.is_some() && !walker.is_recursive() {
+                log::info!("no paths supplied, searching every file in the library");
+                common::library_foreach(&mut add_file)?;
+            } else {
+                common::glob_foreach_many(&mut walker, &[], &mut add_file)?;
+            }
+        }
+        if let Some(query) = &query {
+            let mut matches = Vec::new();
+            for file in files {
+                match index.get(&file) {
+                    Ok(entry) => if query.matches(&file, entry) {
+                        matches.push(file);
+                    },
+                    Err(err) => {
+                        log::warn!("failed to read file '{}'\nreason = {}", file.display(), err);
+                    },
+                }
+            }
+            log::info!("{} files matched the query", matches.len());
+            files = matches;
+        }
+        Ok(files)
+    }
+}
+
+fn read_list(
+    list_path : &str,
+    mut f : impl FnMut(&path::Path) -> common::Result<()>,
+) -> common::Result<()> {
+    let list = if list_path == "-" {
+        let mut list = String::new();
+        std::io::stdin().read_to_string(&mut list)?;
+        list
+    } else {
+        fs::read_to_string(list_path)?
+    };
+    for line in list.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let file = path::Path::new(line);
+        if !file.is_file() {
+            log::warn!("listed file does not exist, skipping: {}", file.display());
+            continue;
+        }
+        if common::is_audio_file(file) {
+            f(file)?;
+        } else {
+            log::info!("skipping non-audio file: {}", file.display());
+        }
+    }
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,373 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path;
+
+/// How many bytes are read from the start of a file to identify it.
+const HEADER_SIZE : usize = 4096;
+
+/// The largest `moov` box or Matroska header that will be searched for the
+/// list of tracks.
+const MAX_TRACKS_SIZE : u64 = 16 * 1024 * 1024;
+
+/// An audio format that can be identified from the contents of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Mp3,
+    Aac,
+    Flac,
+    OggFlac,
+    Vorbis,
+    Opus,
+    Speex,
+    Mp4,
+    Wav,
+    Aiff,
+    Matroska,
+    WebM,
+    Ape,
+    WavPack,
+}
+
+impl Format {
+    pub const ALL : [Self; 14] = [
+        Self::Mp3, Self::Aac, Self::Flac, Self::OggFlac, Self::Vorbis, Self::Opus, Self::Speex,
+        Self::Mp4, Self::Wav, Self::Aiff, Self::Matroska, Self::WebM, Self::Ape, Self::WavPack,
+    ];
+
+    /// The file extensions which are appropriate for this format. The first
+    /// extension is the one used when fixing a file's extension.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::Mp3 => &["mp3"],
+            Self::Aac => &["aac"],
+            Self::Flac => &["flac"],
+            Self::OggFlac => &["oga", "ogg"],
+            Self::Vorbis => &["ogg", "oga"],
+            Self::Opus => &["opus", "ogg", "oga"],
+            Self::Speex => &["spx", "ogg", "oga"],
+            Self::Mp4 => &["m4a", "m4b", "m4p", "mp4", "alac", "aac"],
+            Self::Wav => &["wav", "wave", "rf64"],
+            Self::Aiff => &["aiff", "aif", "aifc"],
+            Self::Matroska => &["mka", "mkv"],
+            Self::WebM => &["webm", "weba"],
+            Self::Ape => &["ape"],
+            Self::WavPack => &["wv"],
+        }
+    }
+
+    /// Returns whether a file extension is appropriate for this format.
+    pub fn accepts_extension(self, ext : &str) -> bool {
+        self.extensions().iter().any(|x| x.eq_ignore_ascii_case(ext))
+    }
+
+    /// Returns whether this format can only store lossless audio.
+    pub fn is_lossless(self) -> bool {
+        matches!(self, Self::Flac | Self::OggFlac | Self::Wav | Self::Aiff | Self::Ape | Self::WavPack)
+    }
+
+    /// A short name for this format, suitable for displaying in a table.
+    pub fn name(self) -> &'static str {
+        self.extensions()[0]
+    }
+}
+
+/// What the contents of a file were identified as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// An audio file, without any video tracks.
+    Audio(Format),
+    /// A container with at least one video track.
+    Video,
+    /// Anything that isn't recognised.
+    Unknown,
+}
+
+impl Kind {
+    /// A short name for this kind, used to store it in the library index.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Audio(format) => format.name(),
+            Self::Video => "video",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    pub fn from_name(name : &str) -> Option<Self> {
+        match name {
+            "video" => Some(Self::Video),
+            "unknown" => Some(Self::Unknown),
+            _ => Format::ALL.into_iter().find(|x| x.name() == name).map(Self::Audio),
+        }
+    }
+}
+
+/// Identifies the kind of a file by reading its contents, ignoring its file
+/// extension entirely.
+pub fn sniff(file_path : &path::Path) -> io::Result<Kind> {
+    let mut file = fs::File::open(file_path)?;
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    (&mut file).take(HEADER_SIZE as u64).read_to_end(&mut header)?;
+    // ID3v2 tags can be prepended to almost anything, so skip past them,
+    // reading the header again from after the tags if they're larger than it
+    let mut offset = 0;
+    loop {
+        let id3_len = id3_prefix_len(&header);
+        if id3_len == 0 {
+            break;
+        }
+        offset += id3_len as u64;
+        if id3_len + 10 <= header.len() {
+            // every tag fits in the header
+            header.drain(..id3_len);
+            break;
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        header.clear();
+        (&mut file).take(HEADER_SIZE as u64).read_to_end(&mut header)?;
+    }
+    let mut data = header.as_slice();
+    if offset > 0 {
+        // skip any padding left after the tag
+        let padding = data.iter().take_while(|x| **x == 0).count();
+        if padding < data.len() {
+            data = &data[padding..];
+        }
+    }
+    Ok(match data {
+        [b'f', b'L', b'a', b'C', ..] => Kind::Audio(Format::Flac),
+        [b'O', b'g', b'g', b'S', ..] => sniff_ogg(data),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..]
+        | [b'R', b'F', b'6', b'4', _, _, _, _, b'W', b'A', b'V', b'E', ..]
+            => Kind::Audio(Format::Wav),
+        [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', b'F' | b'C', ..]
+            => Kind::Audio(Format::Aiff),
+        [b'M', b'A', b'C', b' ', ..] => Kind::Audio(Format::Ape),
+        [b'w', b'v', b'p', b'k', ..] => Kind::Audio(Format::WavPack),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => sniff_mp4(&mut file)?,
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => sniff_matroska(&mut file)?,
+        _ if is_mpeg_audio(data) => Kind::Audio(Format::Mp3),
+        _ if is_adts(data) => Kind::Audio(Format::Aac),
+        _ => Kind::Unknown,
+    })
+}
+
+/// Returns the length of any ID3v2 tags at the start of a file, which may be
+/// longer than the data given.
+pub fn id3_prefix_len(data : &[u8]) -> usize {
+    let mut len = 0;
+    while data.len() >= len + 10 && &data[len..len + 3] == b"ID3" {
+        let header = &data[len..len + 10];
+        let size = header[6..].iter().fold(0usize, |acc, x| (acc << 7) | (*x & 0x7F) as usize);
+        let has_footer = header[5] & 0x10 != 0;
+        len += 10 + size + if has_footer { 10 } else { 0 };
+    }
+    len
+}
+
+/// Checks the first packet of an Ogg stream to find its codec.
+fn sniff_ogg(data : &[u8]) -> Kind {
+    if data.len() < 27 {
+        return Kind::Unknown;
+    }
+    let n_segments = data[26] as usize;
+    let packet = data.get(27 + n_segments..).unwrap_or(&[]);
+    if packet.starts_with(b"\x01vorbis") {
+        Kind::Audio(Format::Vorbis)
+    } else if packet.starts_with(b"OpusHead") {
+        Kind::Audio(Format::Opus)
+    } else if packet.starts_with(b"\x7FFLAC") {
+        Kind::Audio(Format::OggFlac)
+    } else if packet.starts_with(b"Speex   ") {
+        Kind::Audio(Format::Speex)
+    } else if packet.starts_with(b"\x80theora") || packet.starts_with(b"\x01video") {
+        Kind::Video
+    } else {
+        Kind::Unknown
+    }
+}
+
+/// Checks for a valid MPEG audio frame header, looking at the frame after it
+/// too so that random data is less likely to be mistaken for audio.
+fn is_mpeg_audio(data : &[u8]) -> bool {
+    fn frame_len(header : &[u8]) -> Option<usize> {
+        if header.len() < 4 || header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+        let version = (header[1] >> 3) & 0x03;
+        let layer = (header[1] >> 1) & 0x03;
+        let bitrate_index = (header[2] >> 4) as usize;
+        let rate_index = ((header[2] >> 2) & 0x03) as usize;
+        let padding = ((header[2] >> 1) & 0x01) as usize;
+        // only layer III is handled, since layers I and II are rare
+        if version == 0x01 || layer != 0x01 || bitrate_index == 0
+                || bitrate_index == 0x0F || rate_index == 0x03 {
+            return None;
+        }
+        const BITRATES_V1 : [usize; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+        const BITRATES_V2 : [usize; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+        const RATES : [usize; 3] = [44100, 48000, 32000];
+        let (bitrate, rate, factor) = match version {
+            0x03 => (BITRATES_V1[bitrate_index], RATES[rate_index], 144),
+            0x02 => (BITRATES_V2[bitrate_index], RATES[rate_index] / 2, 72),
+            _ => (BITRATES_V2[bitrate_index], RATES[rate_index] / 4, 72),
+        };
+        Some(factor * bitrate * 1000 / rate + padding)
+    }
+    match frame_len(data) {
+        Some(len) if len < data.len() => frame_len(&data[len..]).is_some(),
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// Checks for an ADTS header, used by raw AAC streams.
+fn is_adts(data : &[u8]) -> bool {
+    data.len() >= 7 && data[0] == 0xFF && data[1] & 0xF6 == 0xF0
+}
+
+/// Searches the `moov` box of an MP4 file for any video tracks.
+fn sniff_mp4(file : &mut fs::File) -> io::Result<Kind> {
+    let moov = if let Some(x) = read_mp4_moov(file)? { x } else {
+        return Ok(Kind::Unknown);
+    };
+    let mut handlers = Vec::new();
+    mp4_handlers(&moov, &mut handlers);
+    Ok(if handlers.iter().any(|x| x == b"vide") {
+        Kind::Video
+    } else if handlers.iter().any(|x| x == b"soun") {
+        Kind::Audio(Format::Mp4)
+    } else {
+        Kind::Unknown
+    })
+}
+
+/// Reads the body of the `moov` box of an MP4 file, which describes each of
+/// its tracks.
+pub fn read_mp4_moov(file : &mut fs::File) -> io::Result<Option<Vec<u8>>> {
+    let file_len = file.metadata()?.len();
+    let mut offset = 0;
+    // the `moov` box can be at either the start or the end of the file
+    while offset + 8 <= file_len {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[..8])?;
+        let mut size = u32::from_be_bytes(header[..4].try_into().unwrap()) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            file.read_exact(&mut header[8..])?;
+            size = u64::from_be_bytes(header[8..].try_into().unwrap());
+            header_len = 16;
+        } else if size == 0 {
+            size = file_len - offset;
+        }
+        if size < header_len {
+            break;
+        }
+        if &header[4..8] == b"moov" {
+            if size > MAX_TRACKS_SIZE {
+                break;
+            }
+            let mut moov = vec![0u8; (size - header_len) as usize];
+            file.read_exact(&mut moov)?;
+            return Ok(Some(moov));
+        }
+        offset += size;
+    }
+    Ok(None)
+}
+
+/// Collects the handler type of every track inside of a `moov` box.
+fn mp4_handlers(data : &[u8], handlers : &mut Vec<[u8; 4]>) {
+    let mut data = data;
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        let size = if size == 0 { data.len() } else { size };
+        if size < 8 || size > data.len() {
+            return;
+        }
+        let body = &data[8..size];
+        match &data[4..8] {
+            b"trak" | b"mdia" => mp4_handlers(body, handlers),
+            b"hdlr" if body.len() >= 12 => handlers.push(body[8..12].try_into().unwrap()),
+            _ => (),
+        }
+        data = &data[size..];
+    }
+}
+
+/// Searches the header of a Matroska file for any video tracks.
+fn sniff_matroska(file : &mut fs::File) -> io::Result<Kind> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut data = Vec::new();
+    file.take(MAX_TRACKS_SIZE).read_to_end(&mut data)?;
+    let mut doc_type = None;
+    let mut track_types = Vec::new();
+    ebml_walk(&data, &mut doc_type, &mut track_types);
+    let format = if doc_type.as_deref() == Some(b"webm".as_slice()) {
+        Format::WebM
+    } else {
+        Format::Matroska
+    };
+    Ok(if track_types.contains(&EBML_TRACK_VIDEO) {
+        Kind::Video
+    } else if track_types.contains(&EBML_TRACK_AUDIO) {
+        Kind::Audio(format)
+    } else {
+        Kind::Unknown
+    })
+}
+
+const EBML_HEADER : u64 = 0x1A45DFA3;
+const EBML_DOC_TYPE : u64 = 0x4282;
+const EBML_SEGMENT : u64 = 0x18538067;
+const EBML_TRACKS : u64 = 0x1654AE6B;
+const EBML_TRACK_ENTRY : u64 = 0xAE;
+const EBML_TRACK_TYPE : u64 = 0x83;
+const EBML_CLUSTER : u64 = 0x1F43B675;
+const EBML_TRACK_VIDEO : u64 = 1;
+const EBML_TRACK_AUDIO : u64 = 2;
+
+/// Reads a variable length integer, returning its value and length. Element
+/// IDs keep their length marker, whereas sizes do not.
+fn ebml_vint(data : &[u8], keep_marker : bool) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 || data.len() < len {
+        return None;
+    }
+    let mut value = if keep_marker { first as u64 } else { (first as u64) & (0xFF >> len) };
+    for byte in &data[1..len] {
+        value = (value << 8) | *byte as u64;
+    }
+    Some((value, len))
+}
+
+fn ebml_walk(data : &[u8], doc_type : &mut Option<Vec<u8>>, track_types : &mut Vec<u64>) -> bool {
+    let mut data = data;
+    while !data.is_empty() {
+        let (id, id_len) = if let Some(x) = ebml_vint(data, true) { x } else { return false };
+        let (size, size_len) = if let Some(x) = ebml_vint(&data[id_len..], false) { x } else { return false };
+        let body = &data[id_len + size_len..];
+        // elements of unknown size (all bits set) extend to the end of their parent
+        let unknown_size = size == (1u64 << (7 * size_len)) - 1;
+        let size = if unknown_size { body.len() } else { (size as usize).min(body.len()) };
+        let body = &body[..size];
+        match id {
+            EBML_HEADER | EBML_SEGMENT | EBML_TRACK_ENTRY
+                    if !ebml_walk(body, doc_type, track_types) => return false,
+            EBML_TRACKS => {
+                ebml_walk(body, doc_type, track_types);
+                // every track has been found, so the rest can be skipped
+                return false;
+            },
+            EBML_DOC_TYPE => *doc_type = Some(body.to_vec()),
+            EBML_TRACK_TYPE => {
+                track_types.push(body.iter().fold(0, |acc, x| (acc << 8) | *x as u64));
+            },
+            EBML_CLUSTER => return false,
+            _ => (),
+        }
+        data = &data[id_len + size_len + size..];
+    }
+    true
+}
\ No newline at end of file
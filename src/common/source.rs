@@ -0,0 +1,99 @@
+use std::fs;
+use std::io::Write;
+use std::path;
+use crate::common;
+use crate::common::index;
+use crate::common::tags;
+
+pub const FIELD_URL : &str = "SOURCE";
+pub const FIELD_EXTRACTOR : &str = "SOURCE_EXTRACTOR";
+pub const FIELD_DATE : &str = "SOURCE_DATE";
+
+const LOG_NAME : &str = "sources.tsv";
+
+/// Where a file was downloaded from, and when.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Source {
+    pub url : String,
+    pub extractor : Option<String>,
+    pub date : Option<String>,
+}
+
+impl Source {
+    /// Parses a tab-separated record of the form `url, extractor, date`.
+    /// Missing values are written by `yt-dlp` as `NA`.
+    pub fn from_record(record : &str) -> Option<Self> {
+        let mut parts = record.split('\t').map(|x| x.trim()).map(|x| {
+            if x.is_empty() || x == "NA" { None } else { Some(x.to_string()) }
+        });
+        Some(Self {
+            url : parts.next()??,
+            extractor : parts.next().flatten(),
+            date : parts.next().flatten(),
+        })
+    }
+
+    pub fn to_record(&self) -> String {
+        format!("{}\t{}\t{}", self.url,
+                self.extractor.as_deref().unwrap_or("NA"),
+                self.date.as_deref().unwrap_or("NA"))
+    }
+}
+
+/// Reads the source of a file from its tags.
+pub fn read(file_path : &path::Path) -> Option<Source> {
+    let fields = tags::read_fields(file_path).ok()?;
+    let find = |key : &str| fields.iter()
+            .find(|(x, _)| x.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.clone());
+    Some(Source {
+        url : find(FIELD_URL)?,
+        extractor : find(FIELD_EXTRACTOR),
+        date : find(FIELD_DATE),
+    })
+}
+
+/// Writes the source of a file to its tags.
+pub fn write(file_path : &path::Path, source : &Source) -> common::Result<()> {
+    tags::write_fields(file_path, &[
+        (FIELD_URL, Some(&source.url)),
+        (FIELD_EXTRACTOR, source.extractor.as_deref()),
+        (FIELD_DATE, source.date.as_deref()),
+    ])
+}
+
+/// Records the source of a newly downloaded file, both in its tags and in the
+/// sources log of the library. The log keeps a record of downloads for file
+/// types whose tags catty cannot write.
+pub fn record(file_path : &path::Path, source : &Source) -> common::Result<()> {
+    if let Err(err) = write(file_path, source) {
+        log::warn!("failed to write source to tags of file '{}'\nreason = {}",
+                file_path.display(), err);
+    }
+    fs::create_dir_all(common::DATA_DIR)?;
+    let mut log_file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path::Path::new(common::DATA_DIR).join(LOG_NAME))?;
+    writeln!(log_file, "{}\t{}", index::escape(&file_path.to_string_lossy()), source.to_record())?;
+    Ok(())
+}
+
+/// Searches the sources log for a file, matching on its path, or on its file
+/// name if the file has since been moved.
+pub fn find_in_log(file_path : &path::Path) -> Option<Source> {
+    let log_text = fs::read_to_string(path::Path::new(common::DATA_DIR).join(LOG_NAME)).ok()?;
+    let file_name = file_path.file_name()?;
+    let mut by_name = None;
+    for line in log_text.lines().rev() {
+        let (logged_path, record) = if let Some(x) = line.split_once('\t') { x } else { continue };
+        let logged_path = path::PathBuf::from(index::unescape(logged_path));
+        if logged_path == file_path {
+            return Source::from_record(record);
+        }
+        if by_name.is_none() && logged_path.file_name() == Some(file_name) {
+            by_name = Source::from_record(record);
+        }
+    }
+    by_name
+}
\ No newline at end of file
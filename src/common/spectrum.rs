@@ -0,0 +1,307 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path;
+use std::process;
+use std::collections::HashMap;
+use crate::common;
+
+const CACHE_NAME : &str = "quality.tsv";
+const CACHE_HEADER : &str = "catty-quality 1";
+
+/// The number of samples in each window of the spectrum.
+const FFT_SIZE : usize = 4096;
+
+/// How much of each track is decoded, in seconds.
+const MAX_DURATION : u32 = 120;
+
+/// How far below the loudest frequency the cutoff is measured, in dB.
+const CUTOFF_THRESHOLD : f64 = 60.0;
+
+/// How much quieter the audio just above the cutoff has to be than the audio
+/// just below it for the cutoff to count as a lowpass filter, in dB. Natural
+/// rolloff is much more gradual than this.
+const CLIFF_THRESHOLD : f64 = 30.0;
+
+/// How far on either side of the cutoff is compared, in Hz.
+const CLIFF_WIDTH : f64 = 1000.0;
+
+/// Cutoffs below this fraction of the Nyquist frequency are suspicious.
+const LOSSLESS_BANDWIDTH : f64 = 0.93;
+
+/// Lossy encoders cut off below this frequency, so a sudden drop above it is
+/// where the spectrum of a lower sample rate ends instead, in Hz.
+const UPSAMPLED_CUTOFF : f64 = 20_600.0;
+
+/// What the spectrum of a lossless file says about where its audio came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The spectrum reaches the highest frequency the sample rate can hold.
+    Full,
+    /// The spectrum ends at the lowpass filter of a lossy encoder.
+    Lossy,
+    /// The spectrum ends where a lower sample rate would, such as a hi-res
+    /// file made from CD audio. The audio is still lossless.
+    Upsampled,
+}
+
+impl Verdict {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Full => "ok",
+            Self::Lossy => "lossy",
+            Self::Upsampled => "upsampled",
+        }
+    }
+}
+
+/// Where the spectrum of a track ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Analysis {
+    /// The highest frequency with any significant content, in Hz.
+    pub cutoff : f64,
+    /// The sample rate the track was analysed at, in Hz.
+    pub sample_rate : u32,
+    /// Whether the spectrum ends in a sudden drop, like the lowpass filter
+    /// that lossy encoders apply.
+    pub is_cliff : bool,
+}
+
+impl Analysis {
+    /// Judges the audio by where its spectrum ends. A sudden drop well below
+    /// the Nyquist frequency means it was either encoded by a lossy codec or
+    /// upsampled at some point, depending on how high the drop is.
+    pub fn verdict(&self) -> Verdict {
+        if !self.is_cliff || self.cutoff >= LOSSLESS_BANDWIDTH * self.sample_rate as f64 / 2.0 {
+            Verdict::Full
+        } else if self.cutoff >= UPSAMPLED_CUTOFF {
+            Verdict::Upsampled
+        } else {
+            Verdict::Lossy
+        }
+    }
+
+    /// Returns whether the audio was likely encoded by a lossy codec at some
+    /// point.
+    pub fn is_lossy(&self) -> bool {
+        self.verdict() == Verdict::Lossy
+    }
+
+    /// Guesses the bitrate of the lossy source from the cutoffs that common
+    /// MP3 and AAC encoders use.
+    pub fn likely_source(&self) -> &'static str {
+        match self.cutoff {
+            x if x < 11_500.0 => "~64 kbps",
+            x if x < 15_500.0 => "~96 kbps",
+            x if x < 17_500.0 => "~128 kbps",
+            x if x < 19_500.0 => "~192 kbps",
+            _ => "~256-320 kbps",
+        }
+    }
+
+    /// Describes the result of the analysis in a few words.
+    pub fn describe(&self) -> String {
+        let cutoff = format!("{:.1} kHz", self.cutoff / 1000.0);
+        match self.verdict() {
+            Verdict::Full => format!("full spectrum up to {}", cutoff),
+            Verdict::Lossy => format!("likely transcoded from lossy audio, spectrum cuts off at {} (like {})",
+                    cutoff, self.likely_source()),
+            Verdict::Upsampled => format!("likely upsampled from a lower sample rate, spectrum cuts off at {}", cutoff),
+        }
+    }
+}
+
+/// Measures the spectrum of a file by decoding it to mono with `ffmpeg`. The
+/// audio isn't resampled, since resampling would apply a lowpass filter of
+/// its own.
+pub fn analyse(ffmpeg_path : &path::Path, file_path : &path::Path, sample_rate : u32) -> common::Result<Analysis> {
+    let mut proc = process::Command::new(ffmpeg_path);
+    proc.args(["-v", "error", "-i"]);
+    proc.arg(file_path);
+    proc.args(["-map", "0:a:0", "-t", &MAX_DURATION.to_string(), "-ac", "1", "-f", "f32le", "-"]);
+    proc.stdin(process::Stdio::null());
+    log::debug!("running process with args: {:?}", proc.get_args());
+    let output = proc.output()?;
+    if !output.status.success() {
+        return Err(format!("failed to decode '{}'\n{}", file_path.display(),
+                String::from_utf8_lossy(&output.stderr)).into());
+    }
+    let samples = output.stdout.chunks_exact(4)
+            .map(|x| f32::from_le_bytes(x.try_into().unwrap()) as f64)
+            .collect::<Vec<_>>();
+    let spectrum = power_spectrum(&samples)
+            .ok_or_else(|| format!("track is too short to analyse: {}", file_path.display()))?;
+    Ok(find_cutoff(&spectrum, sample_rate))
+}
+
+/// Averages the power spectrum of every window of the audio, in dB.
+fn power_spectrum(samples : &[f64]) -> Option<Vec<f64>> {
+    let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / FFT_SIZE as f64).cos())
+            .collect::<Vec<_>>();
+    let mut power = vec![0.0; FFT_SIZE / 2];
+    let mut n_windows = 0;
+    for chunk in samples.chunks_exact(FFT_SIZE) {
+        let mut re = chunk.iter().zip(&window).map(|(x, w)| x * w).collect::<Vec<_>>();
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im);
+        for (bin, total) in power.iter_mut().enumerate() {
+            *total += re[bin] * re[bin] + im[bin] * im[bin];
+        }
+        n_windows += 1;
+    }
+    if n_windows == 0 {
+        return None;
+    }
+    // smoothing hides the gaps between harmonics
+    const SMOOTHING : usize = 4;
+    let smoothed = (0..power.len())
+            .map(|bin| {
+                let range = &power[bin.saturating_sub(SMOOTHING)..(bin + SMOOTHING + 1).min(power.len())];
+                range.iter().sum::<f64>() / range.len() as f64
+            })
+            .map(|x| 10.0 * (x / n_windows as f64 + 1e-20).log10())
+            .collect();
+    Some(smoothed)
+}
+
+/// Finds the highest frequency which is within the threshold of the loudest
+/// frequency, ignoring the lowest bass.
+fn find_cutoff(spectrum : &[f64], sample_rate : u32) -> Analysis {
+    let bin_width = sample_rate as f64 / FFT_SIZE as f64;
+    let first_bin = (200.0 / bin_width) as usize;
+    let peak = spectrum[first_bin.min(spectrum.len() - 1)..].iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let cutoff_bin = (first_bin..spectrum.len())
+            .rev()
+            .find(|x| spectrum[*x] > peak - CUTOFF_THRESHOLD)
+            .unwrap_or(first_bin);
+    let width = (CLIFF_WIDTH / bin_width) as usize;
+    let mean = |bins : &[f64]| if bins.is_empty() { None } else { Some(bins.iter().sum::<f64>() / bins.len() as f64) };
+    let below = mean(&spectrum[cutoff_bin.saturating_sub(width)..cutoff_bin]);
+    let above = mean(&spectrum[(cutoff_bin + 1).min(spectrum.len())..(cutoff_bin + 1 + width).min(spectrum.len())]);
+    let is_cliff = match (below, above) {
+        (Some(below), Some(above)) => below - above > CLIFF_THRESHOLD,
+        _ => false,
+    };
+    Analysis { cutoff : cutoff_bin as f64 * bin_width, sample_rate, is_cliff }
+}
+
+/// An in-place radix-2 fast Fourier transform.
+fn fft(re : &mut [f64], im : &mut [f64]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// A persistent cache of the spectrum analysis of each file, keyed by the
+/// hash of its audio. The hashes come from the library index, so files which
+/// haven't changed aren't read again to find them.
+#[derive(Debug)]
+pub struct Cache {
+    entries : HashMap<String, Analysis>,
+    dirty : bool,
+}
+
+impl Cache {
+    fn cache_path() -> path::PathBuf {
+        path::Path::new(common::DATA_DIR).join(CACHE_NAME)
+    }
+
+    pub fn load() -> Self {
+        let mut cache = Self { entries : HashMap::new(), dirty : false };
+        let text = match fs::read_to_string(Self::cache_path()) {
+            Ok(text) => text,
+            Err(_) => return cache,
+        };
+        let mut lines = text.lines();
+        if lines.next() != Some(CACHE_HEADER) {
+            log::warn!("quality cache is from a different version of catty, ignoring it");
+            return cache;
+        }
+        for line in lines {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let analysis = match fields.as_slice() {
+                [_, cutoff, sample_rate, is_cliff] => (|| Some(Analysis {
+                    cutoff : cutoff.parse().ok()?,
+                    sample_rate : sample_rate.parse().ok()?,
+                    is_cliff : is_cliff.parse().ok()?,
+                }))(),
+                _ => None,
+            };
+            match analysis {
+                Some(analysis) => { cache.entries.insert(fields[0].to_string(), analysis); },
+                None => log::warn!("skipping malformed quality cache entry: {:?}", line),
+            }
+        }
+        cache
+    }
+
+    pub fn save(&mut self) -> common::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        fs::create_dir_all(common::DATA_DIR)?;
+        let mut out = BufWriter::new(fs::File::create(Self::cache_path())?);
+        writeln!(out, "{}", CACHE_HEADER)?;
+        let mut hashes = self.entries.keys().collect::<Vec<_>>();
+        hashes.sort();
+        for hash in hashes {
+            let analysis = &self.entries[hash];
+            writeln!(out, "{}\t{}\t{}\t{}", hash, analysis.cutoff, analysis.sample_rate, analysis.is_cliff)?;
+        }
+        out.flush()?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Returns the analysis of a file, only decoding it if its audio hasn't
+    /// been analysed before or `force` is enabled. Also returns whether the
+    /// file was decoded.
+    pub fn get_or_analyse(
+        &mut self,
+        index : &mut common::index::Index,
+        ffmpeg_path : &path::Path,
+        file_path : &path::Path,
+        sample_rate : u32,
+        force : bool,
+    ) -> common::Result<(Analysis, bool)> {
+        let audio_hash = index.audio_hash(file_path)?;
+        if !force {
+            if let Some(analysis) = self.entries.get(&audio_hash) {
+                return Ok((*analysis, false));
+            }
+        }
+        let analysis = analyse(ffmpeg_path, file_path, sample_rate)?;
+        self.entries.insert(audio_hash, analysis);
+        self.dirty = true;
+        Ok((analysis, true))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,294 @@
+use std::fs;
+use std::path;
+use std::process;
+
+/// A single track inside of a larger audio file.
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    pub start : f64,
+    pub end : Option<f64>,
+    pub title : String,
+    pub artist : Option<String>,
+}
+
+/// The tracks of an album, along with any album-level metadata found while
+/// parsing them.
+#[derive(Debug, Default)]
+pub struct Tracklist {
+    pub album : Option<String>,
+    pub album_author : Option<String>,
+    pub chapters : Vec<Chapter>,
+}
+
+impl Tracklist {
+    /// Fills in the end times of each chapter using the start time of the
+    /// chapter that follows it.
+    fn complete(mut self) -> Self {
+        self.chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+        let starts = self.chapters.iter().skip(1).map(|x| x.start).collect::<Vec<_>>();
+        for (chapter, next_start) in self.chapters.iter_mut().zip(starts) {
+            if chapter.end.is_none() {
+                chapter.end = Some(next_start);
+            }
+        }
+        self
+    }
+}
+
+/// Parses a `.cue` sheet. Only the `TITLE`, `PERFORMER`, `TRACK` and
+/// `INDEX 01` commands are supported, which is enough to split a single file.
+pub fn parse_cue(source : &str) -> Tracklist {
+    let mut tracklist = Tracklist::default();
+    let mut current : Option<Chapter> = None;
+    for line in source.lines() {
+        let line = line.trim();
+        let (command, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+        match command.to_ascii_uppercase().as_str() {
+            "TRACK" => {
+                if let Some(chapter) = current.take() {
+                    tracklist.chapters.push(chapter);
+                }
+                current = Some(Chapter {
+                    start : -1.0,
+                    end : None,
+                    title : String::new(),
+                    artist : None,
+                });
+            },
+            "TITLE" => {
+                let title = unquote(args).to_string();
+                match &mut current {
+                    Some(chapter) => chapter.title = title,
+                    None => tracklist.album = Some(title),
+                }
+            },
+            "PERFORMER" => {
+                let performer = unquote(args).to_string();
+                match &mut current {
+                    Some(chapter) => chapter.artist = Some(performer),
+                    None => tracklist.album_author = Some(performer),
+                }
+            },
+            "INDEX" => {
+                let mut parts = args.split_whitespace();
+                if parts.next() != Some("01") {
+                    continue;
+                }
+                if let (Some(chapter), Some(time)) = (&mut current, parts.next()) {
+                    if let Some(start) = parse_cue_time(time) {
+                        chapter.start = start;
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+    if let Some(chapter) = current.take() {
+        tracklist.chapters.push(chapter);
+    }
+    tracklist.chapters.retain(|x| x.start >= 0.0);
+    tracklist.complete()
+}
+
+fn unquote(value : &str) -> &str {
+    value.trim().trim_start_matches('"').trim_end_matches('"')
+}
+
+/// Cue sheets measure time in `mm:ss:ff`, where there are 75 frames per
+/// second.
+fn parse_cue_time(time : &str) -> Option<f64> {
+    let mut parts = time.split(':');
+    let minutes = parts.next()?.parse::<f64>().ok()?;
+    let seconds = parts.next()?.parse::<f64>().ok()?;
+    let frames = parts.next()?.parse::<f64>().ok()?;
+    Some(minutes * 60.0 + seconds + frames / 75.0)
+}
+
+/// Parses a tracklist of the form commonly pasted into video descriptions:
+///
+///  00:00 First Track
+///  03:12 - Second Track
+///  1:02:45 Third Track
+///
+/// Lines where the timestamp comes after the title are also accepted. Any
+/// line without a timestamp is ignored.
+pub fn parse_description(source : &str) -> Tracklist {
+    let re_leading = regex::Regex::new(
+        r"^(?:\d+[.)]\s*)?[\(\[]?((?:\d+:)?\d{1,2}:\d{2})[\)\]]?\s*[-–—:|]?\s*(.+)$"
+    ).unwrap();
+    let re_trailing = regex::Regex::new(
+        r"^(?:\d+[.)]\s*)?(.+?)\s*[-–—:|]?\s*[\(\[]?((?:\d+:)?\d{1,2}:\d{2})[\)\]]?$"
+    ).unwrap();
+    let mut tracklist = Tracklist::default();
+    for line in source.lines() {
+        let line = line.trim();
+        let (time, title) = if let Some(caps) = re_leading.captures(line) {
+            (caps.get(1).unwrap().as_str(), caps.get(2).unwrap().as_str())
+        } else if let Some(caps) = re_trailing.captures(line) {
+            (caps.get(2).unwrap().as_str(), caps.get(1).unwrap().as_str())
+        } else {
+            continue;
+        };
+        if let Some(start) = parse_timestamp(time) {
+            tracklist.chapters.push(Chapter {
+                start,
+                end : None,
+                title : title.trim().to_string(),
+                artist : None,
+            });
+        }
+    }
+    // a single timestamp is more likely to be a comment than a tracklist
+    if tracklist.chapters.len() < 2 {
+        tracklist.chapters.clear();
+    }
+    tracklist.complete()
+}
+
+/// Parses a timestamp of the form `hh:mm:ss` or `mm:ss`.
+pub fn parse_timestamp(time : &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    for part in time.split(':') {
+        seconds = seconds * 60.0 + part.trim().parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+/// Parses the chapters from the output of `ffmpeg -f ffmetadata`. This is
+/// where `yt-dlp --embed-chapters` stores its chapter data.
+pub fn parse_ffmetadata(source : &str) -> Tracklist {
+    let mut tracklist = Tracklist::default();
+    let mut current : Option<(Chapter, f64)> = None;
+    for line in source.lines() {
+        if line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if line.trim() == "[CHAPTER]" {
+            if let Some((chapter, _)) = current.take() {
+                tracklist.chapters.push(chapter);
+            }
+            current = Some((Chapter {
+                start : 0.0,
+                end : None,
+                title : String::new(),
+                artist : None,
+            }, 1.0 / 1000.0));
+            continue;
+        }
+        let (key, value) = if let Some(x) = line.split_once('=') { x } else { continue };
+        let value = unescape_ffmetadata(value);
+        match (&mut current, key.to_ascii_lowercase().as_str()) {
+            (Some((_, timebase)), "timebase") => {
+                if let Some((num, den)) = value.split_once('/') {
+                    if let (Ok(num), Ok(den)) = (num.parse::<f64>(), den.parse::<f64>()) {
+                        *timebase = num / den;
+                    }
+                }
+            },
+            (Some((chapter, timebase)), "start") => {
+                chapter.start = value.parse::<f64>().unwrap_or(0.0) * *timebase;
+            },
+            (Some((chapter, timebase)), "end") => {
+                chapter.end = value.parse::<f64>().ok().map(|x| x * *timebase);
+            },
+            (Some((chapter, _)), "title") => chapter.title = value,
+            (Some((chapter, _)), "artist") => chapter.artist = Some(value),
+            (None, "album") => tracklist.album = Some(value),
+            (None, "title") if tracklist.album.is_none() => tracklist.album = Some(value),
+            (None, "album_artist") => tracklist.album_author = Some(value),
+            (None, "artist") if tracklist.album_author.is_none() => {
+                tracklist.album_author = Some(value);
+            },
+            _ => (),
+        }
+    }
+    if let Some((chapter, _)) = current.take() {
+        tracklist.chapters.push(chapter);
+    }
+    tracklist.complete()
+}
+
+fn unescape_ffmetadata(value : &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(chr) = chars.next() {
+        if chr == '\\' {
+            if let Some(escaped) = chars.next() {
+                out.push(escaped);
+            }
+        } else {
+            out.push(chr);
+        }
+    }
+    out
+}
+
+/// Reads the metadata and chapters embedded in an audio file using `ffmpeg`.
+pub fn read_ffmetadata(ffmpeg_path : &path::Path, file : &path::Path) -> Option<Tracklist> {
+    let output = process::Command::new(ffmpeg_path)
+            .args(["-v", "error", "-i"])
+            .arg(file)
+            .args(["-f", "ffmetadata", "-"])
+            .stdin(process::Stdio::null())
+            .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            Some(parse_ffmetadata(&String::from_utf8_lossy(&output.stdout)))
+        },
+        Ok(output) => {
+            log::warn!("failed to read chapters from file: {}\n{}",
+                    file.display(), String::from_utf8_lossy(&output.stderr));
+            None
+        },
+        Err(err) => {
+            log::warn!("failed to run `ffmpeg`: {}", err);
+            None
+        },
+    }
+}
+
+/// Searches for a tracklist for the given file, in order of preference:
+///  - A `.cue` sheet with the same file stem.
+///  - Chapters embedded in the file.
+///  - A `.description` file with the same file stem containing timestamps.
+pub fn find_tracklist(ffmpeg_path : &path::Path, file : &path::Path) -> Option<Tracklist> {
+    let embedded = read_ffmetadata(ffmpeg_path, file);
+    let mut tracklist = None;
+    if let Ok(cue) = fs::read_to_string(file.with_extension("cue")) {
+        log::info!("using tracklist from cue sheet");
+        tracklist = Some(parse_cue(&cue));
+    }
+    if tracklist.as_ref().is_none_or(|x| x.chapters.is_empty()) {
+        if let Some(embedded) = &embedded {
+            if !embedded.chapters.is_empty() {
+                log::info!("using tracklist from embedded chapters");
+                tracklist = Some(Tracklist {
+                    album : embedded.album.clone(),
+                    album_author : embedded.album_author.clone(),
+                    chapters : embedded.chapters.clone(),
+                });
+            }
+        }
+    }
+    if tracklist.as_ref().is_none_or(|x| x.chapters.is_empty()) {
+        if let Ok(description) = fs::read_to_string(file.with_extension("description")) {
+            let from_description = parse_description(&description);
+            if !from_description.chapters.is_empty() {
+                log::info!("using tracklist from description");
+                tracklist = Some(from_description);
+            }
+        }
+    }
+    let mut tracklist = tracklist.filter(|x| !x.chapters.is_empty())?;
+    // fill in any missing album info using the tags of the file itself
+    if let Some(embedded) = embedded {
+        if tracklist.album.is_none() {
+            tracklist.album = embedded.album;
+        }
+        if tracklist.album_author.is_none() {
+            tracklist.album_author = embedded.album_author;
+        }
+    }
+    Some(tracklist)
+}
\ No newline at end of file
@@ -0,0 +1,487 @@
+use std::path;
+use crate::common;
+use crate::common::lyrics;
+use crate::common::sniff;
+use crate::common::vorbis;
+
+use id3::TagLike;
+
+/// Namespace used by iTunes-style freeform atoms in MP4 files.
+const MP4_MEAN : &str = "com.apple.iTunes";
+
+/// The tag formats that custom fields can be read from and written to.
+///
+/// Fields are named using Vorbis comment conventions (e.g. `SOURCE`), and
+/// are stored as `TXXX` frames in ID3 tags, and as freeform atoms in MP4
+/// files. Ogg files are only supported for Vorbis and Opus streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Id3,
+    Flac,
+    Mp4,
+    Ogg,
+}
+
+impl Container {
+    pub fn from_path(file_path : &path::Path) -> Option<Self> {
+        let ext = file_path.extension().and_then(|x| x.to_str()).map(|x| x.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("mp3") => Some(Self::Id3),
+            Some("flac") => Some(Self::Flac),
+            Some("m4a" | "m4b" | "m4p" | "mp4" | "alac" | "aac") => Some(Self::Mp4),
+            Some("ogg" | "oga" | "opus") => Some(Self::Ogg),
+            // files without an extension are identified by their contents
+            None => match sniff::sniff(file_path).ok()? {
+                sniff::Kind::Audio(sniff::Format::Mp3) => Some(Self::Id3),
+                sniff::Kind::Audio(sniff::Format::Flac) => Some(Self::Flac),
+                sniff::Kind::Audio(sniff::Format::Mp4) => Some(Self::Mp4),
+                sniff::Kind::Audio(sniff::Format::Vorbis | sniff::Format::Opus) => Some(Self::Ogg),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn unsupported(file_path : &path::Path) -> Box<dyn std::error::Error> {
+    format!("writing custom tags is not supported for this file type: {}",
+            file_path.display()).into()
+}
+
+fn read_id3(file_path : &path::Path) -> common::Result<id3::Tag> {
+    match id3::Tag::read_from_path(file_path) {
+        Ok(tag) => Ok(tag),
+        Err(id3::Error { kind : id3::ErrorKind::NoTag, .. }) => Ok(id3::Tag::new()),
+        Err(err) => Err(Box::new(err)),
+    }
+}
+
+/// Reads every custom field stored in a file.
+pub fn read_fields(file_path : &path::Path) -> common::Result<Vec<(String, String)>> {
+    let container = Container::from_path(file_path).ok_or_else(|| unsupported(file_path))?;
+    let mut fields = Vec::new();
+    match container {
+        Container::Id3 => {
+            let tag = read_id3(file_path)?;
+            for text in tag.extended_texts() {
+                fields.push((text.description.to_ascii_uppercase(), text.value.clone()));
+            }
+        },
+        Container::Flac => {
+            let tag = metaflac::Tag::read_from_path(file_path)?;
+            if let Some(comments) = tag.vorbis_comments() {
+                for (key, values) in &comments.comments {
+                    for value in values {
+                        fields.push((key.to_ascii_uppercase(), value.clone()));
+                    }
+                }
+            }
+        },
+        Container::Mp4 => {
+            let tag = mp4ameta::Tag::read_from_path(file_path)?;
+            for (ident, value) in tag.strings() {
+                if let mp4ameta::DataIdent::Freeform { mean, name } = ident {
+                    if mean == MP4_MEAN {
+                        fields.push((name.to_ascii_uppercase(), value.to_string()));
+                    }
+                }
+            }
+        },
+        Container::Ogg => {
+            let comments = vorbis::read(file_path)?;
+            // pictures are read as cover art instead
+            fields.extend(comments.fields.into_iter().filter(|(key, _)| key != vorbis::PICTURE_FIELD));
+        },
+    }
+    Ok(fields)
+}
+
+/// Writes custom fields to a file. Fields with a value of `None` are removed.
+pub fn write_fields(file_path : &path::Path, fields : &[(&str, Option<&str>)]) -> common::Result<()> {
+    let container = Container::from_path(file_path).ok_or_else(|| unsupported(file_path))?;
+    match container {
+        Container::Id3 => {
+            let mut tag = read_id3(file_path)?;
+            for (key, value) in fields {
+                tag.remove_extended_text(Some(key), None);
+                if let Some(value) = value {
+                    tag.add_frame(id3::frame::ExtendedText {
+                        description : key.to_string(),
+                        value : value.to_string(),
+                    });
+                }
+            }
+            tag.write_to_path(file_path, id3::Version::Id3v24)?;
+        },
+        Container::Flac => {
+            let mut tag = metaflac::Tag::read_from_path(file_path)?;
+            for (key, value) in fields {
+                tag.remove_vorbis(key);
+                if let Some(value) = value {
+                    tag.set_vorbis(*key, vec![*value]);
+                }
+            }
+            tag.save()?;
+        },
+        Container::Mp4 => {
+            let mut tag = mp4ameta::Tag::read_from_path(file_path)?;
+            for (key, value) in fields {
+                let ident = mp4ameta::FreeformIdent::new(MP4_MEAN, key);
+                tag.remove_data_of(&ident);
+                if let Some(value) = value {
+                    tag.set_data(ident, mp4ameta::Data::Utf8(value.to_string()));
+                }
+            }
+            tag.write_to_path(file_path)?;
+        },
+        Container::Ogg => {
+            let mut comments = vorbis::read(file_path)?;
+            for (key, value) in fields {
+                comments.remove(key);
+                if let Some(value) = value {
+                    comments.push(key, value);
+                }
+            }
+            vorbis::write(file_path, &comments)?;
+        },
+    }
+    Ok(())
+}
+
+/// An image embedded in a file, such as its cover art.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub mime_type : String,
+    pub data : Vec<u8>,
+}
+
+/// Reads the cover art of a file, preferring the front cover over any other
+/// embedded images.
+pub fn read_cover(file_path : &path::Path) -> common::Result<Option<Image>> {
+    let container = Container::from_path(file_path).ok_or_else(|| unsupported(file_path))?;
+    let image = match container {
+        Container::Id3 => {
+            let tag = read_id3(file_path)?;
+            let front = tag.pictures().find(|x| x.picture_type == id3::frame::PictureType::CoverFront);
+            let image = front.or(tag.pictures().next()).map(|x| Image {
+                mime_type : x.mime_type.clone(),
+                data : x.data.clone(),
+            });
+            image
+        },
+        Container::Flac => {
+            let tag = metaflac::Tag::read_from_path(file_path)?;
+            let front = tag.pictures().find(|x| x.picture_type == metaflac::block::PictureType::CoverFront);
+            let image = front.or(tag.pictures().next()).map(|x| Image {
+                mime_type : x.mime_type.clone(),
+                data : x.data.clone(),
+            });
+            image
+        },
+        Container::Mp4 => {
+            let tag = mp4ameta::Tag::read_from_path(file_path)?;
+            tag.artwork().map(|x| Image {
+                mime_type : match x.fmt {
+                    mp4ameta::ImgFmt::Bmp => "image/bmp",
+                    mp4ameta::ImgFmt::Jpeg => "image/jpeg",
+                    mp4ameta::ImgFmt::Png => "image/png",
+                }.to_string(),
+                data : x.data.to_vec(),
+            })
+        },
+        Container::Ogg => {
+            let comments = vorbis::read(file_path)?;
+            let pictures = comments.get(vorbis::PICTURE_FIELD)
+                    .filter_map(vorbis::Picture::decode)
+                    .collect::<Vec<_>>();
+            let front = pictures.iter().position(|x| x.picture_type == vorbis::FRONT_COVER);
+            pictures.into_iter().nth(front.unwrap_or(0)).map(|x| Image {
+                mime_type : x.mime_type,
+                data : x.data,
+            })
+        },
+    };
+    Ok(image)
+}
+
+/// Embeds an image as the front cover of a file, replacing any existing front
+/// cover.
+pub fn write_cover(file_path : &path::Path, image : &Image) -> common::Result<()> {
+    let container = Container::from_path(file_path).ok_or_else(|| unsupported(file_path))?;
+    match container {
+        Container::Id3 => {
+            let mut tag = read_id3(file_path)?;
+            tag.remove_picture_by_type(id3::frame::PictureType::CoverFront);
+            tag.add_frame(id3::frame::Picture {
+                mime_type : image.mime_type.clone(),
+                picture_type : id3::frame::PictureType::CoverFront,
+                description : String::new(),
+                data : image.data.clone(),
+            });
+            tag.write_to_path(file_path, id3::Version::Id3v24)?;
+        },
+        Container::Flac => {
+            let mut tag = metaflac::Tag::read_from_path(file_path)?;
+            tag.remove_picture_type(metaflac::block::PictureType::CoverFront);
+            tag.add_picture(image.mime_type.clone(), metaflac::block::PictureType::CoverFront, image.data.clone());
+            tag.save()?;
+        },
+        Container::Mp4 => {
+            let fmt = match image.mime_type.as_str() {
+                "image/jpeg" | "image/jpg" => mp4ameta::ImgFmt::Jpeg,
+                "image/png" => mp4ameta::ImgFmt::Png,
+                "image/bmp" => mp4ameta::ImgFmt::Bmp,
+                other => return Err(format!("unsupported cover art type for MP4 files: {}", other).into()),
+            };
+            let mut tag = mp4ameta::Tag::read_from_path(file_path)?;
+            tag.set_artwork(mp4ameta::Img::new(fmt, image.data.clone()));
+            tag.write_to_path(file_path)?;
+        },
+        Container::Ogg => {
+            let mut comments = vorbis::read(file_path)?;
+            comments.fields.retain(|(key, value)| key != vorbis::PICTURE_FIELD
+                    || vorbis::Picture::decode(value).is_none_or(|x| x.picture_type != vorbis::FRONT_COVER));
+            let picture = vorbis::Picture {
+                picture_type : vorbis::FRONT_COVER,
+                mime_type : image.mime_type.clone(),
+                data : image.data.clone(),
+            };
+            comments.push(vorbis::PICTURE_FIELD, &picture.encode());
+            vorbis::write(file_path, &comments)?;
+        },
+    }
+    Ok(())
+}
+
+/// Language code of embedded lyrics, which isn't known.
+const LYRICS_LANG : &str = "und";
+
+/// Reads the lyrics embedded in a file, as LRC text if they're synchronised.
+pub fn read_lyrics(file_path : &path::Path) -> common::Result<Option<String>> {
+    let container = Container::from_path(file_path).ok_or_else(|| unsupported(file_path))?;
+    let text = match container {
+        Container::Id3 => {
+            let tag = read_id3(file_path)?;
+            let unsynced = tag.lyrics().next().map(|x| x.text.clone());
+            let synced = tag.synchronised_lyrics()
+                    .find(|x| x.timestamp_format == id3::frame::TimestampFormat::Ms)
+                    .map(|x| lyrics::format_synced(&x.content));
+            // unsynchronised lyrics which are LRC text keep any extra tags
+            match unsynced {
+                Some(text) if !lyrics::parse_synced(&text).is_empty() => Some(text),
+                unsynced => synced.or(unsynced),
+            }
+        },
+        Container::Flac => {
+            let tag = metaflac::Tag::read_from_path(file_path)?;
+            let text = ["LYRICS", "UNSYNCEDLYRICS"].iter()
+                    .find_map(|key| tag.get_vorbis(key).and_then(|mut x| x.next()).map(String::from));
+            text
+        },
+        Container::Mp4 => {
+            let tag = mp4ameta::Tag::read_from_path(file_path)?;
+            tag.lyrics().map(String::from)
+        },
+        Container::Ogg => {
+            let comments = vorbis::read(file_path)?;
+            let text = ["LYRICS", "UNSYNCEDLYRICS"].iter()
+                    .find_map(|key| comments.get(key).next().map(String::from));
+            text
+        },
+    };
+    Ok(text.filter(|x| !x.trim().is_empty()))
+}
+
+/// Embeds lyrics into a file, replacing any existing lyrics. Synchronised LRC
+/// lyrics are also stored as `SYLT` frames in ID3 tags.
+pub fn write_lyrics(file_path : &path::Path, text : &str) -> common::Result<()> {
+    let container = Container::from_path(file_path).ok_or_else(|| unsupported(file_path))?;
+    match container {
+        Container::Id3 => {
+            let mut tag = read_id3(file_path)?;
+            tag.remove_all_lyrics();
+            tag.remove_all_synchronised_lyrics();
+            tag.add_frame(id3::frame::Lyrics {
+                lang : LYRICS_LANG.to_string(),
+                description : String::new(),
+                text : text.to_string(),
+            });
+            let synced = lyrics::parse_synced(text);
+            if !synced.is_empty() {
+                tag.add_frame(id3::frame::SynchronisedLyrics {
+                    lang : LYRICS_LANG.to_string(),
+                    timestamp_format : id3::frame::TimestampFormat::Ms,
+                    content_type : id3::frame::SynchronisedLyricsType::Lyrics,
+                    description : String::new(),
+                    content : synced,
+                });
+            }
+            tag.write_to_path(file_path, id3::Version::Id3v24)?;
+        },
+        Container::Flac => {
+            let mut tag = metaflac::Tag::read_from_path(file_path)?;
+            tag.remove_vorbis("UNSYNCEDLYRICS");
+            tag.set_vorbis("LYRICS", vec![text]);
+            tag.save()?;
+        },
+        Container::Mp4 => {
+            let mut tag = mp4ameta::Tag::read_from_path(file_path)?;
+            tag.set_lyrics(text);
+            tag.write_to_path(file_path)?;
+        },
+        Container::Ogg => {
+            let mut comments = vorbis::read(file_path)?;
+            comments.remove("UNSYNCEDLYRICS");
+            comments.remove("LYRICS");
+            comments.push("LYRICS", text);
+            vorbis::write(file_path, &comments)?;
+        },
+    }
+    Ok(())
+}
+
+/// A standard tag which can be edited by the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Artists,
+    Album,
+    AlbumArtist,
+    Title,
+    Track,
+    Year,
+}
+
+impl Field {
+    pub const ALL : [Self; 6] = [
+        Self::Artists, Self::Album, Self::AlbumArtist, Self::Title, Self::Track, Self::Year,
+    ];
+
+    pub fn from_name(name : &str) -> Option<Self> {
+        Some(match name.trim().to_ascii_lowercase().as_str() {
+            "artists" | "artist" => Self::Artists,
+            "album" => Self::Album,
+            "album_artist" => Self::AlbumArtist,
+            "title" => Self::Title,
+            "track" => Self::Track,
+            "year" => Self::Year,
+            _ => return None,
+        })
+    }
+
+    /// The name of the field, as used in files edited by the user.
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Artists => "artist",
+            Self::Album => "album",
+            Self::AlbumArtist => "album_artist",
+            Self::Title => "title",
+            Self::Track => "track",
+            Self::Year => "year",
+        }
+    }
+
+    /// A human readable name for the field.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Artists => "artist",
+            Self::Album => "album",
+            Self::AlbumArtist => "album artist",
+            Self::Title => "title",
+            Self::Track => "track number",
+            Self::Year => "year",
+        }
+    }
+
+    pub fn is_numeric(self) -> bool {
+        matches!(self, Self::Track | Self::Year)
+    }
+
+    /// Checks that a value can be stored in this field.
+    pub fn validate(self, value : &str) -> common::Result<()> {
+        match self {
+            Self::Track => value.parse::<u16>().map(|_| ())
+                    .map_err(|_| format!("invalid track number '{}'", value).into()),
+            Self::Year => value.parse::<i32>().map(|_| ())
+                    .map_err(|_| format!("invalid year '{}'", value).into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn read(self, tag : &dyn audiotags::AudioTag) -> Option<String> {
+        match self {
+            Self::Artists => tag.artist().map(String::from),
+            Self::Album => tag.album_title().map(String::from),
+            Self::AlbumArtist => tag.album_artist().map(String::from),
+            Self::Title => tag.title().map(String::from),
+            Self::Track => tag.track_number().map(|x| x.to_string()),
+            Self::Year => tag.year().map(|x| x.to_string()),
+        }
+    }
+
+    /// Sets the value of this field, which must have already been validated.
+    /// A value of `None` removes the field.
+    pub fn write(self, tag : &mut dyn audiotags::AudioTag, value : Option<&str>) {
+        match (self, value) {
+            (Self::Artists, Some(x)) => tag.set_artist(x),
+            (Self::Artists, None) => tag.remove_artist(),
+            (Self::Album, Some(x)) => tag.set_album_title(x),
+            (Self::Album, None) => tag.remove_album_title(),
+            (Self::AlbumArtist, Some(x)) => tag.set_album_artist(x),
+            (Self::AlbumArtist, None) => tag.remove_album_artist(),
+            (Self::Title, Some(x)) => tag.set_title(x),
+            (Self::Title, None) => tag.remove_title(),
+            (Self::Track, Some(x)) => tag.set_track_number(x.parse().unwrap()),
+            (Self::Track, None) => tag.remove_track_number(),
+            (Self::Year, Some(x)) => tag.set_year(x.parse().unwrap()),
+            (Self::Year, None) => tag.remove_year(),
+        }
+    }
+}
+
+/// A change to a standard tag of a file.
+#[derive(Debug)]
+pub struct Change {
+    pub field : Field,
+    pub old : Option<String>,
+    pub new : Option<String>,
+}
+
+/// Shows the changes that will be made to a file.
+pub fn print_changes(file_path : &path::Path, changes : &[Change]) {
+    println!("{}", file_path.display());
+    for change in changes {
+        println!("  {:<14}{} => {}",
+                change.field.name(),
+                change.old.as_deref().unwrap_or("-"),
+                change.new.as_deref().unwrap_or("-"));
+    }
+}
+
+pub fn apply_changes(
+    tag : &mut dyn audiotags::AudioTag,
+    file_path : &path::Path,
+    changes : &[Change],
+) -> common::Result<()> {
+    for change in changes {
+        change.field.write(tag, change.new.as_deref());
+    }
+    write_tag(tag, file_path)
+}
+
+/// Reads the standard tags of a file. MP3 files without any tags are given
+/// an empty tag, so that one can be written.
+pub fn read_tag(file_path : &path::Path) -> common::Result<Box<dyn audiotags::AudioTag + Send + Sync>> {
+    match audiotags::Tag::new().read_from_path(file_path) {
+        Ok(tag) => Ok(tag),
+        Err(audiotags::Error::Id3TagError(id3::Error { kind : id3::ErrorKind::NoTag, .. })) => {
+            Ok(Box::new(audiotags::Id3v2Tag::new()))
+        },
+        Err(err) => Err(Box::new(err)),
+    }
+}
+
+pub fn write_tag(tag : &mut dyn audiotags::AudioTag, file_path : &path::Path) -> common::Result<()> {
+    let file_str = file_path.to_str().ok_or("file path is not valid UTF-8")?;
+    tag.write_to_path(file_str)?;
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,289 @@
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path;
+use crate::common;
+
+use ogg::{PacketReader, PacketWriteEndInfo, PacketWriter};
+
+/// The field that pictures are stored in, as base64 encoded FLAC picture
+/// blocks.
+pub const PICTURE_FIELD : &str = "METADATA_BLOCK_PICTURE";
+
+/// The picture type of a front cover, as numbered by ID3 and FLAC.
+pub const FRONT_COVER : u32 = 3;
+
+/// The codecs whose Ogg streams store Vorbis comments in their second packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Codec {
+    Vorbis,
+    Opus,
+}
+
+impl Codec {
+    fn from_header(packet : &[u8]) -> Option<Self> {
+        if packet.starts_with(b"\x01vorbis") {
+            Some(Self::Vorbis)
+        } else if packet.starts_with(b"OpusHead") {
+            Some(Self::Opus)
+        } else {
+            None
+        }
+    }
+
+    /// The bytes at the start of the comment packet.
+    fn comment_magic(self) -> &'static [u8] {
+        match self {
+            Self::Vorbis => b"\x03vorbis",
+            Self::Opus => b"OpusTags",
+        }
+    }
+}
+
+/// Reads the little endian, length prefixed values of a comment packet.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, len : usize) -> Option<&'a [u8]> {
+        let (taken, rest) = self.0.split_at_checked(len)?;
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn take_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn take_string(&mut self) -> Option<String> {
+        let len = self.take_u32()? as usize;
+        Some(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
+
+/// The Vorbis comments of an Ogg Vorbis or Opus file.
+#[derive(Debug, Clone)]
+pub struct Comments {
+    codec : Codec,
+    vendor : String,
+    /// Every field in the order they are stored, with upper case keys.
+    pub fields : Vec<(String, String)>,
+    /// Whatever follows the comments, such as the framing bit of Vorbis,
+    /// which is kept as it is.
+    trailer : Vec<u8>,
+}
+
+impl Comments {
+    /// Returns every value of a field.
+    pub fn get<'a>(&'a self, key : &'a str) -> impl Iterator<Item = &'a str> {
+        self.fields.iter()
+                .filter(move |(x, _)| x.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.as_str())
+    }
+
+    pub fn remove(&mut self, key : &str) {
+        self.fields.retain(|(x, _)| !x.eq_ignore_ascii_case(key));
+    }
+
+    pub fn push(&mut self, key : &str, value : &str) {
+        self.fields.push((key.to_ascii_uppercase(), value.to_string()));
+    }
+
+    fn parse(codec : Codec, packet : &[u8]) -> Option<Self> {
+        let mut data = Fields(packet.strip_prefix(codec.comment_magic())?);
+        let vendor = data.take_string()?;
+        let len = data.take_u32()?;
+        let mut fields = Vec::new();
+        for _ in 0..len {
+            // comments without a `=` aren't valid, so they are dropped
+            if let Some((key, value)) = data.take_string()?.split_once('=') {
+                fields.push((key.to_ascii_uppercase(), value.to_string()));
+            }
+        }
+        Some(Self { codec, vendor, fields, trailer : data.0.to_vec() })
+    }
+
+    fn to_packet(&self) -> Vec<u8> {
+        fn push_bytes(packet : &mut Vec<u8>, bytes : &[u8]) {
+            packet.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            packet.extend_from_slice(bytes);
+        }
+        let mut packet = self.codec.comment_magic().to_vec();
+        push_bytes(&mut packet, self.vendor.as_bytes());
+        packet.extend_from_slice(&(self.fields.len() as u32).to_le_bytes());
+        for (key, value) in &self.fields {
+            push_bytes(&mut packet, format!("{}={}", key, value).as_bytes());
+        }
+        packet.extend_from_slice(&self.trailer);
+        packet
+    }
+}
+
+type Reader = PacketReader<BufReader<fs::File>>;
+
+/// Reads the first packet of a file, which identifies its codec.
+fn read_header(reader : &mut Reader, file_path : &path::Path) -> common::Result<(Codec, ogg::Packet)> {
+    let packet = reader.read_packet()?.ok_or_else(|| invalid(file_path))?;
+    match Codec::from_header(&packet.data) {
+        Some(codec) => Ok((codec, packet)),
+        None => Err(format!("only Vorbis and Opus streams are supported in Ogg files: {}",
+                file_path.display()).into()),
+    }
+}
+
+/// Reads the next packet of a logical stream, skipping any others which are
+/// multiplexed with it.
+fn read_packet(reader : &mut Reader, serial : u32) -> common::Result<Option<ogg::Packet>> {
+    while let Some(packet) = reader.read_packet()? {
+        if packet.stream_serial() == serial {
+            return Ok(Some(packet));
+        }
+    }
+    Ok(None)
+}
+
+fn invalid(file_path : &path::Path) -> Box<dyn std::error::Error> {
+    format!("invalid Ogg stream headers: {}", file_path.display()).into()
+}
+
+/// Reads the Vorbis comments of an Ogg Vorbis or Opus file.
+pub fn read(file_path : &path::Path) -> common::Result<Comments> {
+    let mut reader = PacketReader::new(BufReader::new(fs::File::open(file_path)?));
+    let (codec, header) = read_header(&mut reader, file_path)?;
+    read_packet(&mut reader, header.stream_serial())?
+            .and_then(|x| Comments::parse(codec, &x.data))
+            .ok_or_else(|| invalid(file_path))
+}
+
+/// Replaces the Vorbis comments of a file. Since the comments are stored at
+/// the start of the stream, every page after them is written again to a
+/// temporary file, which then replaces the original.
+pub fn write(file_path : &path::Path, comments : &Comments) -> common::Result<()> {
+    let mut tmp_name = file_path.file_name().ok_or_else(|| invalid(file_path))?.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = file_path.with_file_name(tmp_name);
+    let result = fs::File::create(&tmp_path).map_err(|x| x.into())
+            .and_then(|tmp_file| rewrite(file_path, comments, BufWriter::new(tmp_file)));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+    fs::rename(&tmp_path, file_path)?;
+    Ok(())
+}
+
+fn rewrite(file_path : &path::Path, comments : &Comments, out : BufWriter<fs::File>) -> common::Result<()> {
+    let mut reader = PacketReader::new(BufReader::new(fs::File::open(file_path)?));
+    let (codec, header) = read_header(&mut reader, file_path)?;
+    if codec != comments.codec {
+        return Err(invalid(file_path));
+    }
+    let serial = header.stream_serial();
+    // packets of other streams would be dropped or reordered
+    let mut next_packet = || -> common::Result<Option<ogg::Packet>> {
+        match reader.read_packet()? {
+            Some(packet) if packet.stream_serial() != serial => Err(format!(
+                    "writing tags to multiplexed or chained Ogg streams is not supported: {}",
+                    file_path.display()).into()),
+            packet => Ok(packet),
+        }
+    };
+    let mut writer = PacketWriter::new(out);
+    // the first packet is always alone on its page, and the headers have to
+    // end a page before the audio starts
+    writer.write_packet(header.data.into_boxed_slice(), serial, PacketWriteEndInfo::EndPage, 0)?;
+    next_packet()?.ok_or_else(|| invalid(file_path))?;
+    let comment_end = match codec {
+        Codec::Vorbis => PacketWriteEndInfo::NormalPacket,
+        Codec::Opus => PacketWriteEndInfo::EndPage,
+    };
+    writer.write_packet(comments.to_packet().into_boxed_slice(), serial, comment_end, 0)?;
+    if codec == Codec::Vorbis {
+        let setup = next_packet()?.ok_or_else(|| invalid(file_path))?;
+        writer.write_packet(setup.data.into_boxed_slice(), serial, PacketWriteEndInfo::EndPage, 0)?;
+    }
+    // the audio is copied, keeping the packets on the same pages
+    while let Some(packet) = next_packet()? {
+        let end = if packet.last_in_stream() {
+            PacketWriteEndInfo::EndStream
+        } else if packet.last_in_page() {
+            PacketWriteEndInfo::EndPage
+        } else {
+            PacketWriteEndInfo::NormalPacket
+        };
+        let absgp = packet.absgp_page();
+        writer.write_packet(packet.data.into_boxed_slice(), serial, end, absgp)?;
+    }
+    writer.into_inner().into_inner().map_err(|x| x.into_error())?.sync_all()?;
+    Ok(())
+}
+
+/// A picture stored in a `METADATA_BLOCK_PICTURE` field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Picture {
+    pub picture_type : u32,
+    pub mime_type : String,
+    pub data : Vec<u8>,
+}
+
+impl Picture {
+    pub fn decode(value : &str) -> Option<Self> {
+        let block = base64_decode(value)?;
+        let mut block = Fields(&block);
+        let take_u32 = |x : &mut Fields| x.take(4).map(|x| u32::from_be_bytes(x.try_into().unwrap()));
+        let picture_type = take_u32(&mut block)?;
+        let mime_len = take_u32(&mut block)? as usize;
+        let mime_type = String::from_utf8_lossy(block.take(mime_len)?).into_owned();
+        let description_len = take_u32(&mut block)? as usize;
+        // the description, width, height, colour depth and palette size
+        block.take(description_len + 16)?;
+        let data_len = take_u32(&mut block)? as usize;
+        let data = block.take(data_len)?.to_vec();
+        Some(Self { picture_type, mime_type, data })
+    }
+
+    /// Encodes the picture without a description or dimensions, which are
+    /// optional.
+    pub fn encode(&self) -> String {
+        let mut block = Vec::with_capacity(self.data.len() + self.mime_type.len() + 32);
+        block.extend_from_slice(&self.picture_type.to_be_bytes());
+        block.extend_from_slice(&(self.mime_type.len() as u32).to_be_bytes());
+        block.extend_from_slice(self.mime_type.as_bytes());
+        block.extend_from_slice(&[0; 20]);
+        block.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        block.extend_from_slice(&self.data);
+        base64_encode(&block)
+    }
+}
+
+const BASE64_ALPHABET : &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data : &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(group >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(text : &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut group = 0u32;
+    let mut bits = 0;
+    for chr in text.bytes().filter(|x| !x.is_ascii_whitespace() && *x != b'=') {
+        let value = BASE64_ALPHABET.iter().position(|x| *x == chr)? as u32;
+        group = (group << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((group >> bits) as u8);
+        }
+    }
+    Some(out)
+}
\ No newline at end of file
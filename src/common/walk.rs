@@ -0,0 +1,221 @@
+use std::fs;
+use std::path;
+use std::collections::{ HashMap, HashSet };
+use crate::common;
+
+/// Name of the files which list paths that catty should not touch, using the
+/// same syntax as `.gitignore` files.
+pub const IGNORE_FILE : &str = ".cattyignore";
+
+#[derive(Debug)]
+struct Rule {
+    pattern : glob::Pattern,
+    negate : bool,
+    dir_only : bool,
+    anchored : bool,
+}
+
+impl Rule {
+    fn parse(line : &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // patterns containing a separator are relative to the ignore file
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+        let pattern = match glob::Pattern::new(line) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                log::warn!("invalid pattern in {}: {:?}\n{}", IGNORE_FILE, line, err);
+                return None;
+            },
+        };
+        Some(Self { pattern, negate, dir_only, anchored })
+    }
+
+    fn matches(&self, rel_path : &path::Path, is_dir : bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let options = glob::MatchOptions {
+            case_sensitive : true,
+            require_literal_separator : true,
+            require_literal_leading_dot : false,
+        };
+        if self.anchored {
+            self.pattern.matches_path_with(rel_path, options)
+        } else if let Some(file_name) = rel_path.file_name() {
+            self.pattern.matches_path_with(path::Path::new(file_name), options)
+        } else {
+            false
+        }
+    }
+}
+
+/// Walks the library to find files, respecting `.cattyignore` files.
+#[derive(Debug)]
+pub struct Walker {
+    recursive : bool,
+    max_depth : Option<usize>,
+    rules : HashMap<path::PathBuf, Vec<Rule>>,
+    visited : HashSet<path::PathBuf>,
+}
+
+impl Walker {
+    /// Creates a new walker. Directories are only searched if `recursive` is
+    /// enabled, in which case `max_depth` limits how deep the search goes.
+    pub fn new(recursive : bool, max_depth : Option<usize>) -> Self {
+        Self {
+            recursive : recursive || max_depth.is_some(),
+            max_depth,
+            rules : HashMap::new(),
+            visited : HashSet::new(),
+        }
+    }
+
+    pub fn is_recursive(&self) -> bool {
+        self.recursive
+    }
+
+    fn rules_for(&mut self, dir : &path::Path) -> &[Rule] {
+        if !self.rules.contains_key(dir) {
+            let mut rules = Vec::new();
+            if let Ok(source) = fs::read_to_string(dir.join(IGNORE_FILE)) {
+                log::debug!("using ignore rules from: {}", dir.join(IGNORE_FILE).display());
+                rules.extend(source.lines().filter_map(Rule::parse));
+            }
+            self.rules.insert(dir.to_path_buf(), rules);
+        }
+        &self.rules[dir]
+    }
+
+    /// Returns whether a path, relative to the library root, is excluded by a
+    /// `.cattyignore` file. Paths outside of the library are never ignored.
+    pub fn is_ignored(&mut self, file_path : &path::Path, is_dir : bool) -> bool {
+        let rel_path = rel_to_library(file_path);
+        let rel_path = if let Some(x) = rel_path { x } else { return false };
+        if rel_path.as_os_str().is_empty() {
+            return false;
+        }
+        if rel_path.starts_with(common::DATA_DIR) {
+            return true;
+        }
+        // a path is ignored if any of its parent directories are ignored
+        if let Some(parent) = rel_path.parent() {
+            if !parent.as_os_str().is_empty() && self.is_ignored(parent, true) {
+                return true;
+            }
+        }
+        // rules closer to the file take priority, and later rules override
+        // earlier ones
+        let mut ignored = false;
+        let mut dir = path::PathBuf::new();
+        let mut ancestors = vec![dir.clone()];
+        for component in rel_path.parent().into_iter().flat_map(|x| x.components()) {
+            dir.push(component);
+            ancestors.push(dir.clone());
+        }
+        for ancestor in ancestors {
+            let base = if ancestor.as_os_str().is_empty() { path::Path::new(".") } else { &ancestor };
+            let base = base.to_path_buf();
+            let sub_path = rel_path.strip_prefix(&ancestor).unwrap_or(&rel_path).to_path_buf();
+            for rule in self.rules_for(&base) {
+                if rule.matches(&sub_path, is_dir) {
+                    ignored = !rule.negate;
+                }
+            }
+        }
+        ignored
+    }
+
+    /// Visits a path matched by a GLOB pattern. Files are passed straight to
+    /// `f`, and directories are searched if the walker is recursive.
+    pub fn visit(
+        &mut self,
+        file_path : &path::Path,
+        f : &mut dyn FnMut(&path::Path) -> common::Result<()>,
+    ) -> common::Result<()> {
+        let is_dir = file_path.is_dir();
+        if self.is_ignored(file_path, is_dir) {
+            log::debug!("skipping ignored path: {}", file_path.display());
+            return Ok(());
+        }
+        if !is_dir {
+            return f(file_path);
+        }
+        if !self.recursive {
+            log::info!("skipping directory (use --recursive to search it): {}", file_path.display());
+            return Ok(());
+        }
+        self.walk_dir(file_path, 1, f)
+    }
+
+    fn walk_dir(
+        &mut self,
+        dir : &path::Path,
+        depth : usize,
+        f : &mut dyn FnMut(&path::Path) -> common::Result<()>,
+    ) -> common::Result<()> {
+        // protect against symlink loops by never visiting a directory twice
+        match fs::canonicalize(dir) {
+            Ok(canon_dir) => if !self.visited.insert(canon_dir) {
+                log::debug!("skipping directory that was already visited: {}", dir.display());
+                return Ok(());
+            },
+            Err(err) => {
+                log::warn!("failed to canonicalise directory: {}\n{}", dir.display(), err);
+                return Ok(());
+            },
+        }
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            entries.push(entry?.path());
+        }
+        entries.sort();
+        for entry in entries {
+            let entry = entry.strip_prefix(".").map(|x| x.to_path_buf()).unwrap_or(entry);
+            let is_dir = entry.is_dir();
+            if self.is_ignored(&entry, is_dir) {
+                log::debug!("skipping ignored path: {}", entry.display());
+                continue;
+            }
+            if !is_dir {
+                f(&entry)?;
+            } else if self.max_depth.is_none_or(|x| depth < x) {
+                self.walk_dir(&entry, depth + 1, f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Makes a path relative to the library root (the working directory).
+pub fn rel_to_library(file_path : &path::Path) -> Option<path::PathBuf> {
+    let rel_path = if file_path.is_absolute() {
+        let cwd = std::env::current_dir().and_then(fs::canonicalize).ok()?;
+        let canon_path = fs::canonicalize(file_path).unwrap_or(file_path.to_path_buf());
+        canon_path.strip_prefix(cwd).ok()?.to_path_buf()
+    } else {
+        file_path.to_path_buf()
+    };
+    let mut out = path::PathBuf::new();
+    for component in rel_path.components() {
+        match component {
+            path::Component::CurDir => (),
+            path::Component::ParentDir => if !out.pop() {
+                return None;
+            },
+            other => out.push(other),
+        }
+    }
+    Some(out)
+}
\ No newline at end of file
@@ -0,0 +1,79 @@
+use std::env;
+use std::path;
+use std::sync::mpsc;
+use std::time;
+use crate::common;
+
+use notify::event::{ AccessKind, AccessMode, EventKind, ModifyKind };
+use notify::Watcher as _;
+
+/// Returns whether a file or directory is hidden, like the `.catty` directory.
+pub fn is_hidden(file_path : &path::Path) -> bool {
+    file_path.file_name()
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| x.starts_with('.'))
+}
+
+/// Reports the files which are created, written or moved into a directory and
+/// its subdirectories, using whatever the platform provides through `notify`.
+pub struct Watcher {
+    root : path::PathBuf,
+    /// The directory that the paths of events are relative to.
+    base : path::PathBuf,
+    /// Stops watching once it is dropped.
+    _watcher : notify::RecommendedWatcher,
+    events : mpsc::Receiver<notify::Result<notify::Event>>,
+}
+
+impl Watcher {
+    pub fn new(root : &path::Path) -> common::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(root, notify::RecursiveMode::Recursive)?;
+        log::debug!("watching directory: {}", root.display());
+        Ok(Self { root : root.to_path_buf(), base : env::current_dir()?, _watcher : watcher, events })
+    }
+
+    /// Waits up to `timeout` for changes, returning the paths which changed.
+    /// Directories which are returned should be scanned for files.
+    pub fn wait(&mut self, timeout : time::Duration) -> common::Result<Vec<path::PathBuf>> {
+        let first = match self.events.recv_timeout(timeout) {
+            Ok(event) => event,
+            Err(mpsc::RecvTimeoutError::Timeout) => return Ok(Vec::new()),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Err("stopped receiving changes".into()),
+        };
+        let mut paths = Vec::new();
+        for event in [first].into_iter().chain(self.events.try_iter()) {
+            let event = match event {
+                Ok(event) if !event.need_rescan() => event,
+                result => {
+                    if let Err(err) = result {
+                        log::debug!("failed to watch for changes\nreason = {}", err);
+                    }
+                    log::warn!("missed some changes, scanning the whole directory again");
+                    paths.push(self.root.clone());
+                    continue;
+                },
+            };
+            // files are only interesting once they are written or moved into
+            // place, but new directories have to be scanned straight away
+            let is_change = matches!(event.kind,
+                    EventKind::Any
+                    | EventKind::Create(_)
+                    | EventKind::Modify(ModifyKind::Any | ModifyKind::Data(_) | ModifyKind::Name(_))
+                    | EventKind::Access(AccessKind::Close(AccessMode::Write)));
+            // the destination of a rename comes last
+            let file_path = match event.paths.last() {
+                Some(file_path) if is_change => file_path.strip_prefix(&self.base).unwrap_or(file_path),
+                _ => continue,
+            };
+            let is_inside_hidden = file_path.strip_prefix(&self.root)
+                    .map(|x| x.ancestors().any(is_hidden))
+                    .unwrap_or(false);
+            if !is_inside_hidden {
+                paths.push(file_path.to_path_buf());
+            }
+        }
+        Ok(paths)
+    }
+}
\ No newline at end of file
@@ -1,11 +1,32 @@
 mod common;
 mod cmd_add;
+mod cmd_art;
+mod cmd_check;
+mod cmd_dupes;
+mod cmd_edit;
+mod cmd_export;
+mod cmd_import_tags;
+mod cmd_index;
+mod cmd_inspect;
+mod cmd_loudness;
+mod cmd_ls;
+mod cmd_lyrics;
+mod cmd_playlist;
 mod cmd_rename;
 mod cmd_sort;
+mod cmd_split;
+mod cmd_stats;
+mod cmd_sync;
+mod cmd_undo;
+mod cmd_verify_quality;
+mod cmd_watch;
 
 use std::env;
+use std::process;
 
 use clap::{Parser, Subcommand};
+use common::select::Selector;
+#[allow(clippy::single_component_path_imports)]
 use colog;
 
 /// Music file manager.
@@ -28,7 +49,9 @@ enum Commands {
     /// Wrapper around `yt-dlp` that attempts to download an audio file in
     /// the highest quality, with as much metadata as it can grab.
     ///
-    /// Will not download video files.
+    /// Will not download video files. The preferred audio formats, and an
+    /// optional codec to transcode downloads into, can be configured in the
+    /// `[add]` table of `catty.toml`.
     Add {
         /// URIs that point to the files to download.
         #[arg(required = true)]
@@ -36,13 +59,216 @@ enum Commands {
         /// Indicates that the files are part of a playlist or album.
         #[arg(short, long, group = "media-type")]
         playlist : bool,
+        /// Splits full-album uploads into separate tracks, using chapters or
+        /// a tracklist from the description.
+        #[arg(short, long)]
+        split : bool,
+    },
+    /// Downloads new items from the subscriptions listed in `catty.toml`.
+    ///
+    /// Items that have already been downloaded are recorded in a download
+    /// archive, and will be skipped.
+    Sync {
+        /// The names of the subscriptions to sync (syncs all subscriptions by
+        /// default).
+        names : Vec<String>,
+    },
+    /// Splits single-file album uploads into separate tracks, using a `.cue`
+    /// sheet with the same name, embedded chapters, or a tracklist from a
+    /// `.description` file with the same name.
+    Split {
+        #[command(flatten)]
+        select : Selector,
+        /// Use this `.cue` sheet or list of timestamps instead of searching
+        /// for a tracklist.
+        #[arg(short, long)]
+        tracklist : Option<String>,
+    },
+    /// Updates the library index, which caches the metadata inferred for each
+    /// file so that it doesn't need to be parsed again until the file changes.
+    Index {
+        #[command(flatten)]
+        select : Selector,
+        /// Discards the existing index and parses every file again.
+        #[arg(long)]
+        rebuild : bool,
+    },
+    /// Shows the metadata inferred for audio files, and where they were
+    /// downloaded from.
+    Inspect {
+        #[command(flatten)]
+        select : Selector,
+    },
+    /// Audits the library for problems, such as misplaced files, albums with
+    /// conflicting tags or missing tracks, unreadable files, empty directories,
+    /// stray non-audio files, and lossless files transcoded from lossy audio
+    /// (see `verify-quality`).
+    ///
+    /// Exits with status 1 if any problems are found, so it can be run from
+    /// cron.
+    Check {
+        #[command(flatten)]
+        select : Selector,
+        /// Don't report these kinds of problems.
+        #[arg(long, value_enum, value_delimiter = ',')]
+        skip : Vec<cmd_check::IssueKind>,
+        /// Print the report as JSON.
+        #[arg(long)]
+        json : bool,
+    },
+    /// Analyses the spectrum of lossless files using `ffmpeg`, to find fakes
+    /// which were transcoded from lossy audio, such as a FLAC made from an MP3.
+    ///
+    /// Lossy encoders remove high frequencies, so the spectrum of a fake ends
+    /// in a sudden drop well below the highest frequency the sample rate can
+    /// hold. A drop above 20.6 kHz is reported as upsampled instead, since
+    /// that is where audio at a lower sample rate ends. Results are cached by
+    /// the hash of the audio.
+    ///
+    /// Exits with status 1 if any fakes are found, not counting upsampled
+    /// files.
+    VerifyQuality {
+        #[command(flatten)]
+        select : Selector,
+        /// Analyse every file again, even if it was analysed before.
+        #[arg(long)]
+        force : bool,
+        /// Print the report as JSON.
+        #[arg(long)]
+        json : bool,
+    },
+    /// Finds duplicate tracks, either with identical audio (ignoring tags) or
+    /// with the same artists and title.
+    ///
+    /// Each group lists the best copy first, preferring lossless formats and
+    /// then larger files. The other copies can be removed, or moved into
+    /// `.catty/quarantine`.
+    Dupes {
+        #[command(flatten)]
+        select : Selector,
+        /// Only group files with identical audio.
+        #[arg(long)]
+        exact : bool,
+        /// Delete every copy except the best one.
+        #[arg(long, conflicts_with = "quarantine")]
+        remove : bool,
+        /// Move every copy except the best one into `.catty/quarantine`.
+        #[arg(long)]
+        quarantine : bool,
+        /// Also remove or quarantine copies which only share their artists,
+        /// title and duration, instead of only those with identical audio.
+        #[arg(long)]
+        similar : bool,
+    },
+    /// Opens the tags of the selected files in a text editor, then applies
+    /// whatever was changed.
+    ///
+    /// The editor is taken from `editor` in the config, or else `$VISUAL` or
+    /// `$EDITOR`. Changing the path of a track moves the file.
+    Edit {
+        #[command(flatten)]
+        select : Selector,
+    },
+    /// Manages the cover art of albums: extracting it into folder images,
+    /// embedding folder images into tracks, and finding albums whose tracks
+    /// have mismatched or missing art.
+    ///
+    /// `sort` also writes the folder image of albums it moves, unless
+    /// `art.on-sort = false` is set in the config (`art.square = true` crops
+    /// video thumbnails to a square).
+    Art {
+        #[command(subcommand)]
+        command : cmd_art::ArtCommand,
+    },
+    /// Exports a catalogue of the library, with one row per track, so that it
+    /// can be analysed in a spreadsheet or compared between machines.
+    Export {
+        #[command(flatten)]
+        select : Selector,
+        /// The format of the catalogue.
+        #[arg(short, long, value_enum, default_value = "csv")]
+        format : cmd_export::ExportFormat,
+        /// Write the catalogue to this file instead of stdout.
+        #[arg(short, long, value_name = "FILE")]
+        output : Option<String>,
+    },
+    /// Applies tag changes from a CSV file, such as one written by `export`
+    /// and edited in a spreadsheet.
+    ///
+    /// Rows are matched to files by their `path`, or by their `source` URL if
+    /// the file has moved. Only cells which differ from the exported values
+    /// are applied, and the changes are shown before they are written.
+    ImportTags {
+        /// The CSV file to import.
+        csv : String,
+        /// Show the changes without writing them.
+        #[arg(short = 'n', long)]
+        dry_run : bool,
+    },
+    /// Measures the EBU R128 loudness of tracks using `ffmpeg`, and writes
+    /// ReplayGain tags for each track and album, or R128 tags for Opus files.
+    ///
+    /// Albums are the tracks in a directory which share an album tag, and the
+    /// rest of an album is measured too when only some of its tracks are
+    /// selected. Measurements are cached by the hash of the audio, so only
+    /// new or changed audio is decoded again.
+    Loudness {
+        #[command(flatten)]
+        select : Selector,
+        /// Measure every file again, even if it was measured before.
+        #[arg(long)]
+        force : bool,
+    },
+    /// Embeds lyrics from `.lrc` files next to tracks, extracts embedded
+    /// lyrics back into `.lrc` files, and finds tracks without lyrics.
+    Lyrics {
+        #[command(subcommand)]
+        command : cmd_lyrics::LyricsCommand,
+    },
+    /// Lists the audio files in the library which match a query.
+    ///
+    /// Queries are a list of terms which must all match, such as
+    /// `artist:"t+pazolite" album:~remix year>=2015 missing:album_artist`.
+    /// Terms can be negated with `-`, and alternatives separated by `OR`.
+    #[command(visible_alias = "query")]
+    Ls {
+        /// The query to match files against (matches every file by default).
+        #[arg(allow_hyphen_values = true)]
+        query : Vec<String>,
+        /// How to display the matching files.
+        #[arg(short, long, value_enum, default_value = "paths")]
+        format : cmd_ls::OutputFormat,
+    },
+    /// Shows statistics about the library, such as the number of tracks and
+    /// albums in each category, the codecs used, and how many tracks are
+    /// missing tags.
+    Stats {
+        /// How many of the artists with the most tracks to show.
+        #[arg(long, default_value = "10")]
+        top : usize,
+        /// Print the statistics as JSON.
+        #[arg(long)]
+        json : bool,
+    },
+    /// Creates playlists from the library, either once or from the smart
+    /// playlists declared in `catty.toml`.
+    ///
+    /// Smart playlists are `[[playlist]]` tables with a `name`, a `where`
+    /// query, and optionally a `format` (`m3u8` or `xspf`). Playlists are
+    /// written to `playlist-dir` (`playlists` by default). M3U playlists in
+    /// the library are kept up to date when `rename`, `sort` or `edit` move
+    /// the files they list.
+    Playlist {
+        #[command(subcommand)]
+        command : cmd_playlist::PlaylistCommand,
     },
     /// Renames all audio files in the working directory so they are in a
     /// consistent format.
     Rename {
-        /// The list of files to format (supports GLOB file path syntax).
-        file_paths : Vec<String>,
-        /// (a)rtist name, (A)lbum name, track (n)umber, track (t)itle
+        #[command(flatten)]
+        select : Selector,
+        /// (a)rtist name, (A)lbum name, track (n)umber, track (t)itle,
+        /// (q)uality, e.g. `[mp3 320kbps]`
         #[arg(short, long, default_value = "aAnt")]
         format : String,
         /// Include the artist name in the format (enabled by default).
@@ -69,15 +295,19 @@ enum Commands {
         /// Exclude the artist title from the format.
         #[arg(long = "no-title", overrides_with = "_title")]
         no_title : bool,
+        /// Replace file extensions which don't match the contents of the
+        /// file, e.g. an Opus file saved as `.webm`, or a download without
+        /// an extension.
+        #[arg(long)]
+        fix_extensions : bool,
     },
     /// Organise audio files in the working directory into subfolders based on
     /// the artist name and album name.
     ///  - Albums without a primary artist are moved to a folder called `.VariousArtists`.
     ///  - Tracks without a known artist are moved to a folder called `.Unknown`.
     Sort {
-        /// The list of files to sort into subfolders (supports GLOB file path
-        /// syntax).
-        file_paths : Vec<String>,
+        #[command(flatten)]
+        select : Selector,
         /// Deletes any empty directories inside of the music library.
         #[arg(short = 'd', long)]
         clean_dirs : bool,
@@ -85,6 +315,27 @@ enum Commands {
         #[arg(short = 'f', long)]
         clean_files : bool,
     },
+    /// Watches an inbox directory inside of the library, and processes new
+    /// audio files once they have finished downloading.
+    ///
+    /// Files are processed once they haven't changed for a few seconds, and
+    /// partial downloads such as `.part` files are ignored. Missing tags are
+    /// filled in with the inferred metadata, then files are renamed and sorted
+    /// into the library without asking for confirmation. Each batch is recorded
+    /// in the journal, so it can be undone. The `[watch]` table of `catty.toml`
+    /// can set `settle` (in seconds), `format`, and disable `write-tags`,
+    /// `rename` or `sort`.
+    Watch {
+        /// The inbox directory to watch.
+        dir : String,
+        /// Process the files which are already in the inbox, then exit.
+        #[arg(long)]
+        once : bool,
+    },
+    /// Moves back the files moved by the last `rename`, `sort`, `edit` or
+    /// `watch`, along with any playlists that refer to them, and restores the
+    /// tags which were changed.
+    Undo,
 }
 
 fn main() {
@@ -94,15 +345,69 @@ fn main() {
         // update working directory to example/
         env::set_current_dir(lib_path).expect("cannot update working dir");
     }
+    let mut exit_code = 0;
     let result = match &cli.command {
-        Commands::Add { uris, playlist }
-            => cmd_add::run(uris, *playlist),
-        Commands::Rename { file_paths, format, no_artist, album, number, no_title, .. }
-            => cmd_rename::run(&file_paths, &format, !*no_artist, *album, *number, !*no_title, cli.yes),
-        Commands::Sort { file_paths, clean_dirs, clean_files }
-            => cmd_sort::run(&file_paths, *clean_dirs, *clean_files, cli.yes),
+        Commands::Add { uris, playlist, split }
+            => cmd_add::run(uris, *playlist, *split, cli.yes),
+        Commands::Sync { names }
+            => cmd_sync::run(names, cli.yes),
+        Commands::Split { select, tracklist }
+            => cmd_split::run(select, tracklist.as_deref(), cli.yes),
+        Commands::Index { select, rebuild }
+            => cmd_index::run(select, *rebuild),
+        Commands::Inspect { select }
+            => cmd_inspect::run(select),
+        Commands::Check { select, skip, json }
+            => cmd_check::run(select, skip, *json).map(|n_issues| {
+                if n_issues > 0 {
+                    exit_code = 1;
+                }
+            }),
+        Commands::VerifyQuality { select, force, json }
+            => cmd_verify_quality::run(select, *force, *json).map(|n_lossy| {
+                if n_lossy > 0 {
+                    exit_code = 1;
+                }
+            }),
+        Commands::Dupes { select, exact, remove, quarantine, similar }
+            => cmd_dupes::run(select, *exact, *remove, *quarantine, *similar, cli.yes),
+        Commands::Edit { select }
+            => cmd_edit::run(select, cli.yes),
+        Commands::Art { command }
+            => cmd_art::run(command, cli.yes),
+        Commands::Export { select, format, output }
+            => cmd_export::run(select, *format, output.as_deref()),
+        Commands::ImportTags { csv, dry_run }
+            => cmd_import_tags::run(csv, *dry_run, cli.yes),
+        Commands::Ls { query, format }
+            => cmd_ls::run(query, *format),
+        Commands::Loudness { select, force }
+            => cmd_loudness::run(select, *force),
+        Commands::Lyrics { command }
+            => cmd_lyrics::run(command),
+        Commands::Playlist { command }
+            => cmd_playlist::run(command, cli.yes),
+        Commands::Stats { top, json }
+            => cmd_stats::run(*top, *json),
+        Commands::Rename { select, format, no_artist, album, number, no_title, fix_extensions, .. }
+            => cmd_rename::run(select, &cmd_rename::Options {
+                format,
+                artist : !*no_artist,
+                album : *album,
+                number : *number,
+                title : !*no_title,
+                fix_extensions : *fix_extensions,
+            }, cli.yes),
+        Commands::Sort { select, clean_dirs, clean_files }
+            => cmd_sort::run(select, *clean_dirs, *clean_files, cli.yes),
+        Commands::Watch { dir, once }
+            => cmd_watch::run(dir, *once),
+        Commands::Undo
+            => cmd_undo::run(cli.yes),
     };
     if let Err(msg) = result {
         log::error!("fatal error encountered:\n{}", msg);
+        exit_code = 2;
     }
+    process::exit(exit_code);
 }
\ No newline at end of file
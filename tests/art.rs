@@ -0,0 +1,115 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+/// The start of a JPEG image, which is enough for catty to tell images apart.
+fn jpeg(width : u16, height : u16) -> Vec<u8> {
+    let mut data = vec![0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x11, 0x08];
+    data.extend(height.to_be_bytes());
+    data.extend(width.to_be_bytes());
+    data.extend([0x03, 0x01, 0x22, 0x00, 0x02, 0x11, 0x01, 0x03, 0x11, 0x01, 0xFF, 0xD9]);
+    data
+}
+
+fn embed_cover(lib : &Library, rel_path : &str, data : &[u8]) {
+    embed_image(lib, rel_path, "image/jpeg", data);
+}
+
+fn embed_image(lib : &Library, rel_path : &str, mime_type : &str, data : &[u8]) {
+    use id3::TagLike;
+    let mut tag = id3::Tag::read_from_path(lib.join(rel_path)).unwrap();
+    tag.add_frame(id3::frame::Picture {
+        mime_type : mime_type.to_string(),
+        picture_type : id3::frame::PictureType::CoverFront,
+        description : String::new(),
+        data : data.to_vec(),
+    });
+    tag.write_to_path(lib.join(rel_path), id3::Version::Id3v24).unwrap();
+}
+
+fn read_cover(lib : &Library, rel_path : &str) -> Option<Vec<u8>> {
+    let tag = id3::Tag::read_from_path(lib.join(rel_path)).unwrap();
+    let cover = tag.pictures().next().map(|x| x.data.clone());
+    cover
+}
+
+fn album_track(lib : &Library, rel_path : &str, title : &str) {
+    lib.add_mp3(rel_path, &Tags {
+        artist : Some("Catty Band"), album : Some("Demo"), album_artist : Some("Catty Band"), title : Some(title), ..Tags::default()
+    });
+}
+
+#[test]
+fn extract_and_check_album_art() {
+    let lib = Library::new();
+    album_track(&lib, "Demo/01 One.mp3", "One");
+    album_track(&lib, "Demo/02 Two.mp3", "Two");
+    album_track(&lib, "Demo/03 Three.mp3", "Three");
+    embed_cover(&lib, "Demo/01 One.mp3", &jpeg(500, 500));
+    embed_cover(&lib, "Demo/02 Two.mp3", &jpeg(500, 500));
+    embed_cover(&lib, "Demo/03 Three.mp3", &jpeg(1280, 720));
+    let output = lib.run(&["art", "check"]);
+    assert_eq!(output.stdout, "Demo: 2 different covers, no folder image\n");
+    lib.run(&["art", "extract"]);
+    assert_eq!(fs::read(lib.join("Demo/cover.jpg")).unwrap(), jpeg(500, 500));
+    // existing folder images are kept
+    fs::write(lib.join("Demo/cover.jpg"), jpeg(600, 600)).unwrap();
+    lib.run(&["art", "extract"]);
+    assert_eq!(fs::read(lib.join("Demo/cover.jpg")).unwrap(), jpeg(600, 600));
+}
+
+#[test]
+fn embed_folder_image() {
+    let lib = Library::new();
+    album_track(&lib, "Demo/01 One.mp3", "One");
+    album_track(&lib, "Demo/02 Two.mp3", "Two");
+    embed_cover(&lib, "Demo/02 Two.mp3", &jpeg(300, 300));
+    fs::write(lib.join("Demo/Folder.JPG"), jpeg(500, 500)).unwrap();
+    lib.run(&["art", "embed", "-r", "Demo"]);
+    assert_eq!(read_cover(&lib, "Demo/01 One.mp3"), Some(jpeg(500, 500)));
+    assert_eq!(read_cover(&lib, "Demo/02 Two.mp3"), Some(jpeg(300, 300)));
+    let output = lib.run(&["art", "check"]);
+    assert_eq!(output.stdout, "Demo: 2 different covers\n");
+}
+
+#[test]
+fn embed_skips_failed_albums() {
+    let lib = Library::new();
+    let ffmpeg = lib.install_script("ffmpeg", "echo 'cannot crop' >&2\nexit 1\n");
+    lib.write_config(&format!("ffmpeg = {:?}\n", ffmpeg.display().to_string()));
+    album_track(&lib, "Demo/01 One.mp3", "One");
+    album_track(&lib, "Live/01 One.mp3", "One");
+    fs::write(lib.join("Demo/cover.jpg"), jpeg(1280, 720)).unwrap();
+    fs::write(lib.join("Live/cover.jpg"), jpeg(500, 500)).unwrap();
+    // the widescreen image can't be cropped, but the other album is embedded
+    let output = lib.run(&["art", "embed", "--square", "-r", "Demo", "Live"]);
+    assert!(output.stderr.contains("cannot crop"), "{}", output.stderr);
+    assert_eq!(read_cover(&lib, "Demo/01 One.mp3"), None);
+    assert_eq!(read_cover(&lib, "Live/01 One.mp3"), Some(jpeg(500, 500)));
+}
+
+#[test]
+fn sort_writes_folder_images() {
+    let lib = Library::new();
+    album_track(&lib, "One.mp3", "One");
+    embed_cover(&lib, "One.mp3", &jpeg(500, 500));
+    lib.add_mp3("Single.mp3", &Tags { artist : Some("Other Band"), title : Some("Single"), ..Tags::default() });
+    embed_cover(&lib, "Single.mp3", &jpeg(500, 500));
+    lib.run(&["sort"]);
+    assert_eq!(lib.files(), [
+        "A-F/Catty Band/Demo/One.mp3",
+        "A-F/Catty Band/Demo/cover.jpg",
+        "L-P/Other Band/Single.mp3",
+    ]);
+}
+
+#[test]
+fn sort_skips_unsupported_folder_images() {
+    let lib = Library::new();
+    album_track(&lib, "One.mp3", "One");
+    embed_image(&lib, "One.mp3", "image/gif", b"GIF89a not much of an image");
+    lib.run(&["sort"]);
+    assert_eq!(lib.files(), ["A-F/Catty Band/Demo/One.mp3"]);
+}
\ No newline at end of file
@@ -0,0 +1,73 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+fn album_track(title : &'static str, track : u32) -> Tags<'static> {
+    Tags {
+        artist : Some("Catty Band"),
+        album_artist : Some("Catty Band"),
+        album : Some("First"),
+        title : Some(title),
+        track : Some(track),
+    }
+}
+
+#[test]
+fn check_healthy_library() {
+    let lib = Library::new();
+    lib.add_mp3("A-F/Catty Band/First/01 One.mp3", &album_track("One", 1));
+    lib.add_mp3("A-F/Catty Band/First/02 Two.mp3", &album_track("Two", 2));
+    fs::write(lib.join("A-F/Catty Band/First/cover.jpg"), "").unwrap();
+    lib.write_config("");
+    let output = lib.run(&["check"]);
+    assert!(output.success, "{}", output.stdout);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn check_accepts_unrecognised_audio_formats() {
+    let lib = Library::new();
+    fs::create_dir_all(lib.join("inbox")).unwrap();
+    fs::write(lib.join("inbox/song.wma"), b"\x30\x26\xb2\x75 not sniffed by catty").unwrap();
+    fs::write(lib.join("inbox/song.tta"), b"TTA1 not sniffed by catty").unwrap();
+    lib.write_config("");
+    let output = lib.run(&["check", "--skip", "misplaced"]);
+    assert!(output.success, "{}", output.stdout);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn check_reports_problems() {
+    let lib = Library::new();
+    lib.add_mp3("A-F/Catty Band/First/01 One.mp3", &album_track("One", 1));
+    lib.add_mp3("A-F/Catty Band/First/03 Three.mp3", &album_track("Three", 3));
+    lib.add_mp3("A-F/Catty Band/First/03 Again.mp3", &Tags { album : Some("Second"), ..album_track("Again", 3) });
+    lib.add_mp3("inbox/song.mp3", &Tags { artist : Some("Catty Band"), ..Tags::default() });
+    fs::write(lib.join("inbox/broken.flac"), "fLaC but not really").unwrap();
+    fs::write(lib.join("inbox/notes.bin"), "some notes").unwrap();
+    fs::create_dir_all(lib.join("empty/nested")).unwrap();
+    let output = lib.run(&["check", "--json"]);
+    assert!(!output.success);
+    let report : serde_json::Value = serde_json::from_str(&output.stdout).unwrap();
+    let issues = report.as_array().unwrap().iter()
+            .map(|x| format!("{} {}", x["kind"].as_str().unwrap(), x["path"].as_str().unwrap()))
+            .collect::<Vec<_>>();
+    assert_eq!(issues, [
+        "conflicting-tags A-F/Catty Band/First",
+        "misplaced A-F/Catty Band/First/03 Again.mp3",
+        "empty-dir empty",
+        "misplaced inbox/broken.flac",
+        "unreadable inbox/broken.flac",
+        "stray-file inbox/notes.bin",
+        "misplaced inbox/song.mp3",
+    ]);
+    // fixing the album shows the remaining problems
+    lib.add_mp3("A-F/Catty Band/First/03 Again.mp3", &album_track("Again", 3));
+    let output = lib.run(&["check", "--skip", "misplaced,empty-dir,stray-file,unreadable"]);
+    let lines = output.stdout.lines()
+            .map(|x| x.split_whitespace().next().unwrap())
+            .collect::<Vec<_>>();
+    assert_eq!(lines, ["track-gap", "duplicate-track"]);
+}
\ No newline at end of file
@@ -0,0 +1,83 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+#[test]
+fn companions_follow_rename() {
+    let lib = Library::new();
+    lib.add_mp3("song.mp3", &Tags { artist : Some("Catty Band"), title : Some("Song"), ..Tags::default() });
+    lib.add_mp3("song.live.mp3", &Tags { artist : Some("Catty Band"), title : Some("Song (Live)"), ..Tags::default() });
+    fs::write(lib.join("song.lrc"), "[00:01.00]la la la\n").unwrap();
+    fs::write(lib.join("song.info.json"), "{}").unwrap();
+    fs::write(lib.join("song.live.lrc"), "[00:01.00]la la la (live)\n").unwrap();
+    fs::write(lib.join("songbook.pdf"), "").unwrap();
+    lib.run(&["rename", "song.mp3"]);
+    assert_eq!(lib.files(), [
+        "Catty Band - Song.info.json",
+        "Catty Band - Song.lrc",
+        "Catty Band - Song.mp3",
+        "song.live.lrc",
+        "song.live.mp3",
+        "songbook.pdf",
+    ]);
+    lib.run(&["undo"]);
+    assert_eq!(lib.files(), [
+        "song.info.json",
+        "song.live.lrc",
+        "song.live.mp3",
+        "song.lrc",
+        "song.mp3",
+        "songbook.pdf",
+    ]);
+}
+
+#[test]
+fn companions_follow_rename_of_every_track() {
+    let lib = Library::new();
+    lib.add_mp3("song.mp3", &Tags { artist : Some("Catty Band"), title : Some("Song"), ..Tags::default() });
+    lib.add_mp3("song.live.mp3", &Tags { artist : Some("Catty Band"), title : Some("Song (Live)"), ..Tags::default() });
+    fs::write(lib.join("song.lrc"), "[00:01.00]la la la\n").unwrap();
+    fs::write(lib.join("song.live.lrc"), "[00:01.00]la la la (live)\n").unwrap();
+    lib.run(&["rename", "song.mp3", "song.live.mp3"]);
+    assert_eq!(lib.files(), [
+        "Catty Band - Song (Live).lrc",
+        "Catty Band - Song (Live).mp3",
+        "Catty Band - Song.lrc",
+        "Catty Band - Song.mp3",
+    ]);
+}
+
+#[test]
+fn album_files_follow_sort() {
+    let lib = Library::new();
+    let tags = Tags { artist : Some("Catty Band"), album : Some("Mix"), ..Tags::default() };
+    lib.add_mp3("Mix/01 Intro.mp3", &Tags { title : Some("Intro"), track : Some(1), ..tags.clone() });
+    lib.add_mp3("Mix/02 Outro.mp3", &Tags { title : Some("Outro"), track : Some(2), ..tags.clone() });
+    fs::write(lib.join("Mix/01 Intro.lrc"), "[00:01.00]hello\n").unwrap();
+    fs::write(lib.join("Mix/booklet.pdf"), "").unwrap();
+    fs::write(lib.join("Mix/rip.log"), "").unwrap();
+    // only files which are expected next to tracks follow them
+    fs::write(lib.join("Mix/stems.zip"), "").unwrap();
+    // a directory which only some of its tracks leave is left alone
+    lib.add_mp3("Loose/Catty.mp3", &Tags { title : Some("Catty"), ..tags.clone() });
+    lib.add_mp3("Loose/Other.mp3", &Tags { artist : Some("Other Band"), title : Some("Other"), ..Tags::default() });
+    fs::write(lib.join("Loose/notes.txt"), "").unwrap();
+    lib.run(&["sort", "-r", "Mix", "Loose/Catty.mp3"]);
+    assert_eq!(lib.files(), [
+        "A-F/Catty Band/Mix/01 Intro.lrc",
+        "A-F/Catty Band/Mix/01 Intro.mp3",
+        "A-F/Catty Band/Mix/02 Outro.mp3",
+        "A-F/Catty Band/Mix/Catty.mp3",
+        "A-F/Catty Band/Mix/booklet.pdf",
+        "A-F/Catty Band/Mix/rip.log",
+        "Loose/Other.mp3",
+        "Loose/notes.txt",
+        "Mix/stems.zip",
+    ]);
+    // only tracks are kept in the index
+    let index = fs::read_to_string(lib.join(".catty/index.tsv")).unwrap();
+    assert!(index.contains("Catty.mp3"), "{}", index);
+    assert!(!index.contains("Intro.lrc") && !index.contains("rip.log"), "{}", index);
+}
\ No newline at end of file
@@ -0,0 +1,116 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+/// Encodes an MP4 box.
+fn mp4_box(kind : &[u8; 4], body : &[u8]) -> Vec<u8> {
+    let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out
+}
+
+fn library() -> Library {
+    let lib = Library::new();
+    let tags = Tags { artist : Some("Catty Band"), title : Some("Song"), ..Tags::default() };
+    lib.add_mp3("Catty Band - Song.mp3", &tags);
+    // the same audio, with different tags
+    lib.add_mp3("inbox/song.mp3", &Tags { album : Some("Singles"), ..tags.clone() });
+    // a different encoding of the same track
+    lib.add_mp3_frames("inbox/song (hq).mp3", &Tags { artist : Some("catty band"), title : Some("Song!"), ..Tags::default() }, 12);
+    lib.add_mp3_frames("Other Band - Song.mp3", &Tags { artist : Some("Other Band"), ..tags.clone() }, 4);
+    lib
+}
+
+#[test]
+fn dupes_lists_groups() {
+    let lib = library();
+    let output = lib.run(&["dupes"]);
+    let lines = output.stdout.lines()
+            .map(|x| x.split_whitespace().filter(|x| *x != "KiB" && x.parse::<f64>().is_err()).collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>();
+    assert_eq!(lines, [
+        "identical audio:",
+        // the copy with more tags is bigger, so it is kept
+        "keep mp3 128kbps inbox/song.mp3",
+        "dupe mp3 128kbps Catty Band - Song.mp3",
+        "same artists and title:",
+        "keep mp3 128kbps inbox/song (hq).mp3",
+        "dupe mp3 128kbps inbox/song.mp3",
+        "dupe mp3 128kbps Catty Band - Song.mp3",
+    ]);
+    assert_eq!(lib.files().len(), 4);
+    let output = lib.run(&["dupes", "--exact"]);
+    assert_eq!(output.stdout.lines().count(), 3);
+}
+
+#[test]
+fn dupes_quarantine_keeps_best() {
+    let lib = library();
+    // copies which only share their metadata are kept without `--similar`
+    lib.run(&["dupes", "--quarantine"]);
+    assert_eq!(lib.files(), [
+        "Other Band - Song.mp3",
+        "inbox/song (hq).mp3",
+        "inbox/song.mp3",
+    ]);
+    assert!(lib.exists(".catty/quarantine/Catty Band - Song.mp3"));
+    lib.run(&["dupes", "--quarantine", "--similar"]);
+    assert_eq!(lib.files(), [
+        "Other Band - Song.mp3",
+        "inbox/song (hq).mp3",
+    ]);
+    assert!(lib.exists(".catty/quarantine/Catty Band - Song.mp3"));
+    assert!(lib.exists(".catty/quarantine/inbox/song.mp3"));
+}
+
+#[test]
+fn dupes_remove() {
+    let lib = library();
+    lib.run(&["dupes", "--exact", "--remove"]);
+    assert_eq!(lib.files(), [
+        "Other Band - Song.mp3",
+        "inbox/song (hq).mp3",
+        "inbox/song.mp3",
+    ]);
+}
+
+#[test]
+fn dupes_ignores_different_durations() {
+    let lib = library();
+    // a much longer version of the same song, like a live recording
+    lib.add_mp3_frames("Catty Band - Song (Live).mp3", &Tags { artist : Some("Catty Band"), title : Some("Song"), ..Tags::default() }, 400);
+    let output = lib.run(&["dupes"]);
+    assert!(!output.stdout.contains("Song (Live).mp3"), "{}", output.stdout);
+}
+
+#[test]
+fn dupes_groups_close_durations() {
+    let lib = Library::new();
+    let tags = Tags { artist : Some("Catty Band"), title : Some("Song"), ..Tags::default() };
+    // either side of a multiple of the duration step
+    lib.add_mp3_frames("Catty Band - Song.mp3", &tags, 94);
+    lib.add_mp3_frames("inbox/song.mp3", &tags, 98);
+    let output = lib.run(&["dupes"]);
+    assert!(output.stdout.contains("same artists and title:"), "{}", output.stdout);
+    assert_eq!(output.stdout.lines().count(), 3);
+}
+
+#[test]
+fn dupes_hashes_corrupt_mp4() {
+    let lib = Library::new();
+    let hdlr = mp4_box(b"hdlr", b"\0\0\0\0\0\0\0\0soun\0\0\0\0");
+    let mut data = mp4_box(b"ftyp", b"M4A \0\0\0\0");
+    data.extend(mp4_box(b"moov", &mp4_box(b"trak", &mp4_box(b"mdia", &hdlr))));
+    // an `mdat` box claiming to be far bigger than the file
+    data.extend(1u32.to_be_bytes());
+    data.extend(b"mdat");
+    data.extend(u64::MAX.to_be_bytes());
+    data.extend(b"some audio");
+    fs::write(lib.join("Catty Band - Song.m4a"), &data).unwrap();
+    fs::write(lib.join("Catty Band - Song (Copy).m4a"), &data).unwrap();
+    let output = lib.run(&["dupes", "--exact"]);
+    assert!(output.stdout.starts_with("identical audio:"), "{}{}", output.stdout, output.stderr);
+}
\ No newline at end of file
@@ -0,0 +1,55 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+fn read_tag(lib : &Library, rel_path : &str) -> id3::Tag {
+    id3::Tag::read_from_path(lib.join(rel_path)).unwrap()
+}
+
+fn library(editor : &str) -> Library {
+    let lib = Library::new();
+    lib.add_mp3("one.mp3", &Tags { artist : Some("Catty Band"), title : Some("One"), ..Tags::default() });
+    lib.add_mp3("two.mp3", &Tags { artist : Some("Catty Band"), album : Some("Demo"), ..Tags::default() });
+    let script = lib.install_script("editor", editor);
+    lib.write_config(&format!("editor = {:?}\n", script.display().to_string()));
+    lib
+}
+
+#[test]
+fn edit_tags_and_paths() {
+    use id3::TagLike;
+    let lib = library("sed -i \
+            -e 's/^title = \"One\"/title = \"Uno\"/' \
+            -e 's/^album = \"Demo\"/album = \"\"/' \
+            -e 's/^track = \"\"/track = 3/' \
+            -e 's|^path = \"two.mp3\"|path = \"Demo/Two?.mp3\"|' \
+            \"$1\"\n");
+    let output = lib.run(&["edit", "one.mp3", "two.mp3"]);
+    assert!(output.stdout.contains("  title         One => Uno"), "{}", output.stdout);
+    let one = read_tag(&lib, "one.mp3");
+    assert_eq!(one.title(), Some("Uno"));
+    assert_eq!(one.track(), Some(3));
+    assert_eq!(lib.files(), ["Demo/Two_.mp3", "one.mp3"]);
+    let two = read_tag(&lib, "Demo/Two_.mp3");
+    assert_eq!(two.album(), None);
+    assert_eq!(two.artist(), Some("Catty Band"));
+    assert!(!lib.exists(".catty/edit.toml"));
+}
+
+#[test]
+fn edit_rejects_invalid_values() {
+    use id3::TagLike;
+    let lib = library("sed -i \
+            -e 's/^title = \"One\"/title = \"Uno\"/' \
+            -e 's/^year = \"\"/year = \"last year\"/' \
+            \"$1\"\n");
+    let output = lib.try_run(&["edit", "one.mp3", "two.mp3"]);
+    assert!(!output.success);
+    assert!(output.stderr.contains(".catty/edit.toml"), "{}", output.stderr);
+    // nothing is applied when any value is invalid
+    assert_eq!(read_tag(&lib, "one.mp3").title(), Some("One"));
+    let edits = fs::read_to_string(lib.join(".catty/edit.toml")).unwrap();
+    assert!(edits.contains("title = \"Uno\""));
+}
\ No newline at end of file
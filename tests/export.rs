@@ -0,0 +1,45 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+fn library() -> Library {
+    let lib = Library::new();
+    lib.add_mp3("one.mp3", &Tags {
+        artist : Some("Catty Band, Other Band"),
+        album : Some("First, Again"),
+        title : Some("One \"Live\""),
+        track : Some(1),
+        ..Tags::default()
+    });
+    lib.add_mp3("nested/two.mp3", &Tags { artist : Some("Catty Band"), ..Tags::default() });
+    lib
+}
+
+#[test]
+fn export_csv() {
+    let lib = library();
+    let size = |x : &str| fs::metadata(lib.join(x)).unwrap().len();
+    let output = lib.run(&["export"]);
+    assert_eq!(output.stdout.lines().collect::<Vec<_>>(), [
+        "path,file_name,artists,features,album,album_artist,track,year,title,codec,duration,bitrate,sample_rate,channels,size,source,extractor,downloaded,tags".to_string(),
+        format!("nested/two.mp3,two.mp3,Catty Band,,,,,,two,mp3,0.2085,128,44100,2,{},,,,", size("nested/two.mp3")),
+        format!("one.mp3,one.mp3,Catty Band; Other Band,,\"First, Again\",,1,,\"One \"\"Live\"\"\",mp3,0.2085,128,44100,2,{},,,,", size("one.mp3")),
+    ]);
+}
+
+#[test]
+fn export_jsonl_to_file() {
+    let lib = library();
+    lib.run(&["export", "--format", "jsonl", "--output", "catalogue.jsonl", "one.mp3"]);
+    let catalogue = fs::read_to_string(lib.join("catalogue.jsonl")).unwrap();
+    let rows = catalogue.lines()
+            .map(|x| serde_json::from_str::<serde_json::Value>(x).unwrap())
+            .collect::<Vec<_>>();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["artists"], serde_json::json!(["Catty Band", "Other Band"]));
+    assert_eq!(rows[0]["title"], "One \"Live\"");
+    assert_eq!(rows[0]["codec"], "mp3");
+    assert_eq!(rows[0]["tags"], serde_json::json!({}));
+}
\ No newline at end of file
@@ -0,0 +1,335 @@
+//! Shared helpers for running catty end-to-end against a temporary library,
+//! with a scripted stand-in for `yt-dlp` so that no network access is needed.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path;
+use std::process;
+
+use id3::TagLike;
+
+/// Size of a single MPEG-1 Layer III frame at 128kbps and 44.1kHz.
+const MP3_FRAME_SIZE : usize = 417;
+
+/// A temporary music library.
+pub struct Library {
+    dir : tempfile::TempDir,
+    fixtures : tempfile::TempDir,
+}
+
+/// Tags to write to a generated audio file.
+#[derive(Default, Clone)]
+pub struct Tags<'a> {
+    pub artist : Option<&'a str>,
+    pub album : Option<&'a str>,
+    pub album_artist : Option<&'a str>,
+    pub title : Option<&'a str>,
+    pub track : Option<u32>,
+}
+
+/// An item that the fake `yt-dlp` can "download".
+pub struct Upload<'a> {
+    pub uri : &'a str,
+    pub id : &'a str,
+    pub file_name : &'a str,
+    pub tags : Tags<'a>,
+}
+
+/// The output of a catty command.
+pub struct Output {
+    pub success : bool,
+    pub stdout : String,
+    pub stderr : String,
+}
+
+impl Library {
+    pub fn new() -> Self {
+        Self {
+            dir : tempfile::tempdir().expect("cannot create library dir"),
+            fixtures : tempfile::tempdir().expect("cannot create fixtures dir"),
+        }
+    }
+
+    pub fn path(&self) -> &path::Path {
+        self.dir.path()
+    }
+
+    pub fn join(&self, rel_path : &str) -> path::PathBuf {
+        self.dir.path().join(rel_path)
+    }
+
+    pub fn exists(&self, rel_path : &str) -> bool {
+        self.join(rel_path).exists()
+    }
+
+    pub fn write_config(&self, config : &str) {
+        fs::write(self.join("catty.toml"), config).expect("cannot write config");
+    }
+
+    /// Creates a small MP3 file inside of the library, with the given tags.
+    pub fn add_mp3(&self, rel_path : &str, tags : &Tags) -> path::PathBuf {
+        let file_path = self.join(rel_path);
+        write_mp3(&file_path, tags);
+        file_path
+    }
+
+    /// Creates an MP3 file with a specific number of frames, so that its audio
+    /// differs from other generated files.
+    pub fn add_mp3_frames(&self, rel_path : &str, tags : &Tags, frames : usize) -> path::PathBuf {
+        let file_path = self.join(rel_path);
+        write_mp3_frames(&file_path, tags, frames);
+        file_path
+    }
+
+    /// Creates a FLAC file inside of the library which holds only its stream
+    /// info, followed by filler in place of audio frames.
+    pub fn add_flac(&self, rel_path : &str, sample_rate : u32, total_samples : u64) -> path::PathBuf {
+        let file_path = self.join(rel_path);
+        write_flac(&file_path, sample_rate, total_samples);
+        file_path
+    }
+
+    /// Creates an Ogg Opus file with the given Vorbis comments, followed by a
+    /// few packets in place of audio.
+    pub fn add_opus(&self, rel_path : &str, comments : &[(&str, &str)]) -> path::PathBuf {
+        let file_path = self.join(rel_path);
+        write_opus(&file_path, comments);
+        file_path
+    }
+
+    /// Lists every file in the library relative to its root, ignoring catty's
+    /// own state and config.
+    pub fn files(&self) -> Vec<String> {
+        let mut files = Vec::new();
+        collect_files(self.path(), self.path(), &mut files);
+        files.retain(|x| !x.starts_with(".catty/") && x != "catty.toml");
+        files.sort();
+        files
+    }
+
+    /// Installs a fake `yt-dlp` which "downloads" the given uploads by copying
+    /// pre-generated fixtures, and registers it in the library config along
+    /// with any extra config.
+    pub fn install_fake_ytdlp(&self, uploads : &[Upload], extra_config : &str) {
+        let script_path = self.fixtures.path().join("yt-dlp");
+        let args_log = self.fixtures.path().join("args.log");
+        let mut script = String::new();
+        script.push_str("#!/bin/sh\n");
+        script.push_str(&format!("printf '%s\\n' \"$@\" >> {}\n", quote(&args_log)));
+        script.push_str("out_dir=.\nprint_file=\narchive=\nprev=\n");
+        script.push_str("for arg in \"$@\"; do\n");
+        script.push_str("  case \"$prev\" in\n");
+        script.push_str("    --print-to-file) prev=template; continue ;;\n");
+        script.push_str("    template) print_file=\"$arg\" ;;\n");
+        script.push_str("    --paths|-P) out_dir=\"$arg\" ;;\n");
+        script.push_str("    --download-archive) archive=\"$arg\" ;;\n");
+        script.push_str("  esac\n");
+        script.push_str("  prev=\"$arg\"\n");
+        script.push_str("done\n");
+        // like `yt-dlp`, relative output files are resolved under `--paths`
+        script.push_str("case \"$print_file\" in\n");
+        script.push_str("  ''|/*) ;;\n");
+        script.push_str("  *) print_file=\"$out_dir/$print_file\"; mkdir -p \"$(dirname \"$print_file\")\" ;;\n");
+        script.push_str("esac\n");
+        for upload in uploads {
+            let fixture_path = self.fixtures.path().join(upload.id);
+            write_mp3(&fixture_path, &upload.tags);
+            let archive_entry = format!("fake {}", upload.id);
+            script.push_str(&format!(
+                "if printf '%s\\n' \"$@\" | grep -qxF {uri}; then\n\
+                 \x20 if [ -z \"$archive\" ] || ! grep -qxF {entry} \"$archive\" 2>/dev/null; then\n\
+                 \x20   mkdir -p \"$out_dir\"\n\
+                 \x20   cp {fixture} \"$out_dir\"/{name}\n\
+                 \x20   if [ -n \"$archive\" ]; then echo {entry} >> \"$archive\"; fi\n\
+                 \x20   if [ -n \"$print_file\" ]; then\n\
+                 \x20     printf '%s\\t%s\\t%s\\t%s\\n' \"$(cd \"$out_dir\" && pwd -P)\"/{name} {uri} fake 2026-01-01 >> \"$print_file\"\n\
+                 \x20   fi\n\
+                 \x20 fi\n\
+                 fi\n",
+                uri = quote_str(upload.uri),
+                entry = quote_str(&archive_entry),
+                fixture = quote(&fixture_path),
+                name = quote_str(upload.file_name),
+            ));
+        }
+        fs::write(&script_path, script).expect("cannot write fake yt-dlp");
+        make_executable(&script_path);
+        self.write_config(&format!("yt-dlp = {:?}\n{}", script_path.display().to_string(), extra_config));
+    }
+
+    /// Returns each argument passed to the fake `yt-dlp`, across every call.
+    pub fn ytdlp_args(&self) -> Vec<String> {
+        let args_log = self.fixtures.path().join("args.log");
+        let args = fs::read_to_string(args_log).unwrap_or_default();
+        args.lines().map(String::from).collect()
+    }
+
+    /// Writes a shell script into the fixtures, returning its path.
+    pub fn install_script(&self, name : &str, body : &str) -> path::PathBuf {
+        let script_path = self.fixtures.path().join(name);
+        fs::write(&script_path, format!("#!/bin/sh\n{}", body)).expect("cannot write script");
+        make_executable(&script_path);
+        script_path
+    }
+
+    /// Runs catty inside of the library, answering yes to every prompt.
+    pub fn run(&self, args : &[&str]) -> Output {
+        let output = self.try_run(args);
+        assert!(!output.stderr.contains("fatal error"), "catty failed:\n{}", output.stderr);
+        output
+    }
+
+    /// Runs catty like `run`, but allows it to fail.
+    pub fn try_run(&self, args : &[&str]) -> Output {
+        let output = process::Command::new(env!("CARGO_BIN_EXE_catty"))
+                .current_dir(self.path())
+                .arg("--yes")
+                .args(args)
+                .env("RUST_LOG", "debug")
+                .stdin(process::Stdio::null())
+                .output()
+                .expect("cannot run catty");
+        Output {
+            success : output.status.success(),
+            stdout : String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr : String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+    }
+}
+
+/// Writes an MP3 file made up of silent frames, with an ID3v2 tag.
+pub fn write_mp3(file_path : &path::Path, tags : &Tags) {
+    write_mp3_frames(file_path, tags, 8);
+}
+
+fn write_mp3_frames(file_path : &path::Path, tags : &Tags, frames : usize) {
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).expect("cannot create fixture dir");
+    }
+    let mut frame = vec![0u8; MP3_FRAME_SIZE];
+    frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+    let mut audio = Vec::new();
+    for _ in 0..frames {
+        audio.extend_from_slice(&frame);
+    }
+    fs::write(file_path, audio).expect("cannot write fixture");
+    let mut tag = id3::Tag::new();
+    if let Some(artist) = tags.artist {
+        tag.set_artist(artist);
+    }
+    if let Some(album) = tags.album {
+        tag.set_album(album);
+    }
+    if let Some(album_artist) = tags.album_artist {
+        tag.set_album_artist(album_artist);
+    }
+    if let Some(title) = tags.title {
+        tag.set_title(title);
+    }
+    if let Some(track) = tags.track {
+        tag.set_track(track);
+    }
+    tag.write_to_path(file_path, id3::Version::Id3v24).expect("cannot write fixture tags");
+}
+
+fn write_flac(file_path : &path::Path, sample_rate : u32, total_samples : u64) {
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).expect("cannot create fixture dir");
+    }
+    let mut data = b"fLaC".to_vec();
+    // a single metadata block, which is the last one
+    data.extend_from_slice(&[0x80, 0, 0, 34]);
+    data.extend_from_slice(&4096u16.to_be_bytes());
+    data.extend_from_slice(&4096u16.to_be_bytes());
+    data.extend_from_slice(&[0; 6]);
+    // sample rate, channels - 1, bits per sample - 1, and total samples
+    let packed = ((sample_rate as u64) << 44) | (1 << 41) | (15 << 36) | total_samples;
+    data.extend_from_slice(&packed.to_be_bytes());
+    data.extend_from_slice(&[0; 16]);
+    // gives each file different "audio"
+    data.extend_from_slice(file_path.to_string_lossy().as_bytes());
+    fs::write(file_path, data).expect("cannot write fixture");
+}
+
+/// The packets written in place of the audio of an Opus file, with the
+/// granule position of the page that each one ends on.
+pub const OPUS_AUDIO : [(&[u8], u64); 3] = [(b"\xF8one", 1920), (b"\xF8two", 1920), (b"\xF8three", 2880)];
+
+fn write_opus(file_path : &path::Path, comments : &[(&str, &str)]) {
+    use ogg::PacketWriteEndInfo::{EndPage, EndStream, NormalPacket};
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).expect("cannot create fixture dir");
+    }
+    // version, channels, pre-skip, input sample rate, gain and mapping family
+    let mut head = b"OpusHead\x01\x02".to_vec();
+    head.extend_from_slice(&312u16.to_le_bytes());
+    head.extend_from_slice(&48000u32.to_le_bytes());
+    head.extend_from_slice(&[0, 0, 0]);
+    let mut tags = b"OpusTags".to_vec();
+    let vendor = b"catty tests";
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor);
+    tags.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for (key, value) in comments {
+        let comment = format!("{}={}", key, value);
+        tags.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        tags.extend_from_slice(comment.as_bytes());
+    }
+    let mut writer = ogg::PacketWriter::new(Vec::new());
+    writer.write_packet(head.into_boxed_slice(), 1, EndPage, 0).unwrap();
+    writer.write_packet(tags.into_boxed_slice(), 1, EndPage, 0).unwrap();
+    for (i, (packet, absgp)) in OPUS_AUDIO.iter().enumerate() {
+        let end = match i {
+            1 => EndPage,
+            2 => EndStream,
+            _ => NormalPacket,
+        };
+        writer.write_packet(packet.to_vec().into_boxed_slice(), 1, end, *absgp).unwrap();
+    }
+    fs::write(file_path, writer.into_inner()).expect("cannot write fixture");
+}
+
+/// Reads the packets of an Ogg file, with the granule position of the page
+/// that each one ends on.
+pub fn read_ogg_packets(file_path : &path::Path) -> Vec<(Vec<u8>, u64)> {
+    let mut reader = ogg::PacketReader::new(fs::File::open(file_path).unwrap());
+    let mut packets = Vec::new();
+    while let Some(packet) = reader.read_packet().expect("invalid Ogg file") {
+        let absgp = packet.absgp_page();
+        packets.push((packet.data, absgp));
+    }
+    packets
+}
+
+fn collect_files(root : &path::Path, dir : &path::Path, files : &mut Vec<String>) {
+    for entry in fs::read_dir(dir).expect("cannot read library dir") {
+        let entry_path = entry.expect("cannot read library entry").path();
+        if entry_path.is_dir() {
+            collect_files(root, &entry_path, files);
+        } else {
+            let rel_path = entry_path.strip_prefix(root).unwrap();
+            files.push(rel_path.to_string_lossy().replace('\\', "/"));
+        }
+    }
+}
+
+fn quote(file_path : &path::Path) -> String {
+    quote_str(&file_path.display().to_string())
+}
+
+fn quote_str(value : &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(unix)]
+fn make_executable(file_path : &path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(file_path).unwrap().permissions();
+    permissions.set_mode(0o755);
+    fs::set_permissions(file_path, permissions).unwrap();
+}
+
+#[cfg(not(unix))]
+fn make_executable(_file_path : &path::Path) {
+    panic!("the fake `yt-dlp` is a shell script, and requires a unix-like system");
+}
\ No newline at end of file
@@ -0,0 +1,63 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+fn library() -> Library {
+    let lib = Library::new();
+    lib.add_mp3("one.mp3", &Tags { artist : Some("Catty Band"), title : Some("One"), ..Tags::default() });
+    lib.add_mp3("two.mp3", &Tags { artist : Some("Catty Band"), album : Some("Demo"), ..Tags::default() });
+    lib
+}
+
+fn read_tag(lib : &Library, rel_path : &str) -> id3::Tag {
+    id3::Tag::read_from_path(lib.join(rel_path)).unwrap()
+}
+
+#[test]
+fn import_edited_export() {
+    use id3::TagLike;
+    let lib = library();
+    lib.run(&["export", "--output", "catalogue.csv"]);
+    let catalogue = fs::read_to_string(lib.join("catalogue.csv")).unwrap();
+    let catalogue = catalogue
+            .replace("one.mp3,one.mp3,Catty Band,", "one.mp3,one.mp3,Catty Band; Other Band,")
+            .replace(",Demo,,,,two,", ",,,2,2019,\"Two, Live\",");
+    fs::write(lib.join("catalogue.csv"), catalogue).unwrap();
+    // a dry run only shows the changes
+    let output = lib.run(&["import-tags", "--dry-run", "catalogue.csv"]);
+    assert_eq!(output.stdout.lines().collect::<Vec<_>>(), [
+        "one.mp3",
+        "  artist        Catty Band => Catty Band, Other Band",
+        "two.mp3",
+        "  album         Demo => -",
+        "  track number  - => 2",
+        "  year          - => 2019",
+        "  title         - => Two, Live",
+    ]);
+    assert_eq!(read_tag(&lib, "two.mp3").album(), Some("Demo"));
+    lib.run(&["import-tags", "catalogue.csv"]);
+    let one = read_tag(&lib, "one.mp3");
+    assert_eq!(one.artist(), Some("Catty Band, Other Band"));
+    assert_eq!(one.title(), Some("One"));
+    let two = read_tag(&lib, "two.mp3");
+    assert_eq!(two.album(), None);
+    assert_eq!(two.title(), Some("Two, Live"));
+    assert_eq!(two.track(), Some(2));
+    // importing again changes nothing
+    let output = lib.run(&["import-tags", "catalogue.csv"]);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn import_matches_moved_files_by_source() {
+    use id3::TagLike;
+    let lib = library();
+    fs::write(lib.join("changes.csv"), "path,source,title\nold/one.mp3,https://example.com/one,Renamed\n").unwrap();
+    let mut tag = read_tag(&lib, "one.mp3");
+    tag.add_frame(id3::frame::ExtendedText { description : "SOURCE".to_string(), value : "https://example.com/one".to_string() });
+    tag.write_to_path(lib.join("one.mp3"), id3::Version::Id3v24).unwrap();
+    lib.run(&["import-tags", "changes.csv"]);
+    assert_eq!(read_tag(&lib, "one.mp3").title(), Some("Renamed"));
+}
\ No newline at end of file
@@ -0,0 +1,86 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+fn read_index(lib : &Library) -> String {
+    fs::read_to_string(lib.join(".catty/index.tsv")).unwrap_or_default()
+}
+
+#[test]
+fn index_caches_metadata() {
+    let lib = Library::new();
+    lib.add_mp3("Artist - Song.mp3", &Tags { artist : Some("Artist"), title : Some("Song"), ..Tags::default() });
+    lib.add_mp3("nested/Other - Tune.mp3", &Tags { artist : Some("Other"), title : Some("Tune"), ..Tags::default() });
+    lib.run(&["index"]);
+    let index = read_index(&lib);
+    assert!(index.starts_with("catty-index 6\n"));
+    assert!(index.contains("Artist - Song.mp3\t"), "{}", index);
+    assert!(index.contains("nested/Other - Tune.mp3\t"), "{}", index);
+}
+
+#[test]
+fn index_reparses_changed_files() {
+    let lib = Library::new();
+    let file = lib.add_mp3("Artist - Song.mp3", &Tags { artist : Some("Artist"), title : Some("Song"), ..Tags::default() });
+    lib.run(&["index"]);
+    assert!(read_index(&lib).contains("\tSong\t"));
+    harness::write_mp3(&file, &Tags { artist : Some("Artist"), title : Some("Changed Song Title"), ..Tags::default() });
+    let output = lib.run(&["inspect", "Artist - Song.mp3"]);
+    assert!(output.stdout.contains("Changed Song Title"), "{}", output.stdout);
+    assert!(read_index(&lib).contains("\tChanged Song Title\t"));
+}
+
+#[test]
+fn index_infers_metadata_after_renames() {
+    let lib = Library::new();
+    lib.add_mp3("Catty Band - Song.mp3", &Tags::default());
+    lib.run(&["index"]);
+    assert!(read_index(&lib).contains("\tCatty Band\t"), "{}", read_index(&lib));
+    // the artist was only known from the old file name
+    lib.run(&["rename", "--format", "t"]);
+    assert_eq!(lib.files(), ["Song.mp3"]);
+    assert!(!read_index(&lib).contains("Catty Band"), "{}", read_index(&lib));
+}
+
+#[test]
+fn index_follows_renames() {
+    let lib = Library::new();
+    lib.add_mp3("song.mp3", &Tags { artist : Some("Artist"), title : Some("Song"), ..Tags::default() });
+    lib.run(&["index"]);
+    lib.run(&["rename"]);
+    assert_eq!(lib.files(), ["Artist - Song.mp3"]);
+    let index = read_index(&lib);
+    assert!(index.contains("\nArtist - Song.mp3\t"), "{}", index);
+    assert!(!index.contains("\nsong.mp3\t"), "{}", index);
+}
+
+#[test]
+fn index_rebuild_removes_stale_entries() {
+    let lib = Library::new();
+    lib.add_mp3("Artist - Song.mp3", &Tags { artist : Some("Artist"), ..Tags::default() });
+    lib.run(&["index"]);
+    fs::remove_file(lib.join("Artist - Song.mp3")).unwrap();
+    lib.run(&["index", "--rebuild"]);
+    assert_eq!(read_index(&lib), "catty-index 6\n");
+}
+
+#[test]
+fn index_caches_collection_authors() {
+    let lib = Library::new();
+    let tags = Tags { artist : Some("Catty Band"), album_artist : Some("Catty Band"), album : Some("Demo"), ..Tags::default() };
+    lib.add_mp3("Demo/01 - One.mp3", &Tags { title : Some("One"), ..tags.clone() });
+    lib.add_mp3("Loose/02 - Two.mp3", &Tags { title : Some("Two"), ..tags.clone() });
+    lib.run(&["index"]);
+    let index = read_index(&lib);
+    let line = |key : &str| index.lines().find(|x| x.starts_with(key)).unwrap().to_string();
+    // only tracks inside of a directory named after their album belong to it
+    assert!(line("Demo/01 - One.mp3\t").ends_with("\tCatty Band\tmp3\t"), "{}", index);
+    assert!(line("Loose/02 - Two.mp3\t").ends_with("\t\tmp3\t"), "{}", index);
+    lib.run(&["sort", "Demo/*", "Loose/*"]);
+    assert_eq!(lib.files(), [
+        "A-F/Catty Band/Demo/01 - One.mp3",
+        "A-F/Catty Band/Demo/02 - Two.mp3",
+    ]);
+}
\ No newline at end of file
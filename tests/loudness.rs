@@ -0,0 +1,120 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+/// Reports tracks with `loud` in their name as louder than the rest, and
+/// logs each file it measures.
+const FFMPEG : &str = r#"echo "$4" >> "$(dirname "$0")/ffmpeg.log"
+case "$4" in
+    *loud*) loudness=-12.0; peak=-1.0 ;;
+    *) loudness=-20.0; peak=-3.0 ;;
+esac
+cat >&2 <<END
+  Duration: 00:00:10.00, start: 0.000000, bitrate: 128 kb/s
+[Parsed_ebur128_0 @ 0x0] Summary:
+
+  Integrated loudness:
+    I:         $loudness LUFS
+    Threshold: -30.0 LUFS
+
+  True peak:
+    Peak:       $peak dBFS
+END
+"#;
+
+fn library() -> (Library, std::path::PathBuf) {
+    let lib = Library::new();
+    let album = Tags { artist : Some("Catty Band"), album : Some("Demo"), ..Tags::default() };
+    // measurements are cached by audio, so each track needs different audio
+    lib.add_mp3_frames("Demo/loud.mp3", &Tags { title : Some("Loud"), ..album.clone() }, 2);
+    lib.add_mp3_frames("Demo/quiet.mp3", &Tags { title : Some("Quiet"), ..album }, 3);
+    let single = Tags { artist : Some("Catty Band"), title : Some("Single"), ..Tags::default() };
+    lib.add_mp3_frames("single.mp3", &single, 4);
+    let script = lib.install_script("ffmpeg", FFMPEG);
+    lib.write_config(&format!("ffmpeg = {:?}\n", script.display().to_string()));
+    let log_path = script.with_file_name("ffmpeg.log");
+    (lib, log_path)
+}
+
+fn read_gain(lib : &Library, rel_path : &str) -> Vec<(String, String)> {
+    let tag = id3::Tag::read_from_path(lib.join(rel_path)).unwrap();
+    let mut fields = tag.extended_texts()
+            .filter(|x| x.description.starts_with("REPLAYGAIN_"))
+            .map(|x| (x.description.clone(), x.value.clone()))
+            .collect::<Vec<_>>();
+    fields.sort();
+    fields
+}
+
+fn fields(values : &[(&str, &str)]) -> Vec<(String, String)> {
+    values.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+#[test]
+fn write_track_and_album_gain() {
+    let (lib, log_path) = library();
+    lib.run(&["loudness"]);
+    assert_eq!(read_gain(&lib, "Demo/loud.mp3"), fields(&[
+        ("REPLAYGAIN_ALBUM_GAIN", "-3.63 dB"),
+        ("REPLAYGAIN_ALBUM_PEAK", "0.891251"),
+        ("REPLAYGAIN_TRACK_GAIN", "-6.00 dB"),
+        ("REPLAYGAIN_TRACK_PEAK", "0.891251"),
+    ]));
+    assert_eq!(read_gain(&lib, "Demo/quiet.mp3"), fields(&[
+        ("REPLAYGAIN_ALBUM_GAIN", "-3.63 dB"),
+        ("REPLAYGAIN_ALBUM_PEAK", "0.891251"),
+        ("REPLAYGAIN_TRACK_GAIN", "2.00 dB"),
+        ("REPLAYGAIN_TRACK_PEAK", "0.707946"),
+    ]));
+    // tracks outside of an album only get a track gain
+    assert_eq!(read_gain(&lib, "single.mp3"), fields(&[
+        ("REPLAYGAIN_TRACK_GAIN", "2.00 dB"),
+        ("REPLAYGAIN_TRACK_PEAK", "0.707946"),
+    ]));
+    assert_eq!(fs::read_to_string(&log_path).unwrap().lines().count(), 3);
+}
+
+#[test]
+fn reuse_cached_analysis() {
+    use id3::TagLike;
+    let (lib, log_path) = library();
+    lib.run(&["loudness"]);
+    // changing tags keeps the audio the same, so it isn't measured again
+    let mut tag = id3::Tag::read_from_path(lib.join("single.mp3")).unwrap();
+    tag.set_title("Renamed");
+    tag.write_to_path(lib.join("single.mp3"), id3::Version::Id3v24).unwrap();
+    lib.run(&["loudness"]);
+    assert_eq!(fs::read_to_string(&log_path).unwrap().lines().count(), 3);
+    lib.run(&["loudness", "--force", "single.mp3"]);
+    assert_eq!(fs::read_to_string(&log_path).unwrap().lines().count(), 4);
+}
+
+#[test]
+fn album_gain_uses_every_track() {
+    let (lib, log_path) = library();
+    lib.run(&["loudness", "Demo/quiet.mp3"]);
+    // the rest of the album is measured, so the album gain is the same
+    assert_eq!(read_gain(&lib, "Demo/quiet.mp3"), fields(&[
+        ("REPLAYGAIN_ALBUM_GAIN", "-3.63 dB"),
+        ("REPLAYGAIN_ALBUM_PEAK", "0.891251"),
+        ("REPLAYGAIN_TRACK_GAIN", "2.00 dB"),
+        ("REPLAYGAIN_TRACK_PEAK", "0.707946"),
+    ]));
+    assert_eq!(read_gain(&lib, "Demo/loud.mp3")[0], ("REPLAYGAIN_ALBUM_GAIN".to_string(), "-3.63 dB".to_string()));
+    assert_eq!(fs::read_to_string(&log_path).unwrap().lines().count(), 2);
+    assert!(read_gain(&lib, "single.mp3").is_empty());
+}
+
+#[test]
+fn write_r128_gain_to_opus() {
+    let (lib, _) = library();
+    lib.add_opus("one.opus", &[("TITLE", "One"), ("REPLAYGAIN_TRACK_GAIN", "1.00 dB")]);
+    lib.run(&["loudness", "one.opus"]);
+    let tags = String::from_utf8_lossy(&harness::read_ogg_packets(&lib.join("one.opus"))[1].0).into_owned();
+    // the gain is relative to -23 LUFS, in 1/256 dB
+    assert!(tags.contains("R128_TRACK_GAIN=-768"), "{}", tags);
+    assert!(!tags.contains("REPLAYGAIN"), "{}", tags);
+    assert!(tags.contains("TITLE=One"), "{}", tags);
+}
\ No newline at end of file
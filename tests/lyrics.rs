@@ -0,0 +1,57 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+const LRC : &str = "[ar:Catty Band]\n[00:01.50]first line\n[00:04.00][00:10.25]chorus\n";
+
+fn library() -> Library {
+    let lib = Library::new();
+    lib.add_mp3("one.mp3", &Tags { artist : Some("Catty Band"), title : Some("One"), ..Tags::default() });
+    lib.add_mp3("two.mp3", &Tags { artist : Some("Catty Band"), title : Some("Two"), ..Tags::default() });
+    fs::write(lib.join("one.lrc"), LRC).unwrap();
+    lib
+}
+
+#[test]
+fn embed_and_extract_lyrics() {
+    let lib = library();
+    lib.run(&["lyrics", "embed"]);
+    let tag = id3::Tag::read_from_path(lib.join("one.mp3")).unwrap();
+    assert_eq!(tag.lyrics().next().map(|x| x.text.as_str()), Some(LRC));
+    let synced = tag.synchronised_lyrics().next().unwrap();
+    assert_eq!(synced.content, [
+        (1500, "first line".to_string()),
+        (4000, "chorus".to_string()),
+        (10250, "chorus".to_string()),
+    ]);
+    fs::remove_file(lib.join("one.lrc")).unwrap();
+    lib.run(&["lyrics", "extract"]);
+    assert_eq!(fs::read_to_string(lib.join("one.lrc")).unwrap(), LRC);
+    assert!(!lib.exists("two.lrc"));
+}
+
+#[test]
+fn extract_synchronised_lyrics() {
+    use id3::TagLike;
+    let lib = library();
+    let mut tag = id3::Tag::read_from_path(lib.join("two.mp3")).unwrap();
+    tag.add_frame(id3::frame::SynchronisedLyrics {
+        lang : "eng".to_string(),
+        timestamp_format : id3::frame::TimestampFormat::Ms,
+        content_type : id3::frame::SynchronisedLyricsType::Lyrics,
+        description : String::new(),
+        content : vec![(500, "hello".to_string()), (61_230, "goodbye".to_string())],
+    });
+    tag.write_to_path(lib.join("two.mp3"), id3::Version::Id3v24).unwrap();
+    lib.run(&["lyrics", "extract", "two.mp3"]);
+    assert_eq!(fs::read_to_string(lib.join("two.lrc")).unwrap(), "[00:00.50]hello\n[01:01.23]goodbye\n");
+}
+
+#[test]
+fn list_missing_lyrics() {
+    let lib = library();
+    let output = lib.run(&["lyrics", "missing"]);
+    assert_eq!(output.stdout, "two.mp3\n");
+}
\ No newline at end of file
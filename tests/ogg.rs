@@ -0,0 +1,51 @@
+mod harness;
+
+use std::fs;
+
+use harness::Library;
+
+const LYRICS : &str = "[00:01.50]first line\n[00:04.00]second line\n";
+
+/// Checks that the headers and audio of a rewritten Opus file are intact.
+fn assert_audio_kept(lib : &Library, rel_path : &str) {
+    let packets = harness::read_ogg_packets(&lib.join(rel_path));
+    assert_eq!(packets.len(), 2 + harness::OPUS_AUDIO.len());
+    assert!(packets[0].0.starts_with(b"OpusHead"));
+    assert!(packets[1].0.starts_with(b"OpusTags"));
+    for ((data, absgp), (expected, expected_absgp)) in packets[2..].iter().zip(harness::OPUS_AUDIO) {
+        assert_eq!((data.as_slice(), *absgp), (expected, expected_absgp));
+    }
+}
+
+#[test]
+fn embed_and_extract_opus_lyrics() {
+    let lib = Library::new();
+    lib.add_opus("one.opus", &[("ARTIST", "Catty Band"), ("UNSYNCEDLYRICS", "old words")]);
+    lib.add_opus("two.opus", &[("ARTIST", "Catty Band")]);
+    fs::write(lib.join("one.lrc"), LYRICS).unwrap();
+    let output = lib.run(&["lyrics", "missing"]);
+    assert_eq!(output.stdout, "two.opus\n");
+    lib.run(&["lyrics", "embed", "--force", "one.opus"]);
+    assert_audio_kept(&lib, "one.opus");
+    fs::remove_file(lib.join("one.lrc")).unwrap();
+    lib.run(&["lyrics", "extract", "one.opus"]);
+    assert_eq!(fs::read_to_string(lib.join("one.lrc")).unwrap(), LYRICS);
+    let tags = String::from_utf8_lossy(&harness::read_ogg_packets(&lib.join("one.opus"))[1].0).into_owned();
+    assert!(tags.contains("ARTIST=Catty Band"), "{}", tags);
+    assert!(!tags.contains("old words"), "{}", tags);
+}
+
+#[test]
+fn embed_opus_cover() {
+    let lib = Library::new();
+    // the album is found from the path, since only custom fields are read
+    let track = "Catty Band/Demo/Catty Band - One.opus";
+    lib.add_opus(track, &[("TITLE", "One")]);
+    let image = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0xFF, 0xD9];
+    fs::write(lib.join("Catty Band/Demo/cover.jpg"), &image).unwrap();
+    lib.run(&["art", "embed"]);
+    assert_audio_kept(&lib, track);
+    fs::remove_file(lib.join("Catty Band/Demo/cover.jpg")).unwrap();
+    lib.run(&["art", "extract"]);
+    assert_eq!(fs::read(lib.join("Catty Band/Demo/cover.jpg")).unwrap(), image);
+}
\ No newline at end of file
@@ -0,0 +1,140 @@
+mod harness;
+
+use harness::{Library, Tags, Upload};
+
+fn song_one() -> Upload<'static> {
+    Upload {
+        uri : "https://example.com/watch?v=one",
+        id : "one",
+        file_name : "Catty Band - Song One (Official Audio).mp3",
+        tags : Tags {
+            artist : Some("Catty Band"),
+            album : Some("First Album"),
+            title : Some("Song One"),
+            track : Some(1),
+            ..Tags::default()
+        },
+    }
+}
+
+fn song_two() -> Upload<'static> {
+    Upload {
+        uri : "https://example.com/watch?v=two",
+        id : "two",
+        file_name : "Zebra Crossing - Night Walk.mp3",
+        tags : Tags {
+            artist : Some("Zebra Crossing"),
+            title : Some("Night Walk"),
+            ..Tags::default()
+        },
+    }
+}
+
+#[test]
+fn add_downloads_with_configured_formats() {
+    let lib = Library::new();
+    lib.install_fake_ytdlp(&[song_one()], "[add]\nformats = [\"opus\", \"m4a\"]\n");
+    lib.run(&["add", "https://example.com/watch?v=one"]);
+    assert_eq!(lib.files(), ["Catty Band - Song One (Official Audio).mp3"]);
+    let args = lib.ytdlp_args();
+    assert!(args.iter().any(|x| x == "ba[ext=opus]/ba[ext=m4a]/ba"), "args: {:?}", args);
+    assert!(args.iter().any(|x| x == "--no-playlist"));
+}
+
+#[test]
+fn add_skips_thumbnail_for_wav() {
+    let lib = Library::new();
+    let ffmpeg_path = lib.install_script("ffmpeg", "exit 0");
+    lib.install_fake_ytdlp(&[song_one()], &format!(
+            "ffmpeg = {:?}\n[add]\ntranscode = \"wav\"\n", ffmpeg_path.display().to_string()));
+    lib.run(&["add", "https://example.com/watch?v=one"]);
+    let args = lib.ytdlp_args();
+    assert!(args.iter().any(|x| x == "wav"), "args: {:?}", args);
+    assert!(args.iter().any(|x| x == "--embed-metadata"));
+    assert!(!args.iter().any(|x| x == "--embed-thumbnail"));
+}
+
+#[test]
+fn add_records_source() {
+    let lib = Library::new();
+    lib.install_fake_ytdlp(&[song_one()], "");
+    lib.run(&["add", "https://example.com/watch?v=one"]);
+    let output = lib.run(&["inspect"]);
+    assert!(output.stdout.contains("https://example.com/watch?v=one"), "{}", output.stdout);
+    assert!(output.stdout.contains("2026-01-01"), "{}", output.stdout);
+}
+
+#[test]
+fn add_rename_sort() {
+    let lib = Library::new();
+    lib.install_fake_ytdlp(&[song_one(), song_two()], "");
+    lib.run(&["add", "https://example.com/watch?v=one", "https://example.com/watch?v=two"]);
+    lib.run(&["rename"]);
+    assert_eq!(lib.files(), [
+        "Catty Band - Song One.mp3",
+        "Zebra Crossing - Night Walk.mp3",
+    ]);
+    lib.run(&["sort"]);
+    assert_eq!(lib.files(), [
+        "A-F/Catty Band/First Album/Catty Band - Song One.mp3",
+        "V-Z/Zebra Crossing/Zebra Crossing - Night Walk.mp3",
+    ]);
+    // the source is still known after the files have been moved
+    let output = lib.run(&["inspect", "A-F/*/*/*.mp3"]);
+    assert!(output.stdout.contains("https://example.com/watch?v=one"), "{}", output.stdout);
+}
+
+#[test]
+fn sort_existing_album() {
+    let lib = Library::new();
+    let tags = Tags {
+        artist : Some("Catty Band"),
+        album_artist : Some("Catty Band"),
+        album : Some("Second Album"),
+        ..Tags::default()
+    };
+    lib.add_mp3("Second Album/01 - Intro.mp3", &Tags { title : Some("Intro"), track : Some(1), ..tags.clone() });
+    lib.add_mp3("Second Album/02 - Outro.mp3", &Tags { title : Some("Outro"), track : Some(2), ..tags.clone() });
+    lib.run(&["sort", "Second Album/*"]);
+    assert_eq!(lib.files(), [
+        "A-F/Catty Band/Second Album/01 - Intro.mp3",
+        "A-F/Catty Band/Second Album/02 - Outro.mp3",
+    ]);
+}
+
+#[test]
+fn sync_skips_archived_items() {
+    let lib = Library::new();
+    lib.install_fake_ytdlp(&[song_one()], concat!(
+        "[[subscription]]\n",
+        "name = \"catty\"\n",
+        "uri = \"https://example.com/watch?v=one\"\n",
+        "destination = \"inbox\"\n",
+        "title = \"Song\"\n",
+    ));
+    lib.run(&["sync"]);
+    assert_eq!(lib.files(), ["inbox/Catty Band - Song One (Official Audio).mp3"]);
+    let args = lib.ytdlp_args();
+    assert!(args.iter().any(|x| x == "title~='Song'"), "args: {:?}", args);
+    let output = lib.run(&["inspect", "inbox/*"]);
+    assert!(output.stdout.contains("https://example.com/watch?v=one"), "{}", output.stdout);
+    // a second sync should not download the file again
+    std::fs::remove_file(lib.join("inbox/Catty Band - Song One (Official Audio).mp3")).unwrap();
+    lib.run(&["sync"]);
+    assert!(lib.files().is_empty());
+}
+
+#[test]
+fn sync_escapes_match_filter() {
+    let lib = Library::new();
+    lib.install_fake_ytdlp(&[song_one()], concat!(
+        "[[subscription]]\n",
+        "name = \"catty\"\n",
+        "uri = \"https://example.com/watch?v=one\"\n",
+        "title = \"Rock & Roll's\"\n",
+        "min-duration = 60\n",
+    ));
+    lib.run(&["sync"]);
+    let args = lib.ytdlp_args();
+    assert!(args.iter().any(|x| x == r"title~='Rock \& Roll\'s' & duration>=60"), "args: {:?}", args);
+}
\ No newline at end of file
@@ -0,0 +1,147 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+fn library() -> Library {
+    let lib = Library::new();
+    lib.add_mp3("A-F/Catty Band/Demo/01 One.mp3", &Tags {
+        artist : Some("Catty Band"), album : Some("Demo"), track : Some(1), title : Some("One"), ..Tags::default()
+    });
+    lib.add_mp3("A-F/Catty Band/Demo/02 Two & Three.mp3", &Tags {
+        artist : Some("Catty Band"), album : Some("Demo"), track : Some(2), title : Some("Two & Three"), ..Tags::default()
+    });
+    lib.add_mp3("Q-U/Other Band/Single.mp3", &Tags {
+        artist : Some("Other Band"), title : Some("Single"), ..Tags::default()
+    });
+    lib
+}
+
+#[test]
+fn create_m3u8_from_query() {
+    let lib = library();
+    lib.run(&["playlist", "create", "Catty Demo", "--where", "artist:\"Catty Band\""]);
+    let playlist = fs::read_to_string(lib.join("playlists/Catty Demo.m3u8")).unwrap();
+    assert_eq!(playlist.lines().collect::<Vec<_>>(), [
+        "#EXTM3U",
+        "#PLAYLIST:Catty Demo",
+        "#EXTINF:0,Catty Band - One",
+        "../A-F/Catty Band/Demo/01 One.mp3",
+        "#EXTINF:0,Catty Band - Two & Three",
+        "../A-F/Catty Band/Demo/02 Two & Three.mp3",
+    ]);
+}
+
+#[test]
+fn update_smart_playlists() {
+    let lib = library();
+    lib.write_config("playlist-dir = \"lists/smart\"\n\
+                      [[playlist]]\nname = \"Demo\"\nwhere = \"album:Demo\"\nformat = \"xspf\"\n\
+                      [[playlist]]\nname = \"Others\"\nwhere = \"-artist:\\\"Catty Band\\\"\"\n");
+    lib.run(&["playlist", "update"]);
+    let demo = fs::read_to_string(lib.join("lists/smart/Demo.xspf")).unwrap();
+    assert!(demo.contains("<location>../../A-F/Catty%20Band/Demo/02%20Two%20%26%20Three.mp3</location>"), "{}", demo);
+    assert!(demo.contains("<title>Two &amp; Three</title>"));
+    assert!(demo.contains("<trackNum>2</trackNum>"));
+    let others = fs::read_to_string(lib.join("lists/smart/Others.m3u8")).unwrap();
+    assert!(others.ends_with("#EXTINF:0,Other Band - Single\n../../Q-U/Other Band/Single.mp3\n"), "{}", others);
+    // new tracks are picked up when the playlists are updated again
+    lib.add_mp3("Q-U/Other Band/B-Side.mp3", &Tags { artist : Some("Other Band"), ..Tags::default() });
+    lib.run(&["playlist", "update", "others"]);
+    let others = fs::read_to_string(lib.join("lists/smart/Others.m3u8")).unwrap();
+    assert!(others.contains("../../Q-U/Other Band/B-Side.mp3"));
+}
+
+#[test]
+fn playlists_follow_sort_and_undo() {
+    let lib = library();
+    fs::write(lib.join("Second Album.m3u"), "#EXTM3U\nSecond/01 - Intro.mp3\n").unwrap();
+    let tags = Tags { artist : Some("Catty Band"), album : Some("Second"), album_artist : Some("Catty Band"), ..Tags::default() };
+    lib.add_mp3("Second/01 - Intro.mp3", &Tags { title : Some("Intro"), track : Some(1), ..tags.clone() });
+    lib.add_mp3("Second/02 - Outro.mp3", &Tags { title : Some("Outro"), track : Some(2), ..tags.clone() });
+    // an album playlist which moves along with the album
+    fs::write(lib.join("Second/album.m3u8"), "01 - Intro.mp3\r\n../Q-U/Other Band/Single.mp3\r\n").unwrap();
+    lib.run(&["sort", "Second/*"]);
+    assert!(lib.exists("A-F/Catty Band/Second/02 - Outro.mp3"));
+    let playlist = fs::read_to_string(lib.join("Second Album.m3u")).unwrap();
+    assert_eq!(playlist, "#EXTM3U\nA-F/Catty Band/Second/01 - Intro.mp3\n");
+    let album = fs::read_to_string(lib.join("A-F/Catty Band/Second/album.m3u8")).unwrap();
+    assert_eq!(album, "01 - Intro.mp3\r\n../../../Q-U/Other Band/Single.mp3\r\n");
+    lib.run(&["undo"]);
+    assert!(lib.exists("Second/02 - Outro.mp3"));
+    assert!(!lib.exists("A-F/Catty Band/Second"));
+    let playlist = fs::read_to_string(lib.join("Second Album.m3u")).unwrap();
+    assert_eq!(playlist, "#EXTM3U\nSecond/01 - Intro.mp3\n");
+    let album = fs::read_to_string(lib.join("Second/album.m3u8")).unwrap();
+    assert_eq!(album, "01 - Intro.mp3\r\n../Q-U/Other Band/Single.mp3\r\n");
+    // there is nothing left to undo
+    lib.run(&["undo"]);
+    assert!(lib.exists("Second/02 - Outro.mp3"));
+}
+
+#[test]
+fn playlists_follow_rename() {
+    let lib = library();
+    lib.run(&["playlist", "create", "Everything"]);
+    lib.run(&["rename", "Q-U/Other Band/Single.mp3"]);
+    let playlist = fs::read_to_string(lib.join("playlists/Everything.m3u8")).unwrap();
+    assert!(playlist.ends_with("#EXTINF:0,Other Band - Single\n../Q-U/Other Band/Other Band - Single.mp3\n"), "{}", playlist);
+}
+
+#[test]
+fn playlists_only_change_moved_entries() {
+    let lib = library();
+    fs::write(lib.join("mix.m3u8"), "./A-F/Catty Band/Demo/01 One.mp3\n./Q-U/Other Band/Single.mp3\n").unwrap();
+    fs::write(lib.join("demo.m3u8"), "./A-F/Catty Band/Demo/01 One.mp3\n").unwrap();
+    lib.run(&["rename", "Q-U/Other Band/Single.mp3"]);
+    let mix = fs::read_to_string(lib.join("mix.m3u8")).unwrap();
+    assert_eq!(mix, "./A-F/Catty Band/Demo/01 One.mp3\nQ-U/Other Band/Other Band - Single.mp3\n");
+    let demo = fs::read_to_string(lib.join("demo.m3u8")).unwrap();
+    assert_eq!(demo, "./A-F/Catty Band/Demo/01 One.mp3\n");
+}
+
+#[test]
+fn fix_broken_entries() {
+    let lib = library();
+    fs::write(lib.join("mix.m3u8"), "#EXTM3U\n\
+            #EXTINF:0,Other Band - Single\n\
+            old/Single (radio edit).mp3\n\
+            /somewhere/else/01 One.mp3\n\
+            http://example.com/stream.mp3\n\
+            missing.mp3\n").unwrap();
+    let output = lib.run(&["playlist", "fix"]);
+    assert_eq!(output.stdout.lines().collect::<Vec<_>>(), [
+        "mix.m3u8",
+        "  old/Single (radio edit).mp3 => Q-U/Other Band/Single.mp3",
+    ]);
+    let playlist = fs::read_to_string(lib.join("mix.m3u8")).unwrap();
+    assert_eq!(playlist, "#EXTM3U\n\
+            #EXTINF:0,Other Band - Single\n\
+            Q-U/Other Band/Single.mp3\n\
+            /somewhere/else/01 One.mp3\n\
+            http://example.com/stream.mp3\n\
+            missing.mp3\n");
+}
+
+#[test]
+fn undo_keeps_moves_which_failed() {
+    let lib = library();
+    let tags = Tags { artist : Some("Catty Band"), album : Some("Second"), album_artist : Some("Catty Band"), ..Tags::default() };
+    lib.add_mp3("inbox/Second/01 - Intro.mp3", &Tags { title : Some("Intro"), track : Some(1), ..tags.clone() });
+    lib.add_mp3("Catty Band - Loose.mp3", &Tags { title : Some("Loose"), ..tags.clone() });
+    lib.run(&["sort", "inbox/Second/*", "Catty Band - Loose.mp3"]);
+    // the directory can't be created again while a file is in its place
+    fs::remove_dir(lib.join("inbox")).unwrap();
+    fs::write(lib.join("inbox"), "").unwrap();
+    let output = lib.try_run(&["undo"]);
+    assert!(output.stderr.contains("1 files are left to undo"), "{}", output.stderr);
+    assert!(lib.exists("Catty Band - Loose.mp3"), "files: {:?}", lib.files());
+    assert!(lib.exists("A-F/Catty Band/Second/01 - Intro.mp3"));
+    fs::remove_file(lib.join("inbox")).unwrap();
+    lib.run(&["undo"]);
+    assert!(lib.exists("inbox/Second/01 - Intro.mp3"));
+    assert!(lib.exists("Catty Band - Loose.mp3"));
+    let journal = fs::read_to_string(lib.join(".catty/journal.jsonl")).unwrap();
+    assert!(journal.is_empty(), "{}", journal);
+}
\ No newline at end of file
@@ -0,0 +1,86 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+/// Answers for every file as if it were a stereo Opus stream.
+const FFPROBE : &str = r#"cat <<END
+{
+    "streams": [
+        { "codec_type": "audio", "codec_name": "opus", "sample_rate": "48000", "channels": 2 }
+    ],
+    "format": { "duration": "184.500000", "bit_rate": "96000" }
+}
+END
+"#;
+
+/// The first page of an Ogg stream holding an Opus header.
+fn opus_header() -> Vec<u8> {
+    let mut page = b"OggS".to_vec();
+    page.extend_from_slice(&[0; 22]);
+    page.extend_from_slice(&[1, 19]);
+    page.extend_from_slice(b"OpusHead");
+    page.extend_from_slice(&[1, 2, 0, 0, 0x80, 0xBB, 0, 0, 0, 0, 0]);
+    page
+}
+
+fn library() -> Library {
+    let lib = Library::new();
+    let tags = Tags { artist : Some("Catty Band"), title : Some("Long"), ..Tags::default() };
+    // 240 frames of 128kbps audio last a little over 6 seconds
+    lib.add_mp3_frames("long.mp3", &tags, 240);
+    lib.add_mp3("short.mp3", &Tags { title : Some("Short"), ..tags });
+    fs::write(lib.join("stream.opus"), opus_header()).unwrap();
+    let script = lib.install_script("ffprobe", FFPROBE);
+    lib.write_config(&format!("ffprobe = {:?}\n", script.display().to_string()));
+    lib
+}
+
+fn ls(lib : &Library, args : &[&str]) -> Vec<String> {
+    let mut full_args = vec!["ls"];
+    full_args.extend_from_slice(args);
+    let output = lib.run(&full_args);
+    output.stdout.lines().map(String::from).collect()
+}
+
+#[test]
+fn probe_audio_properties() {
+    let lib = library();
+    let output = lib.run(&["ls", "--format", "json"]);
+    let tracks : serde_json::Value = serde_json::from_str(&output.stdout).unwrap();
+    let long = &tracks[0];
+    assert_eq!(long["path"], "long.mp3");
+    assert_eq!(long["codec"], "mp3");
+    assert_eq!(long["bitrate"], 128);
+    assert_eq!(long["sample_rate"], 44100);
+    assert_eq!(long["channels"], 2);
+    assert_eq!(long["duration"].as_f64().unwrap().round(), 6.0);
+    // formats which aren't parsed natively fall back to `ffprobe`
+    let stream = &tracks[2];
+    assert_eq!(stream["path"], "stream.opus");
+    assert_eq!(stream["codec"], "opus");
+    assert_eq!(stream["bitrate"], 96);
+    assert_eq!(stream["sample_rate"], 48000);
+    assert_eq!(stream["duration"], 184.5);
+}
+
+#[test]
+fn query_audio_properties() {
+    let lib = library();
+    assert_eq!(ls(&lib, &["duration>=5"]), ["long.mp3", "stream.opus"]);
+    assert_eq!(ls(&lib, &["bitrate<100"]), ["stream.opus"]);
+    assert_eq!(ls(&lib, &["codec:mp3", "sample_rate=44100"]), ["long.mp3", "short.mp3"]);
+    let output = lib.run(&["stats", "--json"]);
+    let stats : serde_json::Value = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(stats["codecs"]["opus"], 1);
+    assert_eq!(stats["sample_rates"]["44100"], 2);
+    assert_eq!(stats["duration_seconds"], 191);
+}
+
+#[test]
+fn rename_with_quality() {
+    let lib = library();
+    lib.run(&["rename", "--format", "atq", "long.mp3"]);
+    assert!(lib.exists("Catty Band - Long [mp3 128kbps].mp3"));
+}
\ No newline at end of file
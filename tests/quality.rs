@@ -0,0 +1,91 @@
+mod harness;
+
+use std::f64::consts::PI;
+use std::fs;
+
+use harness::{Library, Tags};
+
+const SAMPLE_RATE : u32 = 44100;
+
+/// Decodes a file by printing the samples prepared for it, and logs each file
+/// it decodes.
+const FFMPEG : &str = r#"echo "$4" >> "$(dirname "$0")/ffmpeg.log"
+cat "$(dirname "$0")/$(basename "$4").pcm"
+"#;
+
+/// Generates mono audio made of evenly spaced tones, up to a cutoff.
+fn tones(cutoff : f64, sample_rate : u32) -> Vec<u8> {
+    let mut samples = vec![0.0; 4096 * 4];
+    let mut freq = 100.0;
+    while freq <= cutoff {
+        let phase = freq * 0.37;
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample += 0.005 * (2.0 * PI * freq * i as f64 / sample_rate as f64 + phase).sin();
+        }
+        freq += 50.0;
+    }
+    samples.iter().flat_map(|x| (*x as f32).to_le_bytes()).collect()
+}
+
+fn library() -> (Library, std::path::PathBuf) {
+    let lib = Library::new();
+    lib.add_flac("genuine.flac", SAMPLE_RATE, 441000);
+    lib.add_flac("fake.flac", SAMPLE_RATE, 441000);
+    lib.add_mp3("lossy.mp3", &Tags::default());
+    let script = lib.install_script("ffmpeg", FFMPEG);
+    fs::write(script.with_file_name("genuine.flac.pcm"), tones(21_500.0, SAMPLE_RATE)).unwrap();
+    fs::write(script.with_file_name("fake.flac.pcm"), tones(16_000.0, SAMPLE_RATE)).unwrap();
+    lib.write_config(&format!("ffmpeg = {:?}\n", script.display().to_string()));
+    (lib, script.with_file_name("ffmpeg.log"))
+}
+
+#[test]
+fn verify_quality_flags_transcodes() {
+    let (lib, log_path) = library();
+    let output = lib.try_run(&["verify-quality"]);
+    assert!(!output.success);
+    let lines = output.stdout.lines()
+            .map(|x| x.split_whitespace().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{}", output.stdout);
+    assert_eq!((lines[0][0], lines[0][3]), ("lossy", "fake.flac"));
+    assert!(lines[0][1].starts_with("16."), "{}", output.stdout);
+    assert_eq!((lines[1][0], lines[1][3]), ("ok", "genuine.flac"));
+    // the mp3 is lossy to begin with, so isn't decoded
+    assert_eq!(fs::read_to_string(&log_path).unwrap().lines().count(), 2);
+    lib.run(&["verify-quality", "genuine.flac"]);
+    assert_eq!(fs::read_to_string(&log_path).unwrap().lines().count(), 2);
+}
+
+#[test]
+fn check_and_inspect_report_transcodes() {
+    let (lib, _) = library();
+    let output = lib.try_run(&["check", "--skip", "misplaced"]);
+    let issues = output.stdout.lines().filter(|x| x.starts_with("lossy-source")).collect::<Vec<_>>();
+    assert_eq!(issues.len(), 1, "{}", output.stdout);
+    assert!(issues[0].contains("fake.flac: likely transcoded from lossy audio"), "{}", issues[0]);
+    let output = lib.run(&["inspect", "fake.flac", "genuine.flac"]);
+    assert!(output.stdout.contains("  audio         flac, 44100 Hz, 2 channels,"), "{}", output.stdout);
+    assert!(output.stdout.contains("  quality       likely transcoded from lossy audio, spectrum cuts off at 16."), "{}", output.stdout);
+    assert!(output.stdout.contains("  quality       full spectrum up to 21."), "{}", output.stdout);
+}
+
+#[test]
+fn upsampled_audio_is_not_lossy() {
+    let (lib, log_path) = library();
+    // hi-res audio made from CD audio ends at 22.05 kHz, above any lossy
+    // encoder's lowpass
+    lib.add_flac("hires.flac", 96000, 960000);
+    fs::write(log_path.with_file_name("hires.flac.pcm"), tones(22_000.0, 96000)).unwrap();
+    let output = lib.run(&["verify-quality", "hires.flac", "genuine.flac"]);
+    let lines = output.stdout.lines()
+            .map(|x| x.split_whitespace().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+    assert_eq!((lines[1][0], lines[1][3]), ("upsampled", "hires.flac"), "{}", output.stdout);
+    let output = lib.run(&["inspect", "hires.flac"]);
+    assert!(output.stdout.contains("  quality       likely upsampled from a lower sample rate, spectrum cuts off at 2"), "{}", output.stdout);
+    let output = lib.try_run(&["check", "--skip", "misplaced", "hires.flac"]);
+    assert!(!output.stdout.contains("lossy-source"), "{}", output.stdout);
+    // the cached analysis is used, without decoding the file again
+    assert_eq!(fs::read_to_string(&log_path).unwrap().lines().count(), 2);
+}
\ No newline at end of file
@@ -0,0 +1,71 @@
+mod harness;
+
+use harness::{Library, Tags};
+
+fn library() -> Library {
+    let lib = Library::new();
+    lib.add_mp3("a/t+pazolite - Remix.mp3", &Tags {
+        artist : Some("t+pazolite"),
+        album : Some("Remix Works"),
+        title : Some("Remix"),
+        track : Some(3),
+        ..Tags::default()
+    });
+    lib.add_mp3("b/Catty Band - Song.mp3", &Tags {
+        artist : Some("Catty Band"),
+        album_artist : Some("Catty Band"),
+        album : Some("First Album"),
+        title : Some("Song"),
+        track : Some(1),
+    });
+    lib.add_mp3("c/Nobody - Untagged.mp3", &Tags::default());
+    lib
+}
+
+fn ls(lib : &Library, args : &[&str]) -> Vec<String> {
+    let mut full_args = vec!["ls"];
+    full_args.extend_from_slice(args);
+    let output = lib.run(&full_args);
+    output.stdout.lines().map(String::from).collect()
+}
+
+#[test]
+fn ls_fields() {
+    let lib = library();
+    assert_eq!(ls(&lib, &["artist:t+pazolite"]), ["a/t+pazolite - Remix.mp3"]);
+    assert_eq!(ls(&lib, &["album:~remix"]), ["a/t+pazolite - Remix.mp3"]);
+    assert_eq!(ls(&lib, &["missing:album_artist", "has:album"]), ["a/t+pazolite - Remix.mp3"]);
+    assert_eq!(ls(&lib, &["track<=2"]), ["b/Catty Band - Song.mp3"]);
+    assert_eq!(ls(&lib, &["-has:album"]), ["c/Nobody - Untagged.mp3"]);
+}
+
+#[test]
+fn ls_alternatives() {
+    let lib = library();
+    assert_eq!(ls(&lib, &["title:song", "OR", "album:\"remix works\""]), [
+        "a/t+pazolite - Remix.mp3",
+        "b/Catty Band - Song.mp3",
+    ]);
+    assert_eq!(ls(&lib, &["catty"]), ["b/Catty Band - Song.mp3"]);
+}
+
+#[test]
+fn ls_json() {
+    let lib = library();
+    let output = lib.run(&["ls", "--format", "json", "track=1"]);
+    let tracks : serde_json::Value = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(tracks[0]["path"], "b/Catty Band - Song.mp3");
+    assert_eq!(tracks[0]["album_artist"], "Catty Band");
+    assert_eq!(tracks.as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn ls_rejects_unknown_fields() {
+    let lib = library();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_catty"))
+            .current_dir(lib.path())
+            .args(["ls", "colour:blue"])
+            .output()
+            .unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown query field 'colour'"));
+}
\ No newline at end of file
@@ -0,0 +1,48 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+fn library() -> Library {
+    let lib = Library::new();
+    lib.add_mp3("one.mp3", &Tags { artist : Some("Catty Band"), title : Some("One"), ..Tags::default() });
+    lib.add_mp3("two.mp3", &Tags { artist : Some("Catty Band"), ..Tags::default() });
+    lib.add_mp3("nested/three.mp3", &Tags { artist : Some("Other Band"), title : Some("Three"), ..Tags::default() });
+    lib
+}
+
+#[test]
+fn rename_where_query() {
+    let lib = library();
+    lib.run(&["rename", "--where", "artist:\"other band\""]);
+    assert_eq!(lib.files(), [
+        "nested/Other Band - Three.mp3",
+        "one.mp3",
+        "two.mp3",
+    ]);
+}
+
+#[test]
+fn rename_where_query_with_glob() {
+    let lib = library();
+    lib.run(&["rename", "*", "--where", "band -title:two"]);
+    assert_eq!(lib.files(), [
+        "Catty Band - One.mp3",
+        "nested/three.mp3",
+        "two.mp3",
+    ]);
+}
+
+#[test]
+fn sort_from_list() {
+    let lib = library();
+    fs::write(lib.join("list.txt"), "# selected tracks\nnested/three.mp3\n\nmissing.mp3\n").unwrap();
+    lib.run(&["sort", "--from-list", "list.txt"]);
+    assert_eq!(lib.files(), [
+        "L-P/Other Band/three.mp3",
+        "list.txt",
+        "one.mp3",
+        "two.mp3",
+    ]);
+}
\ No newline at end of file
@@ -0,0 +1,70 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+/// Encodes a Matroska element, using an 8 byte size.
+fn ebml(id : &[u8], body : &[u8]) -> Vec<u8> {
+    let mut out = id.to_vec();
+    out.push(0x01);
+    out.extend_from_slice(&(body.len() as u64).to_be_bytes()[1..]);
+    out.extend_from_slice(body);
+    out
+}
+
+/// Creates a WebM file containing tracks of the given types.
+fn webm(track_types : &[u8]) -> Vec<u8> {
+    let mut tracks = Vec::new();
+    for track_type in track_types {
+        tracks.extend(ebml(&[0xAE], &ebml(&[0x83], &[*track_type])));
+    }
+    let mut out = ebml(&[0x1A, 0x45, 0xDF, 0xA3], &ebml(&[0x42, 0x82], b"webm"));
+    out.extend(ebml(&[0x18, 0x53, 0x80, 0x67], &ebml(&[0x16, 0x54, 0xAE, 0x6B], &tracks)));
+    out
+}
+
+fn inspect(lib : &Library) -> Vec<String> {
+    let output = lib.run(&["inspect"]);
+    output.stdout.lines().filter(|x| !x.starts_with(' ')).map(String::from).collect()
+}
+
+#[test]
+fn files_are_detected_by_content() {
+    let lib = Library::new();
+    let tags = Tags { artist : Some("Catty Band"), title : Some("Song"), ..Tags::default() };
+    lib.add_mp3("download", &tags);
+    lib.add_mp3("mislabelled.bin", &tags);
+    fs::write(lib.join("audio.webm"), webm(&[2])).unwrap();
+    fs::write(lib.join("video.webm"), webm(&[1, 2])).unwrap();
+    fs::write(lib.join("notes.txt"), "not audio").unwrap();
+    assert_eq!(inspect(&lib), ["audio.webm", "download", "mislabelled.bin"]);
+}
+
+#[test]
+fn rename_fixes_extensions() {
+    let lib = Library::new();
+    let tags = Tags { artist : Some("Catty Band"), title : Some("Song"), ..Tags::default() };
+    lib.add_mp3("Catty Band - Song.m4a", &tags);
+    lib.add_mp3("Catty Band - Other", &Tags { title : Some("Other"), ..tags.clone() });
+    lib.run(&["rename"]);
+    assert_eq!(lib.files(), ["Catty Band - Other", "Catty Band - Song.m4a"]);
+    lib.run(&["rename", "--fix-extensions"]);
+    assert_eq!(lib.files(), ["Catty Band - Other.mp3", "Catty Band - Song.mp3"]);
+}
+
+#[test]
+fn large_id3_tags_are_skipped() {
+    let lib = Library::new();
+    let flac = fs::read(lib.add_flac("flac", 44100, 44100)).unwrap();
+    // an ID3v2.4 tag which is larger than the header read to identify files
+    let tag_size = 10_000u32;
+    let mut data = b"ID3\x04\x00\x00".to_vec();
+    data.extend((0..4).rev().map(|i| ((tag_size >> (7 * i)) & 0x7F) as u8));
+    data.extend(vec![0; tag_size as usize]);
+    data.extend(flac);
+    fs::write(lib.join("Catty Band - Song"), data).unwrap();
+    fs::remove_file(lib.join("flac")).unwrap();
+    lib.run(&["rename", "--fix-extensions", "Catty Band - Song"]);
+    assert_eq!(lib.files(), ["Catty Band - Song.flac"]);
+}
\ No newline at end of file
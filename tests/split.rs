@@ -0,0 +1,147 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+/// Prints the chapters in `ffmetadata.txt` when asked for metadata, and
+/// otherwise logs the start, duration and output of each track it cuts.
+const FFMPEG : &str = r#"dir="$(dirname "$0")"
+for arg in "$@"; do last="$arg"; done
+case " $* " in
+    *" ffmetadata "*) cat "$dir/ffmetadata.txt" 2>/dev/null; exit 0 ;;
+esac
+start=; duration=; prev=
+for arg in "$@"; do
+    case "$prev" in
+        -ss) start="$arg" ;;
+        -t) duration="$arg" ;;
+    esac
+    prev="$arg"
+done
+echo "$start ${duration:--} $(basename "$last")" >> "$dir/ffmpeg.log"
+: > "$last"
+"#;
+
+fn library() -> (Library, std::path::PathBuf) {
+    let lib = Library::new();
+    let script = lib.install_script("ffmpeg", FFMPEG);
+    lib.write_config(&format!("ffmpeg = {:?}\n", script.display().to_string()));
+    (lib, script.with_file_name("ffmpeg.log"))
+}
+
+fn album_tags() -> Tags<'static> {
+    Tags { artist : Some("Catty Band"), title : Some("Live Set"), ..Tags::default() }
+}
+
+#[test]
+fn split_using_cue_sheet() {
+    let (lib, log_path) = library();
+    lib.add_mp3("Live Set.mp3", &album_tags());
+    fs::write(lib.join("Live Set.cue"), concat!(
+        "PERFORMER \"Catty Band\"\n",
+        "TITLE \"Live at Home\"\n",
+        "FILE \"Live Set.mp3\" MP3\n",
+        "  TRACK 01 AUDIO\n",
+        "    TITLE \"Intro\"\n",
+        "    INDEX 01 00:00:00\n",
+        "  TRACK 02 AUDIO\n",
+        "    TITLE \"Song One\"\n",
+        "    PERFORMER \"Guest\"\n",
+        "    INDEX 00 03:10:00\n",
+        "    INDEX 01 03:12:30\n",
+        "  TRACK 03 AUDIO\n",
+        "    TITLE \"Encore\"\n",
+        "    INDEX 01 07:30:00\n",
+    )).unwrap();
+    lib.run(&["split", "Live Set.mp3"]);
+    // the pregap of `INDEX 00` is ignored, frames are 1/75 of a second, and
+    // the last track runs until the end of the file
+    assert_eq!(fs::read_to_string(&log_path).unwrap(), concat!(
+        "0.000 192.400 01 - Intro.mp3\n",
+        "192.400 257.600 02 - Song One.mp3\n",
+        "450.000 - 03 - Encore.mp3\n",
+    ));
+    assert_eq!(lib.files(), [
+        "Live Set.cue",
+        "Live at Home/01 - Intro.mp3",
+        "Live at Home/02 - Song One.mp3",
+        "Live at Home/03 - Encore.mp3",
+    ]);
+}
+
+#[test]
+fn split_using_description() {
+    let (lib, log_path) = library();
+    lib.add_mp3("Live Set.mp3", &album_tags());
+    fs::write(lib.join("Live Set.description"), concat!(
+        "Recorded live in 2024, thanks for watching!\n",
+        "\n",
+        "Tracklist:\n",
+        "00:00 - Opening\n",
+        "1. Second Song 3:45\n",
+        "[1:02:03] Finale\n",
+    )).unwrap();
+    fs::create_dir_all(lib.join(".catty")).unwrap();
+    fs::write(lib.join(".catty/sources.tsv"), "Live Set.mp3\thttps://example.com/watch?v=live\tfake\t2026-01-01\n").unwrap();
+    lib.run(&["split", "Live Set.mp3"]);
+    assert_eq!(fs::read_to_string(&log_path).unwrap(), concat!(
+        "0.000 225.000 01 - Opening.mp3\n",
+        "225.000 3498.000 02 - Second Song.mp3\n",
+        "3723.000 - 03 - Finale.mp3\n",
+    ));
+    assert_eq!(lib.files(), [
+        "Live Set/01 - Opening.mp3",
+        "Live Set/02 - Second Song.mp3",
+        "Live Set/03 - Finale.mp3",
+        "Live Set/Live Set.description",
+    ]);
+    // the tracks keep the source of the original file
+    let sources = fs::read_to_string(lib.join(".catty/sources.tsv")).unwrap();
+    assert!(sources.contains("Live Set/03 - Finale.mp3\thttps://example.com/watch?v=live\t"), "{}", sources);
+}
+
+#[test]
+fn split_using_embedded_chapters() {
+    let (lib, log_path) = library();
+    lib.add_mp3("Live Set.mp3", &album_tags());
+    fs::write(log_path.with_file_name("ffmetadata.txt"), concat!(
+        ";FFMETADATA1\n",
+        "album=Chaptered\n",
+        "[CHAPTER]\n",
+        "TIMEBASE=1/1000\n",
+        "START=0\n",
+        "END=60000\n",
+        "title=One\n",
+        "[CHAPTER]\n",
+        "TIMEBASE=1/100\n",
+        "START=6000\n",
+        "END=12550\n",
+        "title=Two \\= Too\n",
+    )).unwrap();
+    // a single timestamp in the description isn't enough to override chapters
+    fs::write(lib.join("Live Set.description"), "Out now! 00:00 Intro\n").unwrap();
+    lib.run(&["split", "Live Set.mp3"]);
+    assert_eq!(fs::read_to_string(&log_path).unwrap(), concat!(
+        "0.000 60.000 01 - One.mp3\n",
+        "60.000 65.500 02 - Two = Too.mp3\n",
+    ));
+    assert!(lib.exists("Chaptered/02 - Two = Too.mp3"));
+}
+
+#[test]
+fn split_tracklist_needs_single_file() {
+    let (lib, log_path) = library();
+    lib.add_mp3("Live Set.mp3", &album_tags());
+    lib.add_mp3("Other Set.mp3", &album_tags());
+    fs::write(lib.join("tracks.txt"), "00:00 One\n01:00 Two\n").unwrap();
+    let output = lib.try_run(&["split", "--tracklist", "tracks.txt", "Live Set.mp3", "Other Set.mp3"]);
+    assert!(!output.success);
+    assert!(output.stderr.contains("2 files were selected"), "{}", output.stderr);
+    assert!(!log_path.exists());
+    lib.run(&["split", "--tracklist", "tracks.txt", "Live Set.mp3"]);
+    assert_eq!(fs::read_to_string(&log_path).unwrap(), concat!(
+        "0.000 60.000 01 - One.mp3\n",
+        "60.000 - 02 - Two.mp3\n",
+    ));
+}
\ No newline at end of file
@@ -0,0 +1,62 @@
+mod harness;
+
+use harness::{Library, Tags};
+
+#[test]
+fn stats_counts_library() {
+    let lib = Library::new();
+    let album = Tags {
+        artist : Some("Catty Band"),
+        album_artist : Some("Catty Band"),
+        album : Some("First"),
+        ..Tags::default()
+    };
+    lib.add_mp3("A-F/Catty Band/First/01 One.mp3", &Tags { title : Some("One"), track : Some(1), ..album.clone() });
+    lib.add_mp3("A-F/Catty Band/First/02 Two.mp3", &Tags { title : Some("Two"), track : Some(2), ..album.clone() });
+    lib.add_mp3("V-Z/Zebra/Zebra - Stripes.mp3", &Tags { artist : Some("Zebra"), ..Tags::default() });
+    lib.add_mp3("inbox/untitled.mp3", &Tags::default());
+    lib.add_mp3("inbox/7th - Heaven.mp3", &Tags::default());
+    let output = lib.run(&["stats", "--json", "--top", "2"]);
+    let stats : serde_json::Value = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(stats["tracks"], 5);
+    assert_eq!(stats["albums"], 1);
+    assert_eq!(stats["artists"], 3);
+    assert_eq!(stats["codecs"]["mp3"], 5);
+    assert_eq!(stats["categories"]["A-F"]["tracks"], 2);
+    assert_eq!(stats["categories"]["A-F"]["albums"], 1);
+    assert_eq!(stats["categories"]["V-Z"]["artists"], 1);
+    assert_eq!(stats["other"], 1);
+    assert_eq!(stats["unknown"], 1);
+    assert_eq!(stats["missing"]["album"], 3);
+    assert_eq!(stats["missing"]["source"], 5);
+    assert_eq!(stats["top_artists"], serde_json::json!([
+        { "artist" : "Catty Band", "tracks" : 2 },
+        { "artist" : "7th", "tracks" : 1 },
+    ]));
+    let output = lib.run(&["stats"]);
+    assert!(output.stdout.starts_with("tracks        5\n"));
+}
+
+#[test]
+fn stats_counts_bitrates_by_codec() {
+    let lib = Library::new();
+    lib.add_mp3("Catty Band - One.mp3", &Tags { artist : Some("Catty Band"), title : Some("One"), ..Tags::default() });
+    lib.add_mp3("Catty Band - Two.mp3", &Tags { artist : Some("Catty Band"), title : Some("Two"), ..Tags::default() });
+    lib.add_flac("Catty Band - Three.flac", 44100, 44100);
+    let output = lib.run(&["stats", "--json"]);
+    let stats : serde_json::Value = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(stats["bitrates"]["mp3"]["128-191"], 2);
+    assert_eq!(stats["bitrates"]["mp3"]["320+"], 0);
+    assert_eq!(stats["bitrates"]["flac"]["lossless"], 1);
+    let output = lib.run(&["stats"]);
+    let lines = output.stdout.lines()
+            .skip_while(|x| !x.starts_with("codec"))
+            .take(3)
+            .map(|x| x.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>();
+    assert_eq!(lines, [
+        "codec tracks <128 128-191 192-255 256-319 320+ lossless unknown",
+        "flac 1 0 0 0 0 0 1 0",
+        "mp3 2 0 2 0 0 0 0 0",
+    ]);
+}
\ No newline at end of file
@@ -0,0 +1,63 @@
+mod harness;
+
+use std::fs;
+
+use harness::{Library, Tags};
+
+fn library() -> Library {
+    let lib = Library::new();
+    let tags = Tags { artist : Some("Catty Band"), title : Some("Song"), ..Tags::default() };
+    lib.add_mp3("inbox/song.mp3", &tags);
+    lib.add_mp3("inbox/deeper/tune.mp3", &Tags { title : Some("Tune"), ..tags.clone() });
+    lib.add_mp3("podcasts/episode.mp3", &Tags { title : Some("Episode"), ..tags.clone() });
+    lib
+}
+
+/// Runs `inspect`, returning the path of each file it printed.
+fn inspected_files(lib : &Library, args : &[&str]) -> Vec<String> {
+    let mut full_args = vec!["inspect"];
+    full_args.extend_from_slice(args);
+    let output = lib.run(&full_args);
+    output.stdout.lines().filter(|x| !x.starts_with(' ')).map(String::from).collect()
+}
+
+#[test]
+fn directories_require_recursive() {
+    let lib = library();
+    assert!(inspected_files(&lib, &[]).is_empty());
+    assert_eq!(inspected_files(&lib, &["--recursive"]), [
+        "inbox/deeper/tune.mp3",
+        "inbox/song.mp3",
+        "podcasts/episode.mp3",
+    ]);
+    assert_eq!(inspected_files(&lib, &["inbox", "--max-depth", "1"]), ["inbox/song.mp3"]);
+}
+
+#[test]
+fn ignore_file_excludes_paths() {
+    let lib = library();
+    fs::write(lib.join(".cattyignore"), "# not music\npodcasts/\n").unwrap();
+    fs::write(lib.join("inbox/.cattyignore"), "*.mp3\n!song.mp3\n").unwrap();
+    assert_eq!(inspected_files(&lib, &["-r"]), ["inbox/song.mp3"]);
+    // ignored files are also skipped when matched by a GLOB pattern
+    assert!(inspected_files(&lib, &["podcasts/*"]).is_empty());
+    lib.run(&["sort", "-r"]);
+    assert_eq!(lib.files(), [
+        ".cattyignore",
+        "A-F/Catty Band/song.mp3",
+        "inbox/.cattyignore",
+        "inbox/deeper/tune.mp3",
+        "podcasts/episode.mp3",
+    ]);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_loops_are_visited_once() {
+    let lib = library();
+    std::os::unix::fs::symlink(lib.join("inbox"), lib.join("inbox/deeper/loop")).unwrap();
+    assert_eq!(inspected_files(&lib, &["inbox", "-r"]), [
+        "inbox/deeper/tune.mp3",
+        "inbox/song.mp3",
+    ]);
+}
\ No newline at end of file
@@ -0,0 +1,105 @@
+mod harness;
+
+use std::fs;
+use std::process;
+use std::thread;
+use std::time;
+
+use harness::{Library, Tags};
+use id3::TagLike;
+
+fn read_tag(lib : &Library, rel_path : &str) -> id3::Tag {
+    id3::Tag::read_from_path(lib.join(rel_path)).unwrap()
+}
+
+#[test]
+fn watch_once_tags_renames_and_sorts() {
+    let lib = Library::new();
+    lib.add_mp3("inbox/Catty Band - Song One.mp3", &Tags::default());
+    lib.add_mp3("inbox/Zebra Crossing - Night Walk.mp3.part", &Tags::default());
+    lib.add_mp3("inbox/Night Market/Zebra Crossing - 02 - Neon.mp3", &Tags {
+        artist : Some("Zebra Crossing"),
+        album_artist : Some("Zebra Crossing"),
+        album : Some("Night Market"),
+        title : Some("Neon"),
+        track : Some(2),
+    });
+    fs::write(lib.join("inbox/Night Market/cover.jpg"), b"jpeg").unwrap();
+    lib.write_config("[art]\non-sort = false\n");
+    lib.run(&["watch", "inbox", "--once"]);
+    assert_eq!(lib.files(), [
+        "A-F/Catty Band/Catty Band - Song One.mp3",
+        "V-Z/Zebra Crossing/Night Market/Zebra Crossing - Neon.mp3",
+        "V-Z/Zebra Crossing/Night Market/cover.jpg",
+        "inbox/Zebra Crossing - Night Walk.mp3.part",
+    ]);
+    // missing tags were filled in from the file name
+    let tag = read_tag(&lib, "A-F/Catty Band/Catty Band - Song One.mp3");
+    assert_eq!(tag.artist(), Some("Catty Band"));
+    assert_eq!(tag.title(), Some("Song One"));
+    assert!(!lib.exists("inbox/Night Market"));
+}
+
+#[test]
+fn watch_sorts_albums_like_sort() {
+    let lib = Library::new();
+    lib.write_config("[watch]\nrename = false\n[art]\non-sort = false\n");
+    let album = Tags { album : Some("Split EP"), ..Tags::default() };
+    // only one track names the album artist, but the whole album follows it
+    lib.add_mp3("inbox/Downloads/Split EP/01.mp3", &Tags {
+        artist : Some("Catty Band"), album_artist : Some("Catty Band"), title : Some("One"), ..album.clone()
+    });
+    lib.add_mp3("inbox/Downloads/Split EP/02.mp3", &Tags { artist : Some("Guest Band"), title : Some("Two"), ..album });
+    lib.run(&["watch", "inbox", "--once"]);
+    assert_eq!(lib.files(), [
+        "A-F/Catty Band/Split EP/01.mp3",
+        "A-F/Catty Band/Split EP/02.mp3",
+    ]);
+    assert!(!lib.exists("inbox/Downloads"));
+}
+
+#[test]
+fn watch_can_be_undone() {
+    let lib = Library::new();
+    lib.add_mp3("inbox/Catty Band - Song One.mp3", &Tags::default());
+    lib.run(&["watch", "inbox", "--once"]);
+    assert!(lib.exists("A-F/Catty Band/Catty Band - Song One.mp3"));
+    let output = lib.run(&["undo"]);
+    assert!(output.stdout.contains("inbox/Catty Band - Song One.mp3 artist: Catty Band => -"), "{}", output.stdout);
+    assert_eq!(lib.files(), ["inbox/Catty Band - Song One.mp3"]);
+    let tag = read_tag(&lib, "inbox/Catty Band - Song One.mp3");
+    assert_eq!(tag.artist(), None);
+    assert_eq!(tag.title(), None);
+}
+
+#[test]
+fn watch_processes_finished_downloads() {
+    let lib = Library::new();
+    lib.write_config("[watch]\nsettle = 0.2\nwrite-tags = false\n");
+    fs::create_dir(lib.join("inbox")).unwrap();
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_catty"))
+            .current_dir(lib.path())
+            .args(["watch", "inbox"])
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .spawn()
+            .expect("cannot run catty");
+    thread::sleep(time::Duration::from_millis(500));
+    // downloads are written under a temporary name, then moved into place
+    let part = lib.add_mp3("inbox/Single/Catty Band - Song Two.mp3.part", &Tags::default());
+    thread::sleep(time::Duration::from_millis(500));
+    assert!(part.exists());
+    fs::rename(&part, lib.join("inbox/Single/Catty Band - Song Two.mp3")).unwrap();
+    let sorted = lib.join("A-F/Catty Band/Catty Band - Song Two.mp3");
+    for _ in 0..100 {
+        if sorted.exists() {
+            break;
+        }
+        thread::sleep(time::Duration::from_millis(100));
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(sorted.exists(), "files: {:?}", lib.files());
+    assert!(!lib.exists("inbox/Single"));
+}
\ No newline at end of file